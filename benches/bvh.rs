@@ -0,0 +1,46 @@
+//! Times nearest hit queries over 1000 spheres with and without the BVH.
+//! Run with `cargo bench --bench bvh`
+
+use std::{hint::black_box, time::Instant};
+
+use cgraphics::{intersect::Intersect, renderer::Ray, scene::Scene};
+use nalgebra::Vector3;
+
+const RAYS: usize = 20_000;
+
+fn main() {
+    let scene = Scene::random_spheres(1000, 3);
+    // a fan of rays from outside the cube of spheres, so most of them hit something
+    let rays: Vec<Ray> = (0..RAYS)
+        .map(|i| {
+            let angle = i as f32 / RAYS as f32 * std::f32::consts::TAU;
+            let target = Vector3::new(0.0, 10.0 * angle.cos(), 10.0 * angle.sin());
+            let origin = Vector3::new(-40.0, 0.0, 0.0);
+            Ray::new(origin, target - origin)
+        })
+        .collect();
+
+    // the tree is built on the first query, which shouldn't count
+    scene.nearest_intersection(&rays[0]);
+    let start = Instant::now();
+    for ray in &rays {
+        black_box(scene.nearest_intersection(ray));
+    }
+    let bvh = start.elapsed();
+
+    let start = Instant::now();
+    for ray in &rays {
+        let nearest = scene
+            .objects()
+            .iter()
+            .map(|object| object.shape.intersect_within(ray, 0.0, f32::INFINITY))
+            .min();
+        black_box(nearest);
+    }
+    let linear = start.elapsed();
+
+    println!("{} rays against {} spheres", RAYS, scene.objects().len());
+    println!("bvh:    {:?}", bvh);
+    println!("linear: {:?}", linear);
+    println!("speedup {:.1}x", linear.as_secs_f64() / bvh.as_secs_f64());
+}
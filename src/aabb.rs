@@ -0,0 +1,140 @@
+use nalgebra::Vector3;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    colour::Colour,
+    intersect::{Intersect, Intersection},
+    material::Material,
+    renderer::Ray,
+};
+
+/// Axis aligned bounding box
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Aabb {
+    pub min: Vector3<f32>,
+    pub max: Vector3<f32>,
+}
+
+impl Aabb {
+    pub fn new(min: Vector3<f32>, max: Vector3<f32>) -> Aabb {
+        Aabb { min, max }
+    }
+
+    /// Smallest box containing both boxes
+    pub fn surrounding(a: &Aabb, b: &Aabb) -> Aabb {
+        Aabb {
+            min: a.min.inf(&b.min),
+            max: a.max.sup(&b.max),
+        }
+    }
+
+    pub fn centroid(&self) -> Vector3<f32> {
+        (self.min + self.max) * 0.5
+    }
+
+    /// Slab test, giving the distances the ray enters and leaves the box
+    /// clipped to `t_min..=t_max`. A ray starting inside enters at `t_min`
+    pub fn hit(&self, ray: &Ray, t_min: f32, t_max: f32) -> Option<(f32, f32)> {
+        let mut t_near = t_min;
+        let mut t_far = t_max;
+        for axis in 0..3 {
+            let inverse_direction = 1.0 / ray.direction[axis];
+            let mut t_0 = (self.min[axis] - ray.origin[axis]) * inverse_direction;
+            let mut t_1 = (self.max[axis] - ray.origin[axis]) * inverse_direction;
+            if inverse_direction < 0.0 {
+                std::mem::swap(&mut t_0, &mut t_1);
+            }
+            t_near = t_near.max(t_0);
+            t_far = t_far.min(t_1);
+            if t_far < t_near {
+                return None;
+            }
+        }
+        Some((t_near, t_far))
+    }
+}
+
+/// A solid box that can be put in a scene
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Cuboid {
+    pub bounds: Aabb,
+    pub material: Material,
+}
+
+impl Intersect for Cuboid {
+    fn test_intersection(&self, ray: &Ray) -> Intersection {
+        self.intersect_within(ray, 0.0, f32::INFINITY)
+    }
+
+    fn intersect_within(&self, ray: &Ray, t_min: f32, t_max: f32) -> Intersection {
+        let miss = Intersection::new(Colour::from_gray(0.0), None, None);
+
+        let Some((t_enter, t_exit)) = self.bounds.hit(ray, f32::NEG_INFINITY, f32::INFINITY) else {
+            return miss;
+        };
+        // rays starting inside the box hit the face they leave through
+        let Some(t) = [t_enter, t_exit]
+            .into_iter()
+            .find(|&t| t > t_min && t < t_max)
+        else {
+            return miss;
+        };
+
+        // the face hit is the one the point sits furthest out towards, relative to the box's size
+        let surface = ray.at_point(t);
+        let half_extent = (self.bounds.max - self.bounds.min) * 0.5;
+        let offset = (surface - self.bounds.centroid()).component_div(&half_extent);
+        let axis = offset.iamax();
+        let mut normal = Vector3::zeros();
+        normal[axis] = offset[axis].signum();
+
+        let normal_ray = Ray::new(surface, normal);
+        Intersection::new(
+            self.material.colour_at(&normal_ray.origin, None),
+            Some(t),
+            Some(normal_ray),
+        )
+        .with_material(self.material.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn unit_box() -> Aabb {
+        Aabb::new(Vector3::repeat(-1.0), Vector3::repeat(1.0))
+    }
+
+    fn along_x(origin: Vector3<f32>) -> Ray {
+        Ray::new(origin, Vector3::x())
+    }
+
+    #[test]
+    fn ray_passing_by_misses() {
+        let ray = along_x(Vector3::new(-5.0, 2.0, 0.0));
+        assert_eq!(unit_box().hit(&ray, 0.0, f32::INFINITY), None);
+    }
+
+    #[test]
+    fn ray_grazing_a_face_hits_along_it() {
+        let ray = along_x(Vector3::new(-5.0, 1.0, 0.0));
+        assert_eq!(unit_box().hit(&ray, 0.0, f32::INFINITY), Some((4.0, 6.0)));
+    }
+
+    #[test]
+    fn ray_starting_inside_enters_at_t_min() {
+        let ray = along_x(Vector3::new(0.5, 0.0, 0.0));
+        assert_eq!(unit_box().hit(&ray, 0.0, f32::INFINITY), Some((0.0, 0.5)));
+    }
+
+    #[test]
+    fn surrounding_box_holds_both() {
+        let moved = Aabb::new(Vector3::new(0.0, 0.0, 2.0), Vector3::new(3.0, 1.0, 4.0));
+        let both = Aabb::surrounding(&unit_box(), &moved);
+        assert_eq!(both.min, Vector3::new(-1.0, -1.0, -1.0));
+        assert_eq!(both.max, Vector3::new(3.0, 1.0, 4.0));
+    }
+}
@@ -0,0 +1,123 @@
+use crate::colour::Colour;
+
+// reprojected history counts as this many frames at most, so it still gives way to
+// fresh samples of the new view fairly quickly
+const HISTORY_WEIGHT: u32 = 8;
+
+/// Running sum of every frame rendered since the view last changed, so noisy
+/// renders converge the longer the camera sits still. The frames are linear, as they
+/// come out of the renderer, so averaging them mixes light the way antialiasing should
+pub struct Accumulator {
+    sum: Vec<Vec<Colour>>,
+    weights: Vec<Vec<u32>>, // how many frames each pixel's sum holds
+    frames: u32,
+    history: u32, // frames the reprojected history counts as, 0 without any
+}
+
+impl Accumulator {
+    pub fn new(width: u32, height: u32) -> Accumulator {
+        Accumulator {
+            sum: vec![vec![Colour::default(); width as usize]; height as usize],
+            weights: vec![vec![0; width as usize]; height as usize],
+            frames: 0,
+            history: 0,
+        }
+    }
+
+    /// Throws away every sample, resizing to the given dimensions. The old sums are
+    /// zeroed in place when the size hasn't changed
+    pub fn reset(&mut self, width: u32, height: u32) {
+        self.frames = 0;
+        self.history = 0;
+        if self.sum.len() == height as usize
+            && self.sum.first().map_or(0, |row| row.len()) == width as usize
+        {
+            for row in &mut self.sum {
+                row.fill(Colour::default());
+            }
+            for row in &mut self.weights {
+                row.fill(0);
+            }
+            return;
+        }
+        *self = Accumulator::new(width, height);
+    }
+
+    /// Starts over from colours carried across from the previous view, which count as
+    /// the frames behind them up to `HISTORY_WEIGHT`. Pixels with no history start empty.
+    /// The frame count restarts, since the new view has had no samples of its own
+    pub fn seed_history(&mut self, history: &[Vec<Option<Colour>>]) {
+        self.history = (self.history + self.frames).min(HISTORY_WEIGHT);
+        self.frames = 0;
+        let weight = self.history;
+        for ((sum_row, weight_row), history_row) in
+            self.sum.iter_mut().zip(&mut self.weights).zip(history)
+        {
+            for ((sum, pixel_weight), colour) in sum_row
+                .iter_mut()
+                .zip(weight_row.iter_mut())
+                .zip(history_row)
+            {
+                (*sum, *pixel_weight) = match colour {
+                    Some(colour) => (*colour * weight as f32, weight),
+                    None => (Colour::default(), 0),
+                };
+            }
+        }
+    }
+
+    /// Whether anything has been rendered or carried over since the last reset
+    pub fn has_samples(&self) -> bool {
+        self.frames + self.history > 0
+    }
+
+    pub fn frames(&self) -> u32 {
+        self.frames
+    }
+
+    pub fn add(&mut self, frame: &[Vec<Colour>]) {
+        for ((sum_row, weight_row), frame_row) in
+            self.sum.iter_mut().zip(&mut self.weights).zip(frame)
+        {
+            for ((sum, weight), sample) in
+                sum_row.iter_mut().zip(weight_row.iter_mut()).zip(frame_row)
+            {
+                *sum = *sum + *sample;
+                *weight += 1;
+            }
+        }
+        self.frames += 1;
+    }
+
+    /// Mean of the frames added so far, written over `out`. Pixels holding nothing yet
+    /// are left as they were
+    pub fn average_into(&self, out: &mut [Vec<Colour>]) {
+        for ((out_row, sum_row), weight_row) in out.iter_mut().zip(&self.sum).zip(&self.weights) {
+            for ((pixel, sum), weight) in out_row.iter_mut().zip(sum_row).zip(weight_row) {
+                if *weight > 0 {
+                    *pixel = *sum * (1.0 / *weight as f32);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::output::Encoding;
+
+    #[test]
+    fn black_and_white_average_to_half_linear() {
+        let mut accumulator = Accumulator::new(1, 1);
+        accumulator.add(&[vec![Colour::from_gray(0.0)]]);
+        accumulator.add(&[vec![Colour::from_gray(1.0)]]);
+        let mut out = vec![vec![Colour::default()]];
+        accumulator.average_into(&mut out);
+
+        assert_eq!(out[0][0], Colour::from_gray(0.5));
+        // half the light encodes to sRGB ~186, not the 128 averaging bytes would give
+        let [r, ..] = Encoding::default().encode(out[0][0]);
+        assert_eq!(r, 186);
+    }
+}
@@ -0,0 +1,236 @@
+use std::f32::consts::PI;
+
+use nalgebra::Vector3;
+use serde::{Deserialize, Deserializer, Serialize};
+
+use crate::{colour::Colour, light::Light, sphere::Sphere, texture::ImageTexture};
+
+// the sky model gives luminance in thousands of candela per square metre, this brings a
+// clear midday zenith down to a few tenths
+const SKY_EXPOSURE: f32 = 0.05;
+const SUN_ANGULAR_RADIUS: f32 = 0.5 * PI / 180.0; // about twice the real sun, to show up at low resolutions
+const SUN_BRIGHTNESS: f32 = 20.0;
+const MIN_COS_ZENITH: f32 = 0.01; // the model blows up at the horizon, rays below it see the horizon
+
+/// What rays that miss every object see
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Background {
+    Solid {
+        colour: Colour,
+    },
+    /// Blends from `horizon` for level rays to `zenith` for rays pointing straight up.
+    /// Rays pointing below the horizon see the horizon colour
+    Gradient {
+        horizon: Colour,
+        zenith: Colour,
+    },
+    /// An equirectangular image wrapped around the scene at infinity, the top row
+    /// straight up (+z) and the middle of the image along -x
+    Image {
+        image: ImageTexture,
+    },
+    /// Clear daytime sky from the Preetham model, bluest overhead and hazier towards
+    /// the horizon, with a sun disk. `sun_dir` points at the sun and if left out follows
+    /// the scene's first directional light. Turbidity is how hazy the air is, 2 for
+    /// very clear up to about 10 for thick haze
+    Sky {
+        #[serde(default, skip_serializing_if = "Option::is_none")]
+        sun_dir: Option<Vector3<f32>>,
+        #[serde(default = "default_turbidity")]
+        turbidity: f32,
+    },
+}
+
+fn default_turbidity() -> f32 {
+    3.0
+}
+
+impl Background {
+    pub fn colour(&self, direction: &Vector3<f32>) -> Colour {
+        match self {
+            Background::Solid { colour } => *colour,
+            Background::Gradient { horizon, zenith } => {
+                let t = direction.normalize().z.max(0.0);
+                *horizon * (1.0 - t) + *zenith * t
+            }
+            Background::Image { image } => image.sample(Sphere::uv(&direction.normalize())),
+            Background::Sky { sun_dir, turbidity } => {
+                // a sun nobody placed hangs halfway up the sky
+                let sun = sun_dir.unwrap_or(Vector3::new(1.0, 0.0, 1.0));
+                sky_colour(&direction.normalize(), &sun.normalize(), *turbidity)
+            }
+        }
+    }
+
+    /// Points a sky with no sun direction of its own at the first directional light
+    pub fn follow_sun(&mut self, lights: &[Light]) {
+        let Background::Sky { sun_dir, .. } = self else {
+            return;
+        };
+        if sun_dir.is_some() {
+            return;
+        }
+        *sun_dir = lights.iter().find_map(|light| match light {
+            Light::Directional { direction, .. } => Some(-direction),
+            _ => None,
+        });
+    }
+}
+
+/// Perez et al.'s sky luminance distribution, relative to the zenith, for a view
+/// `theta` from straight up and `gamma` from the sun
+fn perez(coefficients: &[f32; 5], cos_theta: f32, gamma: f32) -> f32 {
+    let [a, b, c, d, e] = *coefficients;
+    (1.0 + a * (b / cos_theta).exp()) * (1.0 + c * (d * gamma).exp() + e * gamma.cos().powi(2))
+}
+
+/// The Preetham, Shirley and Smits (1999) analytic daylight model: luminance and
+/// chromaticity at the zenith fitted against turbidity and sun height, spread over the
+/// sky with `perez`, then converted from CIE xyY to linear sRGB
+fn sky_colour(direction: &Vector3<f32>, sun: &Vector3<f32>, turbidity: f32) -> Colour {
+    let t = turbidity;
+    let cos_theta = direction.z.max(MIN_COS_ZENITH);
+    let theta_sun = sun.z.clamp(MIN_COS_ZENITH, 1.0).acos();
+    let gamma = direction.angle(sun);
+
+    let luminance = [
+        0.1787 * t - 1.4630,
+        -0.3554 * t + 0.4275,
+        -0.0227 * t + 5.3251,
+        0.1206 * t - 2.5771,
+        -0.0670 * t + 0.3703,
+    ];
+    let x_coefficients = [
+        -0.0193 * t - 0.2592,
+        -0.0665 * t + 0.0008,
+        -0.0004 * t + 0.2125,
+        -0.0641 * t - 0.8989,
+        -0.0033 * t + 0.0452,
+    ];
+    let y_coefficients = [
+        -0.0167 * t - 0.2608,
+        -0.0950 * t + 0.0092,
+        -0.0079 * t + 0.2102,
+        -0.0441 * t - 1.6537,
+        -0.0109 * t + 0.0529,
+    ];
+
+    let chi = (4.0 / 9.0 - t / 120.0) * (PI - 2.0 * theta_sun);
+    let zenith_luminance = (4.0453 * t - 4.9710) * chi.tan() - 0.2155 * t + 2.4192;
+    let powers = [theta_sun.powi(3), theta_sun.powi(2), theta_sun, 1.0];
+    let fit = |rows: [[f32; 4]; 3]| {
+        let row = |coefficients: [f32; 4]| -> f32 {
+            coefficients.iter().zip(powers).map(|(c, p)| c * p).sum()
+        };
+        t * t * row(rows[0]) + t * row(rows[1]) + row(rows[2])
+    };
+    let zenith_x = fit([
+        [0.00166, -0.00375, 0.00209, 0.0],
+        [-0.02903, 0.06377, -0.03202, 0.00394],
+        [0.11693, -0.21196, 0.06052, 0.25886],
+    ]);
+    let zenith_y = fit([
+        [0.00275, -0.00610, 0.00317, 0.0],
+        [-0.04214, 0.08970, -0.04153, 0.00516],
+        [0.15346, -0.26756, 0.06670, 0.26688],
+    ]);
+
+    let relative = |coefficients: &[f32; 5]| {
+        perez(coefficients, cos_theta, gamma) / perez(coefficients, 1.0, theta_sun)
+    };
+    let big_y = zenith_luminance * relative(&luminance) * SKY_EXPOSURE;
+    let x = zenith_x * relative(&x_coefficients);
+    let y = zenith_y * relative(&y_coefficients);
+
+    let big_x = x / y * big_y;
+    let big_z = (1.0 - x - y) / y * big_y;
+    let mut colour = Colour::from_rgb(
+        (3.2406 * big_x - 1.5372 * big_y - 0.4986 * big_z).max(0.0),
+        (-0.9689 * big_x + 1.8758 * big_y + 0.0415 * big_z).max(0.0),
+        (0.0557 * big_x - 0.2040 * big_y + 1.0570 * big_z).max(0.0),
+    );
+    if gamma < SUN_ANGULAR_RADIUS && direction.z > 0.0 {
+        colour = colour + Colour::from_gray(SUN_BRIGHTNESS);
+    }
+    colour
+}
+
+impl Default for Background {
+    fn default() -> Self {
+        Background::Solid {
+            colour: Colour::from_gray(0.0),
+        }
+    }
+}
+
+impl From<Colour> for Background {
+    fn from(colour: Colour) -> Self {
+        Background::Solid { colour }
+    }
+}
+
+/// Reads a background written out in full or, for a plain solid background, as
+/// just its colour
+pub fn colour_or_background<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Background, D::Error> {
+    // picked by shape rather than `untagged`, so a mistake inside a background object
+    // is reported as itself
+    let value = serde_json::Value::deserialize(deserializer)?;
+    let background = if value.is_array() {
+        serde_json::from_value::<Colour>(value).map(Background::from)
+    } else {
+        serde_json::from_value(value)
+    };
+    background.map_err(serde::de::Error::custom)
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Rotation3;
+
+    use super::*;
+
+    #[test]
+    fn gradient_is_the_zenith_straight_up_and_the_horizon_level() {
+        let horizon = Colour::from_rgb(1.0, 1.0, 1.0);
+        let zenith = Colour::from_rgb(0.0, 0.25, 1.0);
+        let sky = Background::Gradient { horizon, zenith };
+        assert_eq!(sky.colour(&Vector3::new(0.0, 0.0, 3.0)), zenith);
+        assert_eq!(sky.colour(&Vector3::new(1.0, 1.0, 0.0)), horizon);
+        assert_eq!(sky.colour(&-Vector3::z()), horizon);
+    }
+
+    #[test]
+    fn sky_is_bluer_overhead_than_at_the_horizon() {
+        let sun = Vector3::new(1.0, 0.0, 0.5).normalize();
+        let sky = Background::Sky {
+            sun_dir: Some(sun),
+            turbidity: default_turbidity(),
+        };
+        let blueness = |direction: Vector3<f32>| {
+            let colour = sky.colour(&direction);
+            colour.b() / (colour.r() + colour.g() + colour.b())
+        };
+        // looking away from the sun, where it doesn't tint the horizon
+        assert!(blueness(Vector3::z()) > blueness(Vector3::new(-1.0, 0.0, 0.05)));
+    }
+
+    #[test]
+    fn sun_disk_only_shows_looking_at_the_sun() {
+        let sun = Vector3::new(1.0, 0.0, 0.5).normalize();
+        let sky = Background::Sky {
+            sun_dir: Some(sun),
+            turbidity: default_turbidity(),
+        };
+        let beside = Rotation3::from_axis_angle(&Vector3::y_axis(), 4.0 * SUN_ANGULAR_RADIUS) * sun;
+        let (at, near) = (sky.colour(&sun), sky.colour(&beside));
+        assert!(
+            at.g() > near.g() + SUN_BRIGHTNESS / 2.0,
+            "{:?} {:?}",
+            at,
+            near
+        );
+    }
+}
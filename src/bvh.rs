@@ -0,0 +1,220 @@
+use std::borrow::Borrow;
+
+use crate::{
+    aabb::Aabb,
+    colour::Colour,
+    intersect::{Intersect, Intersectable, Intersection},
+    renderer::Ray,
+};
+
+const LEAF_SIZE: usize = 4;
+const MAX_STACK: usize = 64;
+
+#[derive(Clone, Copy)]
+enum NodeKind {
+    Leaf { start: usize, count: usize },
+    Branch { left: usize, right: usize },
+}
+
+#[derive(Clone, Copy)]
+struct Node {
+    bounds: Aabb,
+    kind: NodeKind,
+}
+
+/// Bounding volume hierarchy over the indices of a scene's objects. Objects that
+/// can't be boxed (planes) sit outside the tree and are tested against every ray
+#[derive(Clone, Default)]
+pub struct Bvh {
+    nodes: Vec<Node>,
+    indices: Vec<usize>, // object indices, ordered so every leaf owns a contiguous run
+    unbounded: Vec<usize>,
+}
+
+impl Bvh {
+    pub fn build(objects: &[impl Borrow<Intersectable>]) -> Bvh {
+        Bvh::build_where(objects, |_| true)
+    }
+
+    /// Like `build`, leaving out any object `include` says no to so that rays never hit it
+    pub fn build_where(
+        objects: &[impl Borrow<Intersectable>],
+        include: impl Fn(usize) -> bool,
+    ) -> Bvh {
+        let mut bvh = Bvh::default();
+        let mut bounded = vec![];
+        for (i, obj) in objects.iter().enumerate().filter(|(i, _)| include(*i)) {
+            match obj.borrow().bounds() {
+                Some(bounds) => bounded.push((i, bounds)),
+                None => bvh.unbounded.push(i),
+            }
+        }
+        if !bounded.is_empty() {
+            bvh.build_node(&mut bounded);
+        }
+        bvh
+    }
+
+    /// Splits the objects at the median centroid along whichever axis the centroids
+    /// are most spread out on, returning the index of the new node
+    fn build_node(&mut self, items: &mut [(usize, Aabb)]) -> usize {
+        let bounds = items
+            .iter()
+            .fold(items[0].1, |acc, (_, b)| Aabb::surrounding(&acc, b));
+        let node_index = self.nodes.len();
+
+        if items.len() <= LEAF_SIZE {
+            let start = self.indices.len();
+            self.indices.extend(items.iter().map(|(i, _)| *i));
+            self.nodes.push(Node {
+                bounds,
+                kind: NodeKind::Leaf {
+                    start,
+                    count: items.len(),
+                },
+            });
+            return node_index;
+        }
+
+        let first_centroid = items[0].1.centroid();
+        let (low, high) = items
+            .iter()
+            .fold((first_centroid, first_centroid), |(low, high), (_, b)| {
+                (low.inf(&b.centroid()), high.sup(&b.centroid()))
+            });
+        let axis = (high - low).imax();
+        items.sort_by(|a, b| a.1.centroid()[axis].total_cmp(&b.1.centroid()[axis]));
+
+        // placeholder until both children exist
+        self.nodes.push(Node {
+            bounds,
+            kind: NodeKind::Leaf { start: 0, count: 0 },
+        });
+        let (left_items, right_items) = items.split_at_mut(items.len() / 2);
+        let left = self.build_node(left_items);
+        let right = self.build_node(right_items);
+        self.nodes[node_index].kind = NodeKind::Branch { left, right };
+        node_index
+    }
+
+    pub fn nearest(&self, objects: &[impl Borrow<Intersectable>], ray: &Ray) -> Intersection {
+        self.nearest_within(objects, ray, 0.0, f32::INFINITY)
+    }
+
+    /// Nearest hit with a distance strictly between `t_min` and `t_max`
+    pub fn nearest_within(
+        &self,
+        objects: &[impl Borrow<Intersectable>],
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+    ) -> Intersection {
+        let mut nearest = Intersection::new(Colour::from_gray(0.0), None, None);
+        for &i in &self.unbounded {
+            nearest = nearest.min(objects[i].borrow().intersect_within(ray, t_min, t_max));
+        }
+        let t_max = nearest.distance.unwrap_or(t_max);
+        self.visit(ray, t_max, |i, t_max| {
+            // only something nearer than the best so far can change the answer
+            let candidate = objects[i].borrow().intersect_within(ray, t_min, *t_max);
+            if candidate < nearest {
+                nearest = candidate;
+            }
+            *t_max = nearest.distance.unwrap_or(*t_max);
+            false
+        });
+        nearest
+    }
+
+    /// Like `intersect::occluded`, stopping at the first blocker found
+    pub fn occluded(
+        &self,
+        objects: &[impl Borrow<Intersectable>],
+        ray: &Ray,
+        t_min: f32,
+        t_max: f32,
+    ) -> bool {
+        let blocks = |i: usize| {
+            objects[i]
+                .borrow()
+                .intersect_within(ray, t_min, t_max)
+                .distance
+                .is_some()
+        };
+        if self.unbounded.iter().any(|&i| blocks(i)) {
+            return true;
+        }
+        let mut found = false;
+        self.visit(ray, t_max, |i, _| {
+            found = blocks(i);
+            found
+        });
+        found
+    }
+
+    /// Walks every leaf whose box the ray enters before `t_max`, calling `test` on each
+    /// object in it. `test` may shrink `t_max` and returns true to stop the walk early
+    fn visit(&self, ray: &Ray, mut t_max: f32, mut test: impl FnMut(usize, &mut f32) -> bool) {
+        if self.nodes.is_empty() {
+            return;
+        }
+        let mut stack = [0usize; MAX_STACK];
+        let mut stack_len = 1;
+        while stack_len > 0 {
+            stack_len -= 1;
+            let node = &self.nodes[stack[stack_len]];
+            if node.bounds.hit(ray, 0.0, t_max).is_none() {
+                continue;
+            }
+            match node.kind {
+                NodeKind::Leaf { start, count } => {
+                    for &i in &self.indices[start..start + count] {
+                        if test(i, &mut t_max) {
+                            return;
+                        }
+                    }
+                }
+                NodeKind::Branch { left, right } => {
+                    stack[stack_len] = left;
+                    stack[stack_len + 1] = right;
+                    stack_len += 2;
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+    use rand::{Rng, SeedableRng};
+    use rand_chacha::ChaCha8Rng;
+
+    use super::*;
+    use crate::scene::Scene;
+
+    #[test]
+    fn finds_the_same_hits_as_testing_every_object() {
+        let scene = Scene::random_spheres(1000, 3);
+        let objects = scene.objects();
+        let bvh = Bvh::build(objects);
+        let mut rng = ChaCha8Rng::seed_from_u64(11);
+        let mut point = |extent: f32| Vector3::from_fn(|_, _| rng.gen_range(-extent..extent));
+
+        let mut hits = 0;
+        for _ in 0..500 {
+            // from anywhere around the spheres, aimed at a point among them
+            let origin = point(40.0);
+            let ray = Ray::new(origin, point(15.0) - origin);
+            let expected = objects
+                .iter()
+                .map(|object| object.shape.intersect_within(&ray, 0.0, f32::INFINITY))
+                .min()
+                .unwrap();
+            let found = bvh.nearest(objects, &ray);
+            assert_eq!(found.distance, expected.distance, "{:?}", ray);
+            hits += found.distance.is_some() as usize;
+        }
+        assert!(hits > 25, "only {} rays hit anything", hits);
+    }
+}
@@ -1,86 +1,836 @@
-use std::{
-    sync::{Arc, RwLock},
-    thread,
+use std::sync::Mutex;
+
+use nalgebra::Vector3;
+use rayon::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    colour::Colour,
+    math,
+    renderer::{Integrator, Ray, RenderConfig},
+    rng,
+    scene::Scene,
+    tile::{self, Tile},
 };
 
-use eframe::egui::Rgba;
+const MIN_FOV: f32 = 1.0;
+const MAX_FOV: f32 = 179.0;
+const MAX_PITCH: f32 = 89.0 * std::f32::consts::PI / 180.0; // looking straight up or down flips the basis
+
+/// Told the fraction of the image that's done each time a tile finishes, from whichever
+/// thread rendered it. Reaches 1 with the last tile
+pub type Progress<'a> = dyn Fn(f32) + Sync + 'a;
+
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+pub enum ProjectionKind {
+    Perspective,
+    Orthographic { scale: f32 }, // half the height of the view volume in world units
+}
+
+/// Supersampling that starts with `min_samples` per pixel and keeps adding `min_samples`
+/// more, up to `max_samples`, while the variance of the pixel's mean brightness is above
+/// `variance_threshold`. Flat areas stop early and edges and noise get the rest
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AdaptiveSampling {
+    pub min_samples: u32,
+    pub max_samples: u32,
+    pub variance_threshold: f32,
+}
+
+/// The parts of a camera a scene file can set to describe a whole shot. Anything left
+/// out keeps whatever the camera it's applied to already had
+#[derive(Clone, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct CameraSettings {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub origin: Option<Vector3<f32>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub direction: Option<Vector3<f32>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub roll: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub fov_y: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub aperture: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub focus_distance: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub width: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub height: Option<u32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub near: Option<f32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub far: Option<f32>,
+}
+
+impl CameraSettings {
+    /// Everything about `camera` that the settings cover
+    pub fn of(camera: &Camera) -> CameraSettings {
+        CameraSettings {
+            origin: Some(camera.location.origin),
+            direction: Some(camera.location.direction),
+            roll: Some(camera.roll),
+            fov_y: Some(camera.fov_y),
+            aperture: Some(camera.aperture),
+            focus_distance: Some(camera.focus_distance),
+            width: Some(camera.width),
+            height: Some(camera.height),
+            near: Some(camera.near),
+            far: Some(camera.far),
+        }
+    }
 
-use crate::{renderer::Ray, scene::Scene};
+    pub fn apply(&self, camera: &mut Camera) {
+        if let Some(origin) = self.origin {
+            camera.location.origin = origin;
+        }
+        if let Some(direction) = self.direction {
+            camera.look_along(&direction);
+        }
+        if let Some(roll) = self.roll {
+            camera.roll = roll;
+        }
+        if let Some(fov_y) = self.fov_y {
+            camera.set_fov(fov_y);
+        }
+        if let Some(aperture) = self.aperture {
+            camera.aperture = aperture.max(0.0);
+        }
+        if let Some(focus_distance) = self.focus_distance {
+            camera.focus_distance = focus_distance.max(f32::EPSILON);
+        }
+        camera.width = self.width.unwrap_or(camera.width).max(1);
+        camera.height = self.height.unwrap_or(camera.height).max(1);
+        camera.set_clip(
+            self.near.unwrap_or(camera.near),
+            self.far.unwrap_or(camera.far),
+        );
+    }
+}
 
+#[derive(Clone, Serialize, Deserialize)]
 pub struct Camera {
     pub location: Ray,
     pub width: u32,
     pub height: u32,
+    pub fov_y: f32, // vertical field of view in degrees
+    pub projection: ProjectionKind,
+    pub samples_per_pixel: u32, // render time grows roughly linearly with this
+    pub yaw: f32,               // radians anticlockwise from +x, seen from above
+    pub pitch: f32,             // radians above the horizon
+    #[serde(default)]
+    pub roll: f32, // radians turned about the view direction, tipping right towards up
+    #[serde(default)]
+    pub aperture: f32, // lens diameter, 0 for a pinhole camera with everything in focus
+    #[serde(default = "default_focus_distance")]
+    pub focus_distance: f32, // how far along each ray things are sharpest
+    #[serde(default)]
+    pub adaptive: Option<AdaptiveSampling>, // used instead of `samples_per_pixel` when set
+    #[serde(default = "default_near")]
+    pub near: f32, // primary rays only see what lies between these distances
+    #[serde(default = "default_far")]
+    pub far: f32,
+}
+
+fn default_focus_distance() -> f32 {
+    10.0
+}
+
+fn default_near() -> f32 {
+    1e-4
+}
+
+fn default_far() -> f32 {
+    1e6
 }
 
 impl Camera {
-    pub fn create_buffer(&self, scene: &Scene) -> Vec<Vec<Rgba>> {
-        let mut buffer: Vec<Vec<Rgba>> =
-            vec![vec![Rgba::from_gray(0.0); self.height as usize]; self.width as usize];
-        for x in 0..self.width {
-            for y in 0..self.height {
-                let x_normalised = ((-2.0 * x as f32) / self.width as f32) + 1.0;
-                let y_normalised = ((2.0 * y as f32) / self.height as f32) - 1.0;
-
-                let pixel_direction = nalgebra::Vector3::new(0.0, y_normalised, x_normalised);
-
-                let pixel_ray = Ray::new(
-                    self.location.origin,
-                    pixel_direction + self.location.direction,
-                );
-                let color = scene.test_intersections(pixel_ray, 0).colour;
-                buffer[x as usize][y as usize] = color;
-            }
+    pub fn new(location: Ray, width: u32, height: u32) -> Camera {
+        let direction = location.direction;
+        let mut camera = Camera {
+            location,
+            width,
+            height,
+            fov_y: 90.0,
+            projection: ProjectionKind::Perspective,
+            samples_per_pixel: 1,
+            yaw: 0.0,
+            pitch: 0.0,
+            roll: 0.0,
+            aperture: 0.0,
+            focus_distance: default_focus_distance(),
+            adaptive: None,
+            near: default_near(),
+            far: default_far(),
+        };
+        camera.rotate(0.0, 0.0); // so a zero length direction still leaves it facing +x
+        camera.look_along(&direction);
+        camera
+    }
+
+    /// Points the camera along `direction`, taking the yaw and pitch from it.
+    /// A zero length direction has no angles, so the camera is left as it was
+    pub fn look_along(&mut self, direction: &Vector3<f32>) {
+        let Some(direction) = direction.try_normalize(f32::EPSILON) else {
+            return;
+        };
+        self.yaw = direction.y.atan2(direction.x);
+        self.pitch = direction.z.clamp(-1.0, 1.0).asin();
+        self.rotate(0.0, 0.0);
+    }
+
+    /// The view both the app and headless renders start from, ten units back
+    /// from the origin looking along +x
+    pub fn default_view(width: u32, height: u32) -> Camera {
+        let origin_ray = Ray::new_preserve(Vector3::new(-10.0, 0.0, 0.0), Vector3::x());
+        Camera::new(origin_ray, width, height).with_fov(90.0)
+    }
+
+    /// Turns the camera by the given yaw and pitch in radians, keeping the pitch
+    /// short of vertical, and rebuilds `location.direction` from the new angles
+    pub fn rotate(&mut self, yaw_delta: f32, pitch_delta: f32) {
+        self.yaw += yaw_delta;
+        self.pitch = (self.pitch + pitch_delta).clamp(-MAX_PITCH, MAX_PITCH);
+        self.location.direction = Vector3::new(
+            self.pitch.cos() * self.yaw.cos(),
+            self.pitch.cos() * self.yaw.sin(),
+            self.pitch.sin(),
+        );
+    }
+
+    /// Turns the camera about its view direction. Unlike pitch there's no limit
+    pub fn roll(&mut self, delta: f32) {
+        self.roll += delta;
+    }
+
+    /// Moves the camera along its own forward and right vectors, `up` staying world up
+    pub fn translate(&mut self, forward: f32, right: f32, up: f32) {
+        let (forward_vec, right_vec, _) = self.basis();
+        self.location.origin += forward_vec * forward + right_vec * right + Vector3::z() * up;
+    }
+
+    /// Backs the camera away along its current direction until the sphere around the
+    /// scene's bounds fits in the narrower of the two fields of view. Leaves the camera
+    /// where it is if nothing in the scene has bounds
+    pub fn frame(&mut self, scene: &Scene) {
+        let Some(bounds) = scene.bounds() else {
+            return;
+        };
+        let centre = bounds.centroid();
+        let radius = ((bounds.max - bounds.min).norm() / 2.0).max(f32::EPSILON);
+
+        let half_height = self.fov_y.to_radians() / 2.0;
+        let aspect = self.width as f32 / self.height as f32;
+        let half_width = (half_height.tan() * aspect).atan();
+        let distance = radius / half_height.min(half_width).sin();
+
+        let (forward, _, _) = self.basis();
+        self.location.origin = centre - forward * distance;
+        if let ProjectionKind::Orthographic { .. } = self.projection {
+            self.projection = ProjectionKind::Orthographic {
+                scale: radius / aspect.min(1.0),
+            };
         }
-        buffer
     }
 
-    #[allow(dead_code)]
-    pub fn create_buffer_parallel(&self, scene: Scene) -> Vec<Vec<Rgba>> {
-        let mut buffer: Vec<Vec<Rgba>> =
-            vec![vec![Rgba::from_gray(0.0); self.height as usize]; self.width as usize];
-        let scene_pointer = Arc::new(RwLock::new(scene));
-        let mut jobs = vec![];
-        for x in 0..self.width {
-            for y in 0..self.height {
-                let dim_small = self.width.min(self.height) as f32;
-                let x_normalised = ((-2.0 * x as f32) / dim_small) + 1.0;
-                let y_normalised = ((2.0 * y as f32) / dim_small) - 1.0;
-
-                let pixel_direction = nalgebra::Vector3::new(0.0, y_normalised, x_normalised);
-
-                let pixel_ray = Ray::new(
-                    self.location.origin,
-                    pixel_direction + self.location.direction,
-                );
-
-                let thread_scene_pointer = Arc::clone(&scene_pointer);
-
-                let job = move || {
-                    return thread_scene_pointer
-                        .read()
-                        .unwrap()
-                        .test_intersections(pixel_ray, 0);
-                };
-                jobs.push((x as usize, y as usize, job));
+    pub fn with_samples(mut self, samples_per_pixel: u32) -> Camera {
+        self.samples_per_pixel = samples_per_pixel.max(1);
+        self
+    }
+
+    pub fn with_adaptive_sampling(mut self, adaptive: Option<AdaptiveSampling>) -> Camera {
+        self.adaptive = adaptive;
+        self
+    }
+
+    pub fn with_projection(mut self, projection: ProjectionKind) -> Camera {
+        self.projection = projection;
+        self
+    }
+
+    /// Turns on depth of field, blurring anything not `focus_distance` away
+    pub fn with_aperture(mut self, aperture: f32, focus_distance: f32) -> Camera {
+        self.aperture = aperture.max(0.0);
+        self.focus_distance = focus_distance.max(f32::EPSILON);
+        self
+    }
+
+    pub fn with_clip(mut self, near: f32, far: f32) -> Camera {
+        self.set_clip(near, far);
+        self
+    }
+
+    /// Ignores anything nearer than `near` or further than `far` from the camera, which
+    /// can't be closer together than `near` is from 0
+    pub fn set_clip(&mut self, near: f32, far: f32) {
+        self.near = near.max(0.0);
+        self.far = far.max(self.near + f32::EPSILON);
+    }
+
+    /// `config` with the primary ray range narrowed to the clip planes
+    fn clipped(&self, config: &RenderConfig) -> RenderConfig {
+        RenderConfig {
+            near: self.near,
+            far: self.far,
+            ..*config
+        }
+    }
+
+    pub fn with_fov(mut self, degrees: f32) -> Camera {
+        self.set_fov(degrees);
+        self
+    }
+
+    pub fn set_fov(&mut self, degrees: f32) {
+        self.fov_y = degrees.clamp(MIN_FOV, MAX_FOV);
+    }
+
+    /// Maps a position on the pixel grid onto the image plane, y spanning -1 (bottom) to 1 (top)
+    /// and x scaled by the aspect ratio so the plane is never stretched.
+    /// Pixel (x, y) covers x..x+1 and y..y+1, so its centre is at (x + 0.5, y + 0.5)
+    pub fn screen_coordinates(&self, x: f32, y: f32) -> (f32, f32) {
+        let aspect = self.width as f32 / self.height as f32;
+
+        let x_screen = ((2.0 * x) / self.width as f32 - 1.0) * aspect;
+        let y_screen = 1.0 - (2.0 * y) / self.height as f32;
+        (x_screen, y_screen)
+    }
+
+    /// Forward, right and up vectors of the camera. With no roll, up leans towards
+    /// world up (z); roll then turns right and up together about forward
+    pub fn basis(&self) -> (Vector3<f32>, Vector3<f32>, Vector3<f32>) {
+        let forward = self.location.direction.normalize();
+        let level_right = Vector3::z().cross(&forward).normalize();
+        let level_up = forward.cross(&level_right);
+        let (sin, cos) = self.roll.sin_cos();
+        let right = level_right * cos + level_up * sin;
+        let up = level_up * cos - level_right * sin;
+        (forward, right, up)
+    }
+
+    /// The ray through (x, y) on the pixel grid ignoring the lens, so everything is in focus
+    pub fn pinhole_ray(&self, x: f32, y: f32) -> Ray {
+        let (forward, right, up) = self.basis();
+        let (x_screen, y_screen) = self.screen_coordinates(x, y);
+        let offset = right * x_screen + up * y_screen;
+
+        match self.projection {
+            ProjectionKind::Perspective => {
+                let half_height = (self.fov_y.to_radians() / 2.0).tan();
+                Ray::new(self.location.origin, forward + offset * half_height)
+            }
+            ProjectionKind::Orthographic { scale } => {
+                Ray::new(self.location.origin + offset * scale, forward)
             }
         }
+    }
+
+    pub fn primary_ray(&self, x: f32, y: f32) -> Ray {
+        let pinhole = self.pinhole_ray(x, y);
+        if self.aperture <= 0.0 {
+            return pinhole;
+        }
+        let (_, right, up) = self.basis();
+
+        // thin lens: start somewhere on the lens and aim at the point the pinhole ray
+        // would have reached at the focus distance, which every lens position agrees on
+        let focus_point = pinhole.at_point(self.focus_distance);
+        let (lens_x, lens_y) = math::random_in_disk(rng::random);
+        let lens_origin = pinhole.origin + (right * lens_x + up * lens_y) * (self.aperture / 2.0);
+        Ray::new(lens_origin, focus_point - lens_origin)
+    }
+
+    /// Inverse of `primary_ray` for a pinhole camera: where on the pixel grid `point`
+    /// appears, or `None` if it's behind the camera
+    pub fn project(&self, point: &Vector3<f32>) -> Option<(f32, f32)> {
+        let (forward, right, up) = self.basis();
+        let offset = point - self.location.origin;
+        let (x_screen, y_screen) = match self.projection {
+            ProjectionKind::Perspective => {
+                let depth = offset.dot(&forward);
+                if depth <= 0.0 {
+                    return None;
+                }
+                let half_height = (self.fov_y.to_radians() / 2.0).tan();
+                let scale = depth * half_height;
+                (offset.dot(&right) / scale, offset.dot(&up) / scale)
+            }
+            ProjectionKind::Orthographic { scale } => {
+                (offset.dot(&right) / scale, offset.dot(&up) / scale)
+            }
+        };
+        let aspect = self.width as f32 / self.height as f32;
+        Some((
+            (x_screen / aspect + 1.0) * self.width as f32 / 2.0,
+            (1.0 - y_screen) * self.height as f32 / 2.0,
+        ))
+    }
 
-        let mut in_progress = vec![];
+    /// Averages `samples_per_pixel` rays spread over the pixel's footprint.
+    /// The spread is fixed so the same camera always produces the same image,
+    /// and a single sample goes straight through the pixel centre. The average is of
+    /// linear light, long before `output` gamma encodes it, so an edge pixel half covered
+    /// by white comes out at 0.5 rather than the darker middle of the encoded range
+    pub fn pixel_colour(&self, scene: &Scene, x: u32, y: u32, config: &RenderConfig) -> Colour {
+        self.sample_pixel(config.mode.integrator(), scene, x, y, config)
+    }
+
+    fn sample_pixel(
+        &self,
+        integrator: Integrator,
+        scene: &Scene,
+        x: u32,
+        y: u32,
+        config: &RenderConfig,
+    ) -> Colour {
+        let config = &self.clipped(config);
+        rng::seed_pixel(config.seed, x, y, 0);
+        if let Some(adaptive) = &self.adaptive {
+            return self.sample_pixel_adaptive(adaptive, integrator, scene, x, y, config);
+        }
+        let samples = self.samples_per_pixel.max(1);
+        let mut total = Colour::default();
+        for i in 0..samples {
+            let (u, v) = sample_offset(i, samples);
+            let pixel_ray = self
+                .primary_ray(x as f32 + u, y as f32 + v)
+                .with_time(shutter_time(i));
+            total = total + integrator(scene, pixel_ray, config);
+        }
+        total * (1.0 / samples as f32)
+    }
 
-        while jobs.len() > 0 {
-            while in_progress.len() < 8 {
-                let job = jobs.pop().unwrap();
-                in_progress.push(thread::spawn(move || return (job.0, job.1, job.2())));
+    /// Samples follow the same low discrepancy sequence as `render_sample`, since how
+    /// many there will be isn't known up front
+    fn sample_pixel_adaptive(
+        &self,
+        adaptive: &AdaptiveSampling,
+        integrator: Integrator,
+        scene: &Scene,
+        x: u32,
+        y: u32,
+        config: &RenderConfig,
+    ) -> Colour {
+        let batch = adaptive.min_samples.max(1);
+        let max_samples = adaptive.max_samples.max(batch);
+        let mut total = Colour::default();
+        let (mut sum, mut sum_of_squares) = (0.0, 0.0);
+        let mut samples = 0;
+        while samples < max_samples {
+            for _ in 0..batch.min(max_samples - samples) {
+                let (u, v) = progressive_offset(samples);
+                let pixel_ray = self
+                    .primary_ray(x as f32 + u, y as f32 + v)
+                    .with_time(shutter_time(samples));
+                let colour = integrator(scene, pixel_ray, config);
+                total = total + colour;
+                sum += colour.intensity();
+                sum_of_squares += colour.intensity().powi(2);
+                samples += 1;
             }
-            for i in 0..in_progress.len() - 1 {
-                if in_progress[i].is_finished() {
-                    let (x, y, intersection) = in_progress.remove(i).join().unwrap();
-                    buffer[x][y] = intersection.colour;
-                    break;
+            let n = samples as f32;
+            let variance = (sum_of_squares / n - (sum / n).powi(2)).max(0.0);
+            if variance / n <= adaptive.variance_threshold {
+                break;
+            }
+        }
+        total * (1.0 / samples as f32)
+    }
+
+    pub fn create_buffer(&self, scene: &Scene, config: &RenderConfig) -> Vec<Vec<Colour>> {
+        let integrator = config.mode.integrator();
+        let mut buffer: Vec<Vec<Colour>> =
+            vec![vec![Colour::from_gray(0.0); self.width as usize]; self.height as usize];
+        for y in 0..self.height {
+            for x in 0..self.width {
+                if config.in_crop(x, y) {
+                    buffer[y as usize][x as usize] =
+                        self.sample_pixel(integrator, scene, x, y, config);
                 }
             }
         }
         buffer
     }
+
+    /// One ray per pixel, offset within the pixel by the `sample_index`th point of a
+    /// low discrepancy sequence, written over `buffer` in place. Averaging successive
+    /// samples antialiases the image, and sample 0 goes through the pixel centre.
+    /// `buffer` has to already match the camera's dimensions
+    pub fn render_sample(
+        &self,
+        scene: &Scene,
+        config: &RenderConfig,
+        sample_index: u32,
+        buffer: &mut [Vec<Colour>],
+    ) {
+        let config = &self.clipped(config);
+        let integrator = config.mode.integrator();
+        let (u, v) = progressive_offset(sample_index);
+        self.render_tiles(
+            buffer,
+            config,
+            |x, y| {
+                rng::seed_pixel(config.seed, x, y, sample_index);
+                let pixel_ray = self
+                    .primary_ray(x as f32 + u, y as f32 + v)
+                    .with_time(shutter_time(sample_index));
+                integrator(scene, pixel_ray, config)
+            },
+            None,
+        );
+    }
+
+    /// The `sample_index`th sample of just the pixels in `tile`, row by row, offset the
+    /// same way as `render_sample`
+    pub fn render_tile(
+        &self,
+        scene: &Scene,
+        config: &RenderConfig,
+        sample_index: u32,
+        tile: &Tile,
+    ) -> Vec<Colour> {
+        let config = &self.clipped(config);
+        let integrator = config.mode.integrator();
+        let (u, v) = progressive_offset(sample_index);
+        tile.pixels()
+            .map(|(x, y)| {
+                rng::seed_pixel(config.seed, x, y, sample_index);
+                let pixel_ray = self
+                    .primary_ray(x as f32 + u, y as f32 + v)
+                    .with_time(shutter_time(sample_index));
+                integrator(scene, pixel_ray, config)
+            })
+            .collect()
+    }
+
+    /// Same image as `create_buffer`, with tiles shared out across rayon's thread pool and
+    /// `progress` told how far it's got as each one finishes
+    pub fn create_buffer_parallel(
+        &self,
+        scene: &Scene,
+        config: &RenderConfig,
+        progress: Option<&Progress>,
+    ) -> Vec<Vec<Colour>> {
+        let integrator = config.mode.integrator();
+        let mut buffer: Vec<Vec<Colour>> =
+            vec![vec![Colour::from_gray(0.0); self.width as usize]; self.height as usize];
+        self.render_tiles(
+            &mut buffer,
+            config,
+            |x, y| self.sample_pixel(integrator, scene, x, y, config),
+            progress,
+        );
+        buffer
+    }
+
+    /// Colours every pixel of `buffer` inside the config's crop with `colour_at`, rendering
+    /// `tile_size` squares in parallel. Each tile writes straight into its own part of the
+    /// buffer
+    fn render_tiles(
+        &self,
+        buffer: &mut [Vec<Colour>],
+        config: &RenderConfig,
+        colour_at: impl Fn(u32, u32) -> Colour + Sync,
+        progress: Option<&Progress>,
+    ) {
+        assert_eq!(buffer.len(), self.height as usize);
+        let tile_size = config.tile_size;
+
+        // cut each band of rows into its tiles' slices, in the same order `tiles` lists them
+        let size = tile_size.max(1) as usize;
+        let mut slices: Vec<Vec<&mut [Colour]>> = Vec::new();
+        for band in buffer.chunks_mut(size) {
+            let first_tile = slices.len();
+            for row in band.iter_mut() {
+                assert_eq!(row.len(), self.width as usize);
+                for (column, chunk) in row.chunks_mut(size).enumerate() {
+                    if first_tile + column == slices.len() {
+                        slices.push(Vec::with_capacity(size));
+                    }
+                    slices[first_tile + column].push(chunk);
+                }
+            }
+        }
+
+        let tiles = tile::tiles(self.width, self.height, tile_size);
+        let total = tiles.len();
+        // tiles finish in any order, so counting and reporting under one lock keeps the
+        // fractions going up. Taking it once a tile costs nothing next to rendering one
+        let finished = Mutex::new(0);
+        tiles.into_par_iter().zip(slices).for_each(|(tile, rows)| {
+            let pixels = rows.into_iter().flat_map(|row| row.iter_mut());
+            for ((x, y), pixel) in tile.pixels().zip(pixels) {
+                if config.in_crop(x, y) {
+                    *pixel = colour_at(x, y);
+                }
+            }
+            if let Some(progress) = progress {
+                let mut finished = finished.lock().unwrap();
+                *finished += 1;
+                progress(*finished as f32 / total as f32);
+            }
+        });
+    }
+}
+
+/// Base `base` radical inverse of `i`, mirroring its digits around the decimal point
+fn radical_inverse(mut i: u32, base: u32) -> f32 {
+    let mut result = 0.0;
+    let mut digit_value = 1.0 / base as f32;
+    while i > 0 {
+        result += (i % base) as f32 * digit_value;
+        i /= base;
+        digit_value /= base as f32;
+    }
+    result
+}
+
+/// Halton (2, 3) sequence shifted so that the first point is the pixel centre
+fn progressive_offset(i: u32) -> (f32, f32) {
+    (
+        (radical_inverse(i, 2) + 0.5).fract(),
+        (radical_inverse(i, 3) + 0.5).fract(),
+    )
+}
+
+/// When in the exposure sample `i` is taken, from a base 5 radical inverse so it doesn't
+/// line up with the position in the pixel. It's not drawn from `rng`, which would change
+/// every other random choice and with them the noise of scenes where nothing moves.
+/// Sample 0 is mid exposure
+fn shutter_time(i: u32) -> f32 {
+    (radical_inverse(i, 5) + 0.5).fract()
+}
+
+/// Position of sample `i` of `n` within a pixel, stratified along x and
+/// scattered along y with the base 2 radical inverse
+fn sample_offset(i: u32, n: u32) -> (f32, f32) {
+    let u = (i as f32 + 0.5) / n as f32;
+    let v = (i.reverse_bits() as f32 / 2f32.powi(32) + 0.5).fract();
+    (u, v)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use super::*;
+    use crate::{
+        background::Background, intersect::Intersectable, material::Material, renderer::RenderMode,
+        sphere::Sphere,
+    };
+
+    fn sphere(center: Vector3<f32>, radius: f32, colour: Colour) -> Intersectable {
+        Intersectable::Sphere(Sphere {
+            origin: center,
+            radius,
+            velocity: None,
+            material: Material::matte(colour),
+        })
+    }
+
+    fn albedo() -> RenderConfig {
+        RenderConfig {
+            mode: RenderMode::Albedo,
+            ..RenderConfig::default()
+        }
+    }
+
+    #[test]
+    fn half_turn_of_roll_flips_the_image_vertically() {
+        // mirror symmetric from side to side, so turning it upside down is the same as
+        // flipping it top to bottom
+        let scene = Scene::new(
+            vec![
+                sphere(
+                    Vector3::new(0.0, 0.0, 3.0),
+                    2.0,
+                    Colour::from_rgb(1.0, 0.0, 0.0),
+                ),
+                sphere(
+                    Vector3::new(0.0, 0.0, -4.0),
+                    1.0,
+                    Colour::from_rgb(0.0, 0.0, 1.0),
+                ),
+            ],
+            vec![],
+        );
+        let camera = Camera::default_view(32, 24);
+        let mut rolled = camera.clone();
+        rolled.roll(PI);
+
+        let upright = camera.create_buffer(&scene, &albedo());
+        let upside_down = rolled.create_buffer(&scene, &albedo());
+        let flipped: Vec<_> = upright.iter().rev().cloned().collect();
+        assert_ne!(upright, flipped);
+        assert_eq!(upside_down, flipped);
+    }
+
+    #[test]
+    fn supersampled_pixels_stay_opaque() {
+        let scene = Scene::new(vec![], vec![]);
+        let camera = Camera::default_view(4, 4).with_samples(4);
+        for pixel in camera.create_buffer(&scene, &albedo()).iter().flatten() {
+            assert_eq!(pixel.a(), 1.0);
+        }
+    }
+
+    #[test]
+    fn right_angle_view_reaches_one_unit_either_side() {
+        let camera = Camera::default_view(64, 64).with_fov(90.0);
+        let (forward, right, _) = camera.basis();
+        let slope = |x: f32| {
+            let direction = camera.pinhole_ray(x, 32.0).direction;
+            direction.dot(&right) / direction.dot(&forward)
+        };
+        assert!((slope(0.0) + 1.0).abs() < 1e-4, "{}", slope(0.0));
+        assert!((slope(64.0) - 1.0).abs() < 1e-4, "{}", slope(64.0));
+    }
+
+    #[test]
+    fn orthographic_rays_are_parallel() {
+        let camera = Camera::default_view(32, 16)
+            .with_projection(ProjectionKind::Orthographic { scale: 2.0 });
+        let left = camera.pinhole_ray(4.5, 8.5);
+        let right = camera.pinhole_ray(27.5, 8.5);
+        assert_eq!(left.direction, right.direction);
+        assert_ne!(left.origin, right.origin);
+    }
+
+    #[test]
+    fn parallel_render_matches_the_serial_one() {
+        let scene = Scene::pondering_orbs();
+        let camera = Camera::default_view(24, 16).with_samples(2);
+        let config = RenderConfig::default();
+        let serial = camera.create_buffer(&scene, &config);
+        let parallel = camera.create_buffer_parallel(&scene, &config, None);
+        assert_eq!(serial, parallel);
+    }
+
+    #[test]
+    fn supersampling_a_flat_colour_keeps_it() {
+        let sky = Colour::from_rgb(0.25, 0.5, 0.75);
+        let scene = Scene::new(vec![], vec![]).with_background(Background::Solid { colour: sky });
+        let camera = Camera::default_view(8, 8).with_samples(4);
+        let buffer = camera.create_buffer(&scene, &RenderConfig::default());
+        assert!(buffer.iter().flatten().all(|pixel| *pixel == sky));
+    }
+
+    #[test]
+    fn closed_aperture_is_a_pinhole() {
+        let camera = Camera::default_view(16, 16).with_aperture(0.0, 5.0);
+        for (x, y) in [(0.0, 0.0), (3.5, 12.25), (16.0, 16.0)] {
+            let (lens, pinhole) = (camera.primary_ray(x, y), camera.pinhole_ray(x, y));
+            assert_eq!(lens.origin, pinhole.origin);
+            assert_eq!(lens.direction, pinhole.direction);
+        }
+
+        // but an open one moves where rays start and still meets at the focus distance
+        let open = Camera::default_view(16, 16).with_aperture(0.5, 5.0);
+        let (lens, pinhole) = (open.primary_ray(8.0, 8.0), open.pinhole_ray(8.0, 8.0));
+        assert_ne!(lens.origin, pinhole.origin);
+        let focus = pinhole.at_point(5.0);
+        let missed_by = (lens.origin - focus).cross(&lens.direction).norm();
+        assert!(missed_by < 1e-4, "{}", missed_by);
+    }
+
+    #[test]
+    fn flat_colour_never_takes_more_than_the_minimum_samples() {
+        use std::sync::atomic::{AtomicU32, Ordering};
+        static SAMPLES: AtomicU32 = AtomicU32::new(0);
+        fn flat(_: &Scene, _: Ray, _: &RenderConfig) -> Colour {
+            SAMPLES.fetch_add(1, Ordering::SeqCst);
+            Colour::from_gray(0.5)
+        }
+
+        let adaptive = AdaptiveSampling {
+            min_samples: 4,
+            max_samples: 64,
+            variance_threshold: 1e-6,
+        };
+        let camera = Camera::default_view(4, 4).with_adaptive_sampling(Some(adaptive));
+        let scene = Scene::new(vec![], vec![]);
+        let config = RenderConfig::default();
+        for (x, y) in [(0, 0), (1, 2), (3, 3)] {
+            SAMPLES.store(0, Ordering::SeqCst);
+            let colour = camera.sample_pixel(flat, &scene, x, y, &config);
+            assert_eq!(colour, Colour::from_gray(0.5));
+            assert_eq!(SAMPLES.load(Ordering::SeqCst), 4);
+        }
+    }
+
+    #[test]
+    fn the_seed_alone_decides_a_path_traced_image() {
+        let scene = Scene::pondering_orbs();
+        let camera = Camera::default_view(16, 12);
+        let seeded = |seed| RenderConfig {
+            mode: RenderMode::PathTraced,
+            seed,
+            ..RenderConfig::default()
+        };
+        let first = camera.create_buffer_parallel(&scene, &seeded(7), None);
+        assert_eq!(first, camera.create_buffer(&scene, &seeded(7)));
+        assert_ne!(first, camera.create_buffer(&scene, &seeded(8)));
+    }
+
+    #[test]
+    fn sphere_beyond_the_far_plane_is_not_seen() {
+        // the middle pixel looks straight at the near side of the sphere, 8 from the camera
+        let red = Colour::from_rgb(1.0, 0.0, 0.0);
+        let scene = Scene::new(vec![sphere(Vector3::zeros(), 2.0, red)], vec![]);
+        let centre = |camera: Camera| camera.pixel_colour(&scene, 4, 4, &albedo());
+        assert_eq!(centre(Camera::default_view(9, 9)), red);
+        assert_eq!(centre(Camera::default_view(9, 9).with_clip(0.1, 8.5)), red);
+        assert_ne!(centre(Camera::default_view(9, 9).with_clip(0.1, 7.5)), red);
+    }
+
+    #[test]
+    fn moving_sphere_smears_along_its_motion() {
+        let ball = |velocity| {
+            Intersectable::Sphere(Sphere {
+                origin: Vector3::zeros(),
+                radius: 2.0,
+                velocity,
+                material: Material::matte(Colour::from_gray(1.0)),
+            })
+        };
+        let camera = Camera::default_view(40, 20).with_samples(16);
+        let render =
+            |velocity| camera.create_buffer(&Scene::new(vec![ball(velocity)], vec![]), &albedo());
+        // lit pixels along the middle row, about one to a world unit at the sphere
+        let footprint = |buffer: &[Vec<Colour>]| buffer[10].iter().filter(|p| p.r() > 0.0).count();
+
+        let still = render(None);
+        assert_eq!(render(Some(Vector3::zeros())), still);
+        let moving = render(Some(Vector3::new(0.0, 6.0, 0.0)));
+        assert!(
+            footprint(&moving) > footprint(&still) + 4,
+            "{} {}",
+            footprint(&moving),
+            footprint(&still)
+        );
+    }
+
+    #[test]
+    fn only_pixels_inside_the_crop_are_rendered_over() {
+        let sky = Colour::from_rgb(0.0, 0.0, 1.0);
+        let scene = Scene::new(vec![], vec![]).with_background(Background::Solid { colour: sky });
+        let camera = Camera::default_view(8, 6);
+        let config = RenderConfig {
+            crop: Some((2, 1, 6, 4)),
+            tile_size: 3, // tiles straddling the crop's edges
+            ..RenderConfig::default()
+        };
+        let before = Colour::from_rgb(0.0, 1.0, 0.0);
+        let mut buffer = vec![vec![before; 8]; 6];
+        camera.render_sample(&scene, &config, 0, &mut buffer);
+
+        for (y, row) in buffer.iter().enumerate() {
+            for (x, pixel) in row.iter().enumerate() {
+                let inside = (2..6).contains(&x) && (1..4).contains(&y);
+                assert_eq!(*pixel, if inside { sky } else { before }, "({}, {})", x, y);
+            }
+        }
+    }
 }
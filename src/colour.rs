@@ -0,0 +1,97 @@
+use std::ops::{Add, Mul};
+
+use serde::{Deserialize, Serialize};
+
+/// Linear, premultiplied RGBA, what every buffer and shading function works in. It only
+/// becomes gamma encoded bytes on the way out, see `output::Encoding`. Scene files write
+/// it as `[r, g, b, a]`
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+pub struct Colour([f32; 4]);
+
+impl Colour {
+    pub const fn from_rgba_premultiplied(r: f32, g: f32, b: f32, a: f32) -> Colour {
+        Colour([r, g, b, a])
+    }
+
+    pub const fn from_rgb(r: f32, g: f32, b: f32) -> Colour {
+        Colour([r, g, b, 1.0])
+    }
+
+    pub const fn from_gray(l: f32) -> Colour {
+        Colour([l, l, l, 1.0])
+    }
+
+    /// White at `a` coverage, premultiplied so every channel is `a`
+    pub const fn from_white_alpha(a: f32) -> Colour {
+        Colour([a, a, a, a])
+    }
+
+    /// Decodes an 8 bit sRGB pixel with straight alpha, as image files store them
+    pub fn from_srgba_unmultiplied([r, g, b, a]: [u8; 4]) -> Colour {
+        let alpha = a as f32 / 255.0;
+        let linear = |c: u8| {
+            let c = c as f32 / 255.0;
+            if c <= 0.04045 {
+                c / 12.92
+            } else {
+                ((c + 0.055) / 1.055).powf(2.4)
+            }
+        };
+        Colour([
+            linear(r) * alpha,
+            linear(g) * alpha,
+            linear(b) * alpha,
+            alpha,
+        ])
+    }
+
+    pub fn r(&self) -> f32 {
+        self.0[0]
+    }
+
+    pub fn g(&self) -> f32 {
+        self.0[1]
+    }
+
+    pub fn b(&self) -> f32 {
+        self.0[2]
+    }
+
+    pub fn a(&self) -> f32 {
+        self.0[3]
+    }
+
+    pub fn to_array(&self) -> [f32; 4] {
+        self.0
+    }
+
+    /// Perceived brightness, weighting green above red above blue
+    pub fn intensity(&self) -> f32 {
+        0.3 * self.r() + 0.59 * self.g() + 0.11 * self.b()
+    }
+}
+
+impl Add for Colour {
+    type Output = Colour;
+
+    fn add(self, other: Colour) -> Colour {
+        Colour(std::array::from_fn(|i| self.0[i] + other.0[i]))
+    }
+}
+
+/// Channel by channel, as when light of one colour lights a surface of another
+impl Mul for Colour {
+    type Output = Colour;
+
+    fn mul(self, other: Colour) -> Colour {
+        Colour(std::array::from_fn(|i| self.0[i] * other.0[i]))
+    }
+}
+
+impl Mul<f32> for Colour {
+    type Output = Colour;
+
+    fn mul(self, factor: f32) -> Colour {
+        Colour(self.0.map(|c| c * factor))
+    }
+}
@@ -0,0 +1,195 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    aabb::Aabb,
+    colour::Colour,
+    intersect::{Intersect, Intersectable, Intersection},
+    renderer::Ray,
+};
+
+const CROSSING_EPSILON: f32 = 1e-4; // how far past one crossing the search for the next starts
+const MAX_CROSSINGS: usize = 32; // per operand, in case a ray keeps hitting something open
+
+/// How the two operands of a `Csg` are combined
+#[derive(Clone, Copy, Debug, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum CsgOperation {
+    Union,        // inside either
+    Intersection, // inside both
+    Difference,   // inside the left but not the right
+}
+
+impl CsgOperation {
+    fn contains(self, in_left: bool, in_right: bool) -> bool {
+        match self {
+            CsgOperation::Union => in_left || in_right,
+            CsgOperation::Intersection => in_left && in_right,
+            CsgOperation::Difference => in_left && !in_right,
+        }
+    }
+}
+
+/// Solid made by combining two others, e.g. a sphere with a smaller one carved out of
+/// it. Both have to be closed, like spheres, boxes and tori, so that along any ray their
+/// surface takes turns entering and leaving. Each part of the surface keeps the
+/// material of the operand it came from
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Csg {
+    pub operation: CsgOperation,
+    pub left: Box<Intersectable>,
+    pub right: Box<Intersectable>,
+}
+
+/// One place a ray passes through an operand's surface
+struct Crossing {
+    distance: f32,
+    entering: bool,
+    intersection: Intersection,
+}
+
+impl Csg {
+    pub fn new(operation: CsgOperation, left: Intersectable, right: Intersectable) -> Csg {
+        Csg {
+            operation,
+            left: Box::new(left),
+            right: Box::new(right),
+        }
+    }
+
+    pub fn bounds(&self) -> Option<Aabb> {
+        let (left, right) = (self.left.bounds(), self.right.bounds());
+        match self.operation {
+            CsgOperation::Union => Some(Aabb::surrounding(&left?, &right?)),
+            CsgOperation::Intersection => match (left, right) {
+                (Some(a), Some(b)) => Some(Aabb::new(a.min.sup(&b.min), a.max.inf(&b.max))),
+                (a, b) => a.or(b),
+            },
+            CsgOperation::Difference => left,
+        }
+    }
+}
+
+impl Intersect for Csg {
+    fn test_intersection(&self, ray: &Ray) -> Intersection {
+        self.intersect_within(ray, 0.0, f32::INFINITY)
+    }
+
+    /// Walks through where the ray crosses either operand in order, keeping track of
+    /// which it's inside, and stops at the first crossing that takes it into or out of
+    /// the combined solid
+    fn intersect_within(&self, ray: &Ray, t_min: f32, t_max: f32) -> Intersection {
+        let left = crossings(&self.left, ray);
+        let right = crossings(&self.right, ray);
+        // a ray whose first crossing leaves an operand started inside it
+        let mut in_left = left.first().is_some_and(|crossing| !crossing.entering);
+        let mut in_right = right.first().is_some_and(|crossing| !crossing.entering);
+        let mut inside = self.operation.contains(in_left, in_right);
+
+        let mut events = left
+            .iter()
+            .map(|crossing| (crossing, false))
+            .chain(right.iter().map(|crossing| (crossing, true)))
+            .collect::<Vec<_>>();
+        events.sort_by(|(a, _), (b, _)| a.distance.total_cmp(&b.distance));
+
+        for (crossing, from_right) in events {
+            if from_right {
+                in_right = crossing.entering;
+            } else {
+                in_left = crossing.entering;
+            }
+            let now_inside = self.operation.contains(in_left, in_right);
+            if now_inside == inside {
+                continue;
+            }
+            inside = now_inside;
+            if crossing.distance <= t_min {
+                continue;
+            }
+            if crossing.distance >= t_max {
+                break;
+            }
+
+            let mut intersection = crossing.intersection.clone();
+            intersection.distance = Some(crossing.distance);
+            // what's carved out is outside the solid, so its surface faces into it
+            if from_right && self.operation == CsgOperation::Difference {
+                if let Some(normal) = &mut intersection.normal {
+                    normal.direction = -normal.direction;
+                    intersection.front_face = !intersection.front_face;
+                }
+            }
+            return intersection;
+        }
+        Intersection::new(Colour::from_gray(0.0), None, None)
+    }
+}
+
+/// Every crossing of `object`'s surface along the ray, nearest first, found by
+/// restarting the ray just past each one
+fn crossings(object: &Intersectable, ray: &Ray) -> Vec<Crossing> {
+    let mut crossings = Vec::new();
+    let mut travelled = 0.0;
+    while crossings.len() < MAX_CROSSINGS {
+        let rest = Ray::new_preserve(ray.at_point(travelled), ray.direction).with_time(ray.time);
+        let intersection = object.test_intersection(&rest);
+        let (Some(distance), Some(_)) = (intersection.distance, &intersection.normal) else {
+            break;
+        };
+        travelled += distance;
+        crossings.push(Crossing {
+            distance: travelled,
+            entering: intersection.front_face,
+            intersection,
+        });
+        travelled += CROSSING_EPSILON;
+    }
+    crossings
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+
+    use super::*;
+    use crate::{material::Material, sphere::Sphere};
+
+    fn ball(origin: Vector3<f32>, radius: f32) -> Intersectable {
+        Intersectable::Sphere(Sphere {
+            origin,
+            radius,
+            velocity: None,
+            material: Material::matte(Colour::from_gray(1.0)),
+        })
+    }
+
+    /// A ball of radius 2 with a bite of radius 1 taken out of its -x side
+    fn bitten() -> Csg {
+        Csg::new(
+            CsgOperation::Difference,
+            ball(Vector3::zeros(), 2.0),
+            ball(Vector3::new(-2.0, 0.0, 0.0), 1.0),
+        )
+    }
+
+    #[test]
+    fn ray_into_the_bite_hits_the_cavity_wall() {
+        let ray = Ray::new(Vector3::new(-5.0, 0.0, 0.0), Vector3::x());
+        let hit = bitten().test_intersection(&ray);
+        let distance = hit.distance.unwrap();
+        assert!((distance - 4.0).abs() < 1e-4, "{}", distance);
+        // the carved out sphere's normal, turned round to point out of what's left
+        let normal = hit.normal.unwrap().direction;
+        assert!((normal + Vector3::x()).norm() < 1e-4, "{}", normal);
+    }
+
+    #[test]
+    fn ray_past_the_bite_hits_the_outside() {
+        let ray = Ray::new(Vector3::new(-5.0, 0.0, 1.5), Vector3::x());
+        let hit = bitten().test_intersection(&ray);
+        let point = ray.at_point(hit.distance.unwrap());
+        assert!((point.norm() - 2.0).abs() < 1e-4, "{}", point);
+        assert!(hit.normal.unwrap().direction.dot(&point) > 0.0);
+    }
+}
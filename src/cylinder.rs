@@ -0,0 +1,133 @@
+use nalgebra::Vector3;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    aabb::Aabb,
+    colour::Colour,
+    intersect::{Intersect, Intersection},
+    material::Material,
+    renderer::Ray,
+};
+
+const PARALLEL_EPSILON: f32 = 1e-6;
+
+/// Finite cylinder running from `start` to `end`, closed off with flat caps
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Cylinder {
+    pub start: Vector3<f32>,
+    pub end: Vector3<f32>,
+    pub radius: f32,
+    pub material: Material,
+}
+
+impl Cylinder {
+    /// Nearest crossing in front of the ray's origin and the outward normal there,
+    /// checking the curved side and both caps
+    fn hit(&self, ray: &Ray) -> Option<(f32, Vector3<f32>)> {
+        let axis = self.end - self.start;
+        let height = axis.norm();
+        let axis = axis / height;
+        let mut nearest: Option<(f32, Vector3<f32>)> = None;
+        let mut consider = |t: f32, normal: Vector3<f32>| {
+            if t > 0.0 && nearest.is_none_or(|(best, _)| t < best) {
+                nearest = Some((t, normal));
+            }
+        };
+
+        // the side: drop the components along the axis and solve for the circle.
+        // A ray running alongside the axis never crosses it
+        let offset = ray.origin - self.start;
+        let direction_across = ray.direction - axis * ray.direction.dot(&axis);
+        let offset_across = offset - axis * offset.dot(&axis);
+        let a = direction_across.norm_squared();
+        if a > PARALLEL_EPSILON {
+            let half_b = direction_across.dot(&offset_across);
+            let c = offset_across.norm_squared() - self.radius.powi(2);
+            let discriminant = half_b.powi(2) - a * c;
+            if discriminant >= 0.0 {
+                let root = discriminant.sqrt();
+                for t in [(-half_b - root) / a, (-half_b + root) / a] {
+                    let along = (offset + ray.direction * t).dot(&axis);
+                    if (0.0..=height).contains(&along) {
+                        let across = offset_across + direction_across * t;
+                        consider(t, across / self.radius);
+                    }
+                }
+            }
+        }
+
+        // the caps: planes at either end, kept only within the radius
+        let denominator = ray.direction.dot(&axis);
+        if denominator.abs() > PARALLEL_EPSILON {
+            for (centre, normal) in [(self.start, -axis), (self.end, axis)] {
+                let t = (centre - ray.origin).dot(&axis) / denominator;
+                if (ray.at_point(t) - centre).norm_squared() <= self.radius.powi(2) {
+                    consider(t, normal);
+                }
+            }
+        }
+
+        nearest
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        // each cap is a disk, which reaches out radius * sin(angle to the axis) along each world axis
+        let axis = (self.end - self.start).normalize();
+        let extent = axis.map(|a| self.radius * (1.0 - a * a).max(0.0).sqrt());
+        Aabb::new(
+            self.start.inf(&self.end) - extent,
+            self.start.sup(&self.end) + extent,
+        )
+    }
+}
+
+impl Intersect for Cylinder {
+    fn test_intersection(&self, ray: &Ray) -> Intersection {
+        let Some((t, normal)) = self.hit(ray) else {
+            return Intersection::new(Colour::from_gray(0.0), None, None);
+        };
+
+        let normal_ray = Ray::new(ray.at_point(t), normal);
+        Intersection::new(
+            self.material.colour_at(&normal_ray.origin, None),
+            Some(t),
+            Some(normal_ray),
+        )
+        .with_material(self.material.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Radius 1, standing on the origin 2 high
+    fn post() -> Cylinder {
+        Cylinder {
+            start: Vector3::zeros(),
+            end: Vector3::new(0.0, 0.0, 2.0),
+            radius: 1.0,
+            material: Material::matte(Colour::from_gray(1.0)),
+        }
+    }
+
+    #[test]
+    fn side_hit_faces_out_from_the_axis() {
+        let ray = Ray::new(Vector3::new(-5.0, 0.0, 1.0), Vector3::x());
+        let hit = post().test_intersection(&ray);
+        assert!((hit.distance.unwrap() - 4.0).abs() < 1e-5);
+        assert!((hit.normal.unwrap().direction + Vector3::x()).norm() < 1e-5);
+    }
+
+    #[test]
+    fn ray_down_the_axis_hits_the_top_cap() {
+        let ray = Ray::new(Vector3::new(0.5, 0.0, 5.0), -Vector3::z());
+        let hit = post().test_intersection(&ray);
+        assert!((hit.distance.unwrap() - 3.0).abs() < 1e-5);
+        assert_eq!(hit.normal.unwrap().direction, Vector3::z());
+
+        let outside = Ray::new(Vector3::new(1.5, 0.0, 5.0), -Vector3::z());
+        assert_eq!(post().test_intersection(&outside).distance, None);
+    }
+}
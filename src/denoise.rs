@@ -0,0 +1,234 @@
+use rayon::prelude::*;
+
+use crate::{
+    camera::Camera,
+    colour::Colour,
+    renderer::{RenderConfig, RenderMode},
+    scene::Scene,
+};
+
+// the 1D B3 spline kernel, applied in both directions with ever wider gaps between taps
+const KERNEL: [f32; 5] = [1.0 / 16.0, 1.0 / 4.0, 3.0 / 8.0, 1.0 / 4.0, 1.0 / 16.0];
+
+/// How hard the denoiser smooths. Each sigma is how different two pixels can be in that
+/// buffer before they stop being averaged together, so smaller keeps more edges
+#[derive(Clone, Copy, Debug)]
+pub struct DenoiseSettings {
+    pub iterations: u32, // each doubles the filter's reach, 0 leaves the image alone
+    pub colour_sigma: f32,
+    pub normal_sigma: f32,
+    pub albedo_sigma: f32,
+}
+
+impl Default for DenoiseSettings {
+    fn default() -> Self {
+        DenoiseSettings {
+            iterations: 5,
+            colour_sigma: 0.5,
+            normal_sigma: 0.1,
+            albedo_sigma: 0.1,
+        }
+    }
+}
+
+/// The normal and albedo passes of a view, which find the edges that noise hides
+pub struct Guides {
+    pub normals: Vec<Vec<Colour>>, // mapped onto 0..1 as in `RenderMode::Normals`
+    pub albedo: Vec<Vec<Colour>>,
+}
+
+impl Guides {
+    pub fn render(camera: &Camera, scene: &Scene, config: &RenderConfig) -> Guides {
+        let pass =
+            |mode| camera.create_buffer_parallel(scene, &RenderConfig { mode, ..*config }, None);
+        Guides {
+            normals: pass(RenderMode::Normals),
+            albedo: pass(RenderMode::Albedo),
+        }
+    }
+}
+
+/// Edge avoiding à-trous wavelet filter (Dammertz et al. 2010). Every iteration blurs
+/// with a 5x5 kernel whose taps are spread twice as far apart as the last, weighting each
+/// neighbour by how alike it is in colour, normal and albedo. The colour sigma halves
+/// every iteration, as the noise it has to see past is already mostly gone
+pub fn denoise(
+    colour: &[Vec<Colour>],
+    guides: &Guides,
+    settings: &DenoiseSettings,
+) -> Vec<Vec<Colour>> {
+    // filtered as the colours that get shown, so pixels only look alike if they are
+    let mut current = map_pixels(colour, |pixel| {
+        let alpha = pixel.a();
+        let scale = if alpha > 0.0 { 1.0 / alpha } else { 1.0 };
+        Colour::from_rgba_premultiplied(
+            pixel.r() * scale,
+            pixel.g() * scale,
+            pixel.b() * scale,
+            alpha,
+        )
+    });
+    for iteration in 0..settings.iterations {
+        let step = 1i64 << iteration;
+        let colour_sigma = settings.colour_sigma / (1u32 << iteration) as f32;
+        current = filter_pass(&current, guides, settings, step, colour_sigma);
+    }
+    map_pixels(&current, |pixel| {
+        let alpha = pixel.a();
+        Colour::from_rgba_premultiplied(
+            pixel.r() * alpha,
+            pixel.g() * alpha,
+            pixel.b() * alpha,
+            alpha,
+        )
+    })
+}
+
+fn map_pixels(buffer: &[Vec<Colour>], f: impl Fn(Colour) -> Colour) -> Vec<Vec<Colour>> {
+    buffer
+        .iter()
+        .map(|row| row.iter().map(|pixel| f(*pixel)).collect())
+        .collect()
+}
+
+fn filter_pass(
+    colour: &[Vec<Colour>],
+    guides: &Guides,
+    settings: &DenoiseSettings,
+    step: i64,
+    colour_sigma: f32,
+) -> Vec<Vec<Colour>> {
+    let height = colour.len() as i64;
+    let width = colour.first().map_or(0, |row| row.len()) as i64;
+    (0..height)
+        .into_par_iter()
+        .map(|y| {
+            (0..width)
+                .map(|x| {
+                    let (py, px) = (y as usize, x as usize);
+                    let centre = colour[py][px];
+                    let mut sum = Colour::default();
+                    let mut total_weight = 0.0;
+                    for (j, ky) in KERNEL.iter().enumerate() {
+                        for (i, kx) in KERNEL.iter().enumerate() {
+                            let qx = x + (i as i64 - 2) * step;
+                            let qy = y + (j as i64 - 2) * step;
+                            if qx < 0 || qy < 0 || qx >= width || qy >= height {
+                                continue;
+                            }
+                            let (qy, qx) = (qy as usize, qx as usize);
+                            let sample = colour[qy][qx];
+                            let weight = ky
+                                * kx
+                                * similarity(centre, sample, colour_sigma)
+                                * similarity(
+                                    guides.normals[py][px],
+                                    guides.normals[qy][qx],
+                                    settings.normal_sigma,
+                                )
+                                * similarity(
+                                    guides.albedo[py][px],
+                                    guides.albedo[qy][qx],
+                                    settings.albedo_sigma,
+                                );
+                            sum = sum + sample * weight;
+                            total_weight += weight;
+                        }
+                    }
+                    // the centre always has a weight of its own, so this never divides by 0
+                    sum * (1.0 / total_weight)
+                })
+                .collect()
+        })
+        .collect()
+}
+
+/// 1 for identical colours, falling towards 0 as they grow further apart than `sigma`
+fn similarity(a: Colour, b: Colour, sigma: f32) -> f32 {
+    let difference = [a.r() - b.r(), a.g() - b.g(), a.b() - b.b()];
+    let distance_squared: f32 = difference.iter().map(|d| d * d).sum();
+    (-distance_squared / sigma.powi(2).max(f32::EPSILON)).exp()
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+
+    use super::*;
+    use crate::{intersect::Intersectable, material::Material, plane::Plane, sphere::Sphere};
+
+    #[test]
+    fn guides_of_a_flat_mirror_only_see_the_mirror() {
+        // the red ball behind the camera shows up in the mirror, but the guides only
+        // look at what the camera's rays hit first
+        let mirror = Intersectable::Plane(Plane {
+            point: Vector3::zeros(),
+            normal: -Vector3::x(),
+            material: Material::mirror(Colour::from_gray(0.8)),
+        });
+        let ball = Intersectable::Sphere(Sphere {
+            origin: Vector3::new(-20.0, 0.0, 0.0),
+            radius: 5.0,
+            velocity: None,
+            material: Material::matte(Colour::from_rgb(1.0, 0.0, 0.0)),
+        });
+        let scene = Scene::new(vec![mirror, ball], vec![]);
+        let camera = Camera::default_view(12, 8);
+        let guides = Guides::render(&camera, &scene, &RenderConfig::default());
+
+        let facing_back = Colour::from_rgb(0.0, 0.5, 0.5);
+        assert!(guides.normals.iter().flatten().all(|n| *n == facing_back));
+        let mirror_colour = Colour::from_gray(0.8);
+        assert!(guides.albedo.iter().flatten().all(|a| *a == mirror_colour));
+    }
+
+    #[test]
+    fn noise_is_smoothed_away_but_the_edge_between_two_patches_stays_sharp() {
+        // a dark left half and a bright right half, both noisy
+        let (width, height) = (32, 16);
+        let patch = |x: usize| if x < width / 2 { 0.2 } else { 0.8 };
+        crate::rng::seed_pixel(3, 0, 0, 0);
+        let noisy: Vec<Vec<Colour>> = (0..height)
+            .map(|_| {
+                (0..width)
+                    .map(|x| Colour::from_gray(patch(x) + (crate::rng::random() - 0.5) * 0.2))
+                    .collect()
+            })
+            .collect();
+        let guides = Guides {
+            normals: vec![vec![Colour::from_rgb(0.5, 0.5, 1.0); width]; height],
+            albedo: (0..height)
+                .map(|_| (0..width).map(|x| Colour::from_gray(patch(x))).collect())
+                .collect(),
+        };
+        let settings = DenoiseSettings::default();
+        let denoised = denoise(&noisy, &guides, &settings);
+
+        let variance = |buffer: &[Vec<Colour>], columns: std::ops::Range<usize>| {
+            let values: Vec<f32> = buffer
+                .iter()
+                .flat_map(|row| row[columns.clone()].iter().map(|pixel| pixel.r()))
+                .collect();
+            let mean = values.iter().sum::<f32>() / values.len() as f32;
+            values.iter().map(|v| (v - mean).powi(2)).sum::<f32>() / values.len() as f32
+        };
+        for interior in [2..14, 18..30] {
+            let (before, after) = (
+                variance(&noisy, interior.clone()),
+                variance(&denoised, interior),
+            );
+            assert!(after < before / 4.0, "{} {}", before, after);
+        }
+        for row in &denoised {
+            for x in [width / 2 - 1, width / 2] {
+                assert!((row[x].r() - patch(x)).abs() < 0.1, "{}: {}", x, row[x].r());
+            }
+        }
+
+        let untouched = DenoiseSettings {
+            iterations: 0,
+            ..settings
+        };
+        assert_eq!(denoise(&noisy, &guides, &untouched), noisy);
+    }
+}
@@ -0,0 +1,97 @@
+use cgraphics::{colour::Colour, output};
+use eframe::egui::{self, ColorImage, TextureHandle, TextureOptions};
+
+/// The buffer as a texture for egui to draw, encoded and uploaded again only after the
+/// buffer or the way it's encoded changes, so an idle view costs nothing to show
+pub struct Display {
+    pixels: Vec<u8>, // scratch space the buffer is encoded into, kept between uploads
+    texture: Option<TextureHandle>,
+    encoding: Option<output::Encoding>, // of what's in `texture`
+    stale: bool,
+}
+
+impl Display {
+    pub fn new() -> Display {
+        Display {
+            pixels: Vec::new(),
+            texture: None,
+            encoding: None,
+            stale: true,
+        }
+    }
+
+    /// Marks the buffer as changed since it was last shown
+    pub fn invalidate(&mut self) {
+        self.stale = true;
+    }
+
+    /// The texture showing `buffer`, and whether it had to be uploaded again this time
+    pub fn texture(
+        &mut self,
+        ctx: &egui::Context,
+        buffer: &[Vec<Colour>],
+        encoding: output::Encoding,
+    ) -> (&TextureHandle, bool) {
+        let changed = self.stale || self.encoding != Some(encoding) || self.texture.is_none();
+        if changed {
+            output::flatten_into(buffer, encoding, &mut self.pixels);
+            let size = [buffer.first().map_or(0, |row| row.len()), buffer.len()];
+            let image = ColorImage::from_rgba_unmultiplied(size, &self.pixels);
+            match &mut self.texture {
+                Some(texture) => texture.set(image, TextureOptions::default()),
+                None => {
+                    self.texture =
+                        Some(ctx.load_texture("render", image, TextureOptions::default()))
+                }
+            }
+            self.encoding = Some(encoding);
+            self.stale = false;
+        }
+        (self.texture.as_ref().unwrap(), changed)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn buffer(level: f32) -> Vec<Vec<Colour>> {
+        vec![vec![Colour::from_gray(level); 64]; 48]
+    }
+
+    #[test]
+    fn unchanged_buffer_isnt_uploaded_again() {
+        let ctx = egui::Context::default();
+        let mut display = Display::new();
+        let encoding = output::Encoding::default();
+        let (texture, uploaded) = display.texture(&ctx, &buffer(0.5), encoding);
+        let id = texture.id();
+        assert!(uploaded);
+        for _ in 0..10 {
+            let (texture, uploaded) = display.texture(&ctx, &buffer(0.5), encoding);
+            assert!(!uploaded);
+            assert_eq!(texture.id(), id);
+        }
+    }
+
+    #[test]
+    fn changes_reuse_the_texture_and_scratch_space() {
+        let ctx = egui::Context::default();
+        let mut display = Display::new();
+        let encoding = output::Encoding::default();
+        let id = display.texture(&ctx, &buffer(0.0), encoding).0.id();
+        let scratch = display.pixels.as_ptr();
+        for level in [0.25, 0.5, 1.0] {
+            display.invalidate();
+            let (texture, uploaded) = display.texture(&ctx, &buffer(level), encoding);
+            assert!(uploaded);
+            assert_eq!(texture.id(), id);
+            assert_eq!(display.pixels.as_ptr(), scratch);
+        }
+        let dithered = output::Encoding {
+            dither: true,
+            ..encoding
+        };
+        assert!(display.texture(&ctx, &buffer(1.0), dithered).1);
+    }
+}
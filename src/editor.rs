@@ -0,0 +1,115 @@
+use eframe::egui::{self, color_picker, Rgba};
+
+use cgraphics::{
+    colour::Colour, material::Material, renderer::RenderConfig, sphere::Sphere, texture::Texture,
+};
+
+pub const MIN_RADIUS: f32 = 0.01; // smaller spheres vanish between pixels anyway
+const MAX_RADIUS: f32 = 20.0;
+const POSITION_SPEED: f32 = 0.05; // world units per point dragged
+const MAX_DEPTH: u32 = 100;
+const DEEP_DEPTH: u32 = 16; // beyond this the slider warns, mirrors facing each other get slow
+const MAX_SHADOW_SAMPLES: u32 = 64;
+
+/// A sphere for the "Add sphere" button to drop in front of the camera
+pub fn new_sphere(center: nalgebra::Vector3<f32>) -> Sphere {
+    Sphere {
+        origin: center,
+        radius: 1.0,
+        velocity: None,
+        material: Material::matte(Colour::from_gray(0.7)),
+    }
+}
+
+/// Widgets for the sphere's centre, radius and colour. Returns whether anything changed
+pub fn sphere_editor(ui: &mut egui::Ui, sphere: &mut Sphere) -> bool {
+    let mut changed = false;
+    ui.horizontal(|ui| {
+        ui.label("Center");
+        for axis in 0..3 {
+            changed |= ui
+                .add(egui::DragValue::new(&mut sphere.origin[axis]).speed(POSITION_SPEED))
+                .changed();
+        }
+    });
+    changed |= ui
+        .add(egui::Slider::new(&mut sphere.radius, MIN_RADIUS..=MAX_RADIUS).text("Radius"))
+        .changed();
+    // typed in or loaded values can still be out of range
+    sphere.radius = sphere.radius.max(MIN_RADIUS);
+
+    ui.horizontal(|ui| {
+        ui.label("Colour");
+        match &mut sphere.material.albedo {
+            Texture::Solid(colour) => {
+                // egui's picker edits its own colour type, converted back only if it moved
+                let mut rgba = to_rgba(*colour);
+                if color_picker::color_edit_button_rgba(ui, &mut rgba, color_picker::Alpha::Opaque)
+                    .changed()
+                {
+                    *colour = from_rgba(rgba);
+                    changed = true;
+                }
+            }
+            _ => {
+                ui.label("textured");
+            }
+        }
+    });
+    changed
+}
+
+/// egui's `Rgba` is linear and premultiplied like `Colour`, so converting only copies
+fn to_rgba(colour: Colour) -> Rgba {
+    let [r, g, b, a] = colour.to_array();
+    Rgba::from_rgba_premultiplied(r, g, b, a)
+}
+
+fn from_rgba(rgba: Rgba) -> Colour {
+    let [r, g, b, a] = rgba.to_array();
+    Colour::from_rgba_premultiplied(r, g, b, a)
+}
+
+/// Widgets for how the renderer traces, returning whether anything changed so the view
+/// can be rendered again
+pub fn render_settings(ui: &mut egui::Ui, config: &mut RenderConfig) -> bool {
+    // 0 is direct shading only, no reflected or refracted rays at all
+    let mut changed = ui
+        .add(egui::Slider::new(&mut config.max_depth, 0..=MAX_DEPTH).text("Max bounces"))
+        .changed();
+    if config.max_depth > DEEP_DEPTH {
+        ui.colored_label(ui.visuals().warn_fg_color, "Deep bounces can be very slow");
+    }
+    // 1 is a hard edge, more blur it a little like a small area light would
+    changed |= ui
+        .add(
+            egui::Slider::new(&mut config.shadow_samples, 1..=MAX_SHADOW_SAMPLES)
+                .text("Shadow samples"),
+        )
+        .changed();
+    changed
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn colours_round_trip_through_egui() {
+        let colour = Colour::from_rgba_premultiplied(0.25, 0.5, 2.0, 0.75);
+        let rgba = to_rgba(colour);
+        assert_eq!(rgba.to_array(), [0.25, 0.5, 2.0, 0.75]);
+        assert_eq!(from_rgba(rgba), colour);
+    }
+
+    #[test]
+    fn radius_typed_in_below_the_minimum_is_raised_to_it() {
+        let mut sphere = new_sphere(nalgebra::Vector3::zeros());
+        sphere.radius = -3.0;
+        let ctx = egui::Context::default();
+        let _ = ctx.run(Default::default(), |ctx| {
+            egui::CentralPanel::default().show(ctx, |ui| sphere_editor(ui, &mut sphere));
+        });
+        assert_eq!(sphere.radius, MIN_RADIUS);
+    }
+}
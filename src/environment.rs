@@ -0,0 +1,193 @@
+use std::f32::consts::PI;
+
+use nalgebra::Vector3;
+
+use crate::{sphere::Sphere, texture::ImageTexture};
+
+/// Picks directions towards an environment map in proportion to how much light comes
+/// from them, so a small bright sun in a wide dim sky gets found by the rays aimed at it
+/// rather than by the odd bounce that happens to wander into it. Each texel is weighted
+/// by its brightness and by the solid angle it covers, which shrinks towards the poles
+/// of the equirectangular image, so a map that's the same colour everywhere samples
+/// every direction equally
+#[derive(Clone)]
+pub struct EnvironmentSampler {
+    width: u32,
+    height: u32,
+    rows: Vec<f32>,         // cumulative chance of each row, the last 1
+    columns: Vec<Vec<f32>>, // cumulative chance of each texel within its row
+}
+
+impl EnvironmentSampler {
+    /// `None` for an image with no light in it, which there'd be no point aiming at
+    pub fn new(image: &ImageTexture) -> Option<EnvironmentSampler> {
+        let (width, height) = image.dimensions();
+        let mut row_weights = Vec::with_capacity(height as usize);
+        let mut columns = Vec::with_capacity(height as usize);
+        for y in 0..height {
+            // relative to the texels on the equator
+            let solid_angle = latitude((y as f32 + 0.5) / height as f32).cos();
+            let weights = (0..width)
+                .map(|x| image.texel(x, y).intensity().max(0.0) * solid_angle)
+                .collect::<Vec<_>>();
+            let (cdf, total) = cumulative(&weights);
+            row_weights.push(total);
+            columns.push(cdf);
+        }
+        let (rows, total) = cumulative(&row_weights);
+        if total <= 0.0 {
+            return None;
+        }
+        Some(EnvironmentSampler {
+            width,
+            height,
+            rows,
+            columns,
+        })
+    }
+
+    /// A direction towards the map and the density of picking it per steradian
+    pub fn sample(&self, mut random: impl FnMut() -> f32) -> (Vector3<f32>, f32) {
+        let y = pick(&self.rows, random());
+        let x = pick(&self.columns[y], random());
+        // anywhere within the texel
+        let u = (x as f32 + random()) / self.width as f32;
+        let v = (y as f32 + random()) / self.height as f32;
+        (direction(u, v), self.texel_pdf(x, y, v))
+    }
+
+    /// Density of `sample` returning `direction`, per steradian
+    pub fn pdf(&self, direction: &Vector3<f32>) -> f32 {
+        let uv = Sphere::uv(&direction.normalize());
+        let x = ((uv.x * self.width as f32) as usize).min(self.width as usize - 1);
+        let y = ((uv.y * self.height as f32) as usize).min(self.height as usize - 1);
+        self.texel_pdf(x, y, uv.y)
+    }
+
+    /// Chance of texel (x, y) spread over the patch of sky it covers, which is
+    /// 2 pi^2 cos(latitude) / (width * height) steradians around the point `v` of the way
+    /// down the image. The latitude comes from `v` rather than the direction's height,
+    /// which loses too much precision near the poles
+    fn texel_pdf(&self, x: usize, y: usize, v: f32) -> f32 {
+        let cos_latitude = latitude(v).cos();
+        if cos_latitude <= 0.0 {
+            return 0.0;
+        }
+        let chance = step(&self.rows, y) * step(&self.columns[y], x);
+        chance * (self.width * self.height) as f32 / (2.0 * PI * PI * cos_latitude)
+    }
+}
+
+/// Running totals of `weights` scaled to end at 1, and what they added up to before. All
+/// zero weights give an even spread, though nothing picks from it with a chance of 0
+fn cumulative(weights: &[f32]) -> (Vec<f32>, f32) {
+    let total: f32 = weights.iter().sum();
+    if total <= 0.0 {
+        let n = weights.len() as f32;
+        return ((1..=weights.len()).map(|i| i as f32 / n).collect(), 0.0);
+    }
+    let mut running = 0.0;
+    let cdf = weights
+        .iter()
+        .map(|weight| {
+            running += weight;
+            running / total
+        })
+        .collect();
+    (cdf, total)
+}
+
+/// Index of the first entry of `cdf` above `u`, so each is picked with the chance of its step
+fn pick(cdf: &[f32], u: f32) -> usize {
+    cdf.partition_point(|&c| c <= u).min(cdf.len() - 1)
+}
+
+/// Chance of `pick` landing on `i`
+fn step(cdf: &[f32], i: usize) -> f32 {
+    cdf[i] - if i == 0 { 0.0 } else { cdf[i - 1] }
+}
+
+/// Latitude of the row `v` of the way down the image, pi / 2 at the top
+fn latitude(v: f32) -> f32 {
+    (0.5 - v) * PI
+}
+
+/// The direction `Sphere::uv` maps to (u, v), which is where `Background::Image` shows it
+fn direction(u: f32, v: f32) -> Vector3<f32> {
+    let longitude = (u - 0.5) * 2.0 * PI;
+    let (sin_latitude, cos_latitude) = latitude(v).sin_cos();
+    Vector3::new(
+        cos_latitude * longitude.cos(),
+        cos_latitude * longitude.sin(),
+        sin_latitude,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use image::{Rgba, RgbaImage};
+
+    use super::*;
+    use crate::rng;
+
+    fn sampler(image: RgbaImage, name: &str) -> EnvironmentSampler {
+        let path = std::env::temp_dir().join(format!("{}-{}.png", name, std::process::id()));
+        image.save(&path).unwrap();
+        let texture = ImageTexture::load(path.clone());
+        std::fs::remove_file(&path).unwrap();
+        EnvironmentSampler::new(&texture.unwrap()).unwrap()
+    }
+
+    #[test]
+    fn one_bright_texel_is_where_most_samples_go() {
+        let (bright_x, bright_y) = (5, 3);
+        let image = RgbaImage::from_fn(16, 8, |x, y| {
+            if (x, y) == (bright_x, bright_y) {
+                Rgba([255, 255, 255, 255])
+            } else {
+                Rgba([1, 1, 1, 255])
+            }
+        });
+        let sampler = sampler(image, "sun");
+        let increasing = |cdf: &[f32]| cdf.windows(2).all(|pair| pair[0] <= pair[1]);
+        assert!(increasing(&sampler.rows));
+        assert!(sampler.columns.iter().all(|cdf| increasing(cdf)));
+        assert!((sampler.rows.last().unwrap() - 1.0).abs() < 1e-6);
+
+        rng::seed_pixel(0, 0, 0, 0);
+        let samples = 1000;
+        let on_the_sun = (0..samples)
+            .filter(|_| {
+                let (direction, _) = sampler.sample(rng::random);
+                let uv = Sphere::uv(&direction);
+                ((uv.x * 16.0) as u32, (uv.y * 8.0) as u32) == (bright_x, bright_y)
+            })
+            .count();
+        assert!(on_the_sun > samples * 9 / 10, "{}", on_the_sun);
+    }
+
+    #[test]
+    fn even_map_samples_every_direction_equally() {
+        let sampler = sampler(
+            RgbaImage::from_pixel(16, 8, Rgba([128, 128, 128, 255])),
+            "even",
+        );
+        for cdf in &sampler.columns {
+            let chances: Vec<f32> = (0..cdf.len()).map(|x| step(cdf, x)).collect();
+            assert!(
+                chances.iter().all(|c| (c - 1.0 / 16.0).abs() < 1e-6),
+                "{:?}",
+                chances
+            );
+        }
+
+        // directions uniform over the sphere have heights uniform over -1..1
+        rng::seed_pixel(0, 0, 0, 0);
+        let samples = 4000;
+        let height: f32 = (0..samples)
+            .map(|_| sampler.sample(rng::random).0.z.abs())
+            .sum();
+        let mean = height / samples as f32;
+        assert!((mean - 0.5).abs() < 0.03, "{}", mean);
+    }
+}
@@ -0,0 +1,181 @@
+use std::f32::consts::PI;
+
+use nalgebra::{Vector2, Vector3};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    aabb::Aabb,
+    colour::Colour,
+    intersect::{Intersect, Intersection},
+    material::Material,
+    math,
+    plane::plane_hit,
+    renderer::Ray,
+};
+
+// flat shapes get a box this thick so the BVH can still hit it
+const BOUNDS_PADDING: f32 = 1e-4;
+
+/// Circle of `radius` around `center`, facing `normal`
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Disk {
+    pub center: Vector3<f32>,
+    pub normal: Vector3<f32>,
+    pub radius: f32,
+    pub material: Material,
+}
+
+/// Parallelogram with one corner at `corner` and sides `edge_u` and `edge_v`, so a
+/// rectangle when the edges are perpendicular. Faces along `edge_u × edge_v`
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Quad {
+    pub corner: Vector3<f32>,
+    pub edge_u: Vector3<f32>,
+    pub edge_v: Vector3<f32>,
+    pub material: Material,
+}
+
+impl Disk {
+    /// Distance along the ray and the UV of the hit, u going once around the rim
+    /// and v out from the centre to the edge
+    fn hit(&self, ray: &Ray) -> Option<(f32, Vector2<f32>)> {
+        let normal = self.normal.normalize();
+        let t = plane_hit(&self.center, &normal, ray)?;
+        let offset = ray.at_point(t) - self.center;
+        let distance = offset.norm();
+        if distance > self.radius {
+            return None;
+        }
+
+        let (tangent, bitangent) = math::onb_from_normal(&normal);
+        let angle = offset.dot(&bitangent).atan2(offset.dot(&tangent));
+        Some((
+            t,
+            Vector2::new(0.5 + angle / (2.0 * PI), distance / self.radius),
+        ))
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        let normal = self.normal.normalize();
+        let extent = normal.map(|n| self.radius * (1.0 - n * n).max(0.0).sqrt() + BOUNDS_PADDING);
+        Aabb::new(self.center - extent, self.center + extent)
+    }
+}
+
+impl Quad {
+    fn normal(&self) -> Vector3<f32> {
+        self.edge_u.cross(&self.edge_v).normalize()
+    }
+
+    /// Distance along the ray and how far across each edge the hit is, both in 0..1
+    fn hit(&self, ray: &Ray) -> Option<(f32, Vector2<f32>)> {
+        let n = self.edge_u.cross(&self.edge_v);
+        let t = plane_hit(&self.corner, &n.normalize(), ray)?;
+
+        // solve offset = u * edge_u + v * edge_v, which works for any parallelogram
+        let offset = ray.at_point(t) - self.corner;
+        let w = n / n.norm_squared();
+        let u = w.dot(&offset.cross(&self.edge_v));
+        let v = w.dot(&self.edge_u.cross(&offset));
+        if !(0.0..=1.0).contains(&u) || !(0.0..=1.0).contains(&v) {
+            return None;
+        }
+        Some((t, Vector2::new(u, v)))
+    }
+
+    pub fn bounds(&self) -> Aabb {
+        let corners = [
+            self.corner,
+            self.corner + self.edge_u,
+            self.corner + self.edge_v,
+            self.corner + self.edge_u + self.edge_v,
+        ];
+        let padding = Vector3::repeat(BOUNDS_PADDING);
+        let min = corners.iter().fold(corners[0], |acc, c| acc.inf(c));
+        let max = corners.iter().fold(corners[0], |acc, c| acc.sup(c));
+        Aabb::new(min - padding, max + padding)
+    }
+}
+
+fn flat_intersection(
+    ray: &Ray,
+    t: f32,
+    normal: Vector3<f32>,
+    uv: Vector2<f32>,
+    material: &Material,
+) -> Intersection {
+    let normal_ray = Ray::new(ray.at_point(t), normal);
+    let mut intersection = Intersection::new(
+        material.colour_at(&normal_ray.origin, Some(uv)),
+        Some(t),
+        Some(normal_ray),
+    )
+    .with_material(material.clone());
+    intersection.uv = Some(uv);
+    intersection
+}
+
+impl Intersect for Disk {
+    fn test_intersection(&self, ray: &Ray) -> Intersection {
+        let Some((t, uv)) = self.hit(ray) else {
+            return Intersection::new(Colour::from_gray(0.0), None, None);
+        };
+        flat_intersection(ray, t, self.normal.normalize(), uv, &self.material)
+    }
+}
+
+impl Intersect for Quad {
+    fn test_intersection(&self, ray: &Ray) -> Intersection {
+        let Some((t, uv)) = self.hit(ray) else {
+            return Intersection::new(Colour::from_gray(0.0), None, None);
+        };
+        flat_intersection(ray, t, self.normal(), uv, &self.material)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn white() -> Material {
+        Material::matte(Colour::from_gray(1.0))
+    }
+
+    fn down_at(x: f32, y: f32) -> Ray {
+        Ray::new(Vector3::new(x, y, 1.0), -Vector3::z())
+    }
+
+    #[test]
+    fn disk_hits_just_inside_its_radius_and_misses_just_outside() {
+        let disk = Disk {
+            center: Vector3::zeros(),
+            normal: Vector3::z(),
+            radius: 2.0,
+            material: white(),
+        };
+        assert_eq!(
+            disk.test_intersection(&down_at(1.99, 0.0)).distance,
+            Some(1.0)
+        );
+        assert_eq!(disk.test_intersection(&down_at(2.01, 0.0)).distance, None);
+        assert_eq!(disk.test_intersection(&down_at(0.0, -2.01)).distance, None);
+    }
+
+    #[test]
+    fn quad_hits_just_inside_its_edges_and_misses_just_outside() {
+        let quad = Quad {
+            corner: Vector3::zeros(),
+            edge_u: Vector3::new(2.0, 0.0, 0.0),
+            edge_v: Vector3::new(0.0, 1.0, 0.0),
+            material: white(),
+        };
+        let hit = quad.test_intersection(&down_at(1.99, 0.5));
+        assert_eq!(hit.distance, Some(1.0));
+        let uv = hit.uv.unwrap();
+        assert!((uv - Vector2::new(0.995, 0.5)).norm() < 1e-5, "{:?}", uv);
+        assert_eq!(quad.test_intersection(&down_at(2.01, 0.5)).distance, None);
+        assert_eq!(quad.test_intersection(&down_at(1.0, -0.01)).distance, None);
+    }
+}
@@ -0,0 +1,324 @@
+use std::{
+    io::Write,
+    path::{Path, PathBuf},
+    str::FromStr,
+    sync::atomic::{AtomicU32, Ordering},
+};
+
+use cgraphics::{
+    camera::{AdaptiveSampling, Camera, CameraSettings},
+    colour::Colour,
+    denoise::{self, DenoiseSettings, Guides},
+    output,
+    renderer::{RenderConfig, RenderMode},
+    scene::Scene,
+};
+
+pub const USAGE: &str = "usage: cgraphics [--headless [--scene scene.json | --random-spheres n \
+[--seed 0]] [--out out.png|out.exr] [--width 800] [--height 500] [--samples 1] \
+[--max-samples n [--variance-threshold 0.0001]] [--max-depth 8] \
+[--mode shaded|normals|albedo|depth|path|wireframe|toon [--toon-bands 3] [--no-outline]] [--aovs] [--denoise 0 [--denoise-colour 0.5]] \
+[--dither] [--ao-samples 0] [--shadow-samples 1] [--aperture 0] [--focus 10] [--near 0.0001] [--far 1000000] \
+[--crop x0,y0,x1,y1] [--progress]]";
+
+/// Settings for rendering a single frame straight to disk without opening a window
+pub struct HeadlessOptions {
+    pub scene: Option<PathBuf>, // falls back to `Scene::pondering_orbs` when not given
+    pub random_spheres: Option<usize>, // render `Scene::random_spheres` instead, for benchmarking
+    pub seed: u64, // for the random spheres and for every random choice while rendering
+    pub out: PathBuf, // a PNG, or a float OpenEXR of the linear values if it ends in .exr
+    pub width: Option<u32>, // falls back to the scene file's camera, then 800 by 500
+    pub height: Option<u32>,
+    pub samples_per_pixel: u32,
+    pub max_samples: Option<u32>, // samples adaptively between `samples_per_pixel` and this
+    pub variance_threshold: f32,
+    pub max_depth: u32,
+    pub mode: RenderMode,
+    pub toon_bands: u32,
+    pub toon_outline: bool,
+    pub aovs: bool, // also save the normal and albedo passes, next to `out`
+    pub denoise: DenoiseSettings, // no iterations unless asked for, so off by default
+    pub dither: bool,
+    pub ao_samples: u32,
+    pub shadow_samples: u32,
+    pub aperture: Option<f32>, // like the resolution, given here or in the scene file
+    pub focus_distance: Option<f32>,
+    pub near: Option<f32>, // clip planes, also from the scene file if not given
+    pub far: Option<f32>,
+    pub crop: Option<(u32, u32, u32, u32)>, // only this part is rendered and saved
+    pub progress: bool,                     // print how far the render has got to stderr as it goes
+}
+
+impl Default for HeadlessOptions {
+    fn default() -> Self {
+        HeadlessOptions {
+            scene: None,
+            random_spheres: None,
+            seed: 0,
+            out: PathBuf::from("out.png"),
+            width: None,
+            height: None,
+            samples_per_pixel: 1,
+            max_samples: None,
+            variance_threshold: 1e-4,
+            max_depth: 8,
+            mode: RenderMode::Shaded,
+            toon_bands: 3,
+            toon_outline: true,
+            aovs: false,
+            denoise: DenoiseSettings {
+                iterations: 0,
+                ..DenoiseSettings::default()
+            },
+            dither: false,
+            ao_samples: 0,
+            shadow_samples: 1,
+            aperture: None,
+            focus_distance: None,
+            near: None,
+            far: None,
+            crop: None,
+            progress: false,
+        }
+    }
+}
+
+/// Reads the command line, returning `None` when `--headless` is absent so the
+/// interactive app should run instead
+pub fn parse_args<I: Iterator<Item = String>>(args: I) -> Result<Option<HeadlessOptions>, String> {
+    let mut headless = false;
+    let mut options = HeadlessOptions::default();
+    let mut args = args;
+
+    while let Some(arg) = args.next() {
+        if arg == "--headless" {
+            headless = true;
+            continue;
+        }
+        if arg == "--aovs" {
+            options.aovs = true;
+            continue;
+        }
+        if arg == "--no-outline" {
+            options.toon_outline = false;
+            continue;
+        }
+        if arg == "--dither" {
+            options.dither = true;
+            continue;
+        }
+        if arg == "--progress" {
+            options.progress = true;
+            continue;
+        }
+
+        let mut value = || {
+            args.next()
+                .ok_or_else(|| format!("missing value for {}", arg))
+        };
+        match arg.as_str() {
+            "--scene" => options.scene = Some(PathBuf::from(value()?)),
+            "--random-spheres" => options.random_spheres = Some(parse(&arg, &value()?)?),
+            "--seed" => options.seed = parse(&arg, &value()?)?,
+            "--out" => options.out = PathBuf::from(value()?),
+            "--width" => options.width = Some(positive(&arg, &value()?)?),
+            "--height" => options.height = Some(positive(&arg, &value()?)?),
+            "--samples" => options.samples_per_pixel = positive(&arg, &value()?)?,
+            "--max-samples" => options.max_samples = Some(positive(&arg, &value()?)?),
+            "--variance-threshold" => options.variance_threshold = parse(&arg, &value()?)?,
+            "--max-depth" => options.max_depth = parse(&arg, &value()?)?,
+            "--mode" => {
+                options.mode = match value()?.as_str() {
+                    "shaded" => RenderMode::Shaded,
+                    "normals" => RenderMode::Normals,
+                    "albedo" => RenderMode::Albedo,
+                    "depth" => RenderMode::Depth,
+                    "path" => RenderMode::PathTraced,
+                    "wireframe" => RenderMode::Wireframe,
+                    "toon" => RenderMode::Toon,
+                    other => return Err(format!("unknown render mode {}", other)),
+                }
+            }
+            "--denoise" => options.denoise.iterations = parse(&arg, &value()?)?,
+            "--denoise-colour" => options.denoise.colour_sigma = parse(&arg, &value()?)?,
+            "--toon-bands" => options.toon_bands = positive(&arg, &value()?)?,
+            "--ao-samples" => options.ao_samples = parse(&arg, &value()?)?,
+            "--shadow-samples" => options.shadow_samples = positive(&arg, &value()?)?,
+            "--aperture" => options.aperture = Some(parse(&arg, &value()?)?),
+            "--focus" => options.focus_distance = Some(parse(&arg, &value()?)?),
+            "--near" => options.near = Some(parse(&arg, &value()?)?),
+            "--far" => options.far = Some(parse(&arg, &value()?)?),
+            "--crop" => options.crop = Some(parse_crop(&arg, &value()?)?),
+            _ => return Err(format!("unrecognised argument {}", arg)),
+        }
+    }
+
+    if !headless {
+        return Ok(None);
+    }
+    if options.scene.is_some() && options.random_spheres.is_some() {
+        return Err("--scene and --random-spheres can't be used together".to_string());
+    }
+    return Ok(Some(options));
+}
+
+fn parse<T: FromStr>(flag: &str, value: &str) -> Result<T, String> {
+    value
+        .parse()
+        .map_err(|_| format!("invalid value for {}: {}", flag, value))
+}
+
+fn positive(flag: &str, value: &str) -> Result<u32, String> {
+    match value.parse::<u32>() {
+        Ok(n) if n > 0 => Ok(n),
+        _ => Err(format!(
+            "{} must be a positive integer, got {}",
+            flag, value
+        )),
+    }
+}
+
+/// Four comma separated pixel coordinates, the left, top, right and bottom edges
+fn parse_crop(flag: &str, value: &str) -> Result<(u32, u32, u32, u32), String> {
+    let edges = value
+        .split(',')
+        .map(|edge| parse(flag, edge.trim()))
+        .collect::<Result<Vec<u32>, _>>()?;
+    match edges[..] {
+        [x0, y0, x1, y1] if x0 < x1 && y0 < y1 => Ok((x0, y0, x1, y1)),
+        _ => Err(format!(
+            "{} must be x0,y0,x1,y1 with x0 < x1 and y0 < y1, got {}",
+            flag, value
+        )),
+    }
+}
+
+/// The part of `buffer` inside `crop`, which has to fit within it
+fn cut(buffer: Vec<Vec<Colour>>, crop: Option<(u32, u32, u32, u32)>) -> Vec<Vec<Colour>> {
+    let Some((x0, y0, x1, y1)) = crop else {
+        return buffer;
+    };
+    buffer[y0 as usize..y1 as usize]
+        .iter()
+        .map(|row| row[x0 as usize..x1 as usize].to_vec())
+        .collect()
+}
+
+/// Renders one frame and writes it as a PNG. The camera is the one the scene file
+/// describes, or the one the app starts with, with the command line having the last word
+pub fn run(options: &HeadlessOptions) -> Result<(), String> {
+    let (scene, mut camera) = match (&options.scene, options.random_spheres) {
+        (Some(path), _) => Scene::load_shot(path)?,
+        (None, Some(n)) => (
+            Scene::random_spheres(n, options.seed),
+            Camera::default_view(800, 500),
+        ),
+        (None, None) => (Scene::pondering_orbs(), Camera::default_view(800, 500)),
+    };
+    let overrides = CameraSettings {
+        width: options.width,
+        height: options.height,
+        aperture: options.aperture,
+        focus_distance: options.focus_distance,
+        near: options.near,
+        far: options.far,
+        ..CameraSettings::default()
+    };
+    overrides.apply(&mut camera);
+    let camera = camera
+        .with_samples(options.samples_per_pixel)
+        .with_adaptive_sampling(options.max_samples.map(|max_samples| AdaptiveSampling {
+            min_samples: options.samples_per_pixel,
+            max_samples,
+            variance_threshold: options.variance_threshold,
+        }));
+
+    if let Some((x0, y0, x1, y1)) = options.crop {
+        if x1 > camera.width || y1 > camera.height {
+            return Err(format!(
+                "crop {},{},{},{} doesn't fit in the {}x{} image",
+                x0, y0, x1, y1, camera.width, camera.height
+            ));
+        }
+    }
+
+    let config = RenderConfig {
+        max_depth: options.max_depth,
+        mode: options.mode,
+        ao_samples: options.ao_samples,
+        shadow_samples: options.shadow_samples,
+        toon_bands: options.toon_bands,
+        toon_outline: options.toon_outline,
+        seed: options.seed,
+        crop: options.crop,
+        ..RenderConfig::default()
+    };
+
+    log::info!(
+        "rendering {}x{} at {} samples per pixel",
+        camera.width,
+        camera.height,
+        options.samples_per_pixel
+    );
+    // only printed when the whole percentage goes up, not for every tile
+    let shown = AtomicU32::new(0);
+    let progress = |fraction: f32| {
+        let percent = (fraction * 100.0) as u32;
+        if shown.fetch_max(percent, Ordering::Relaxed) < percent {
+            eprint!("\rrendered {:>3}%", percent);
+            let _ = std::io::stderr().flush();
+        }
+    };
+    let rendered = cgraphics::render(
+        &scene,
+        &camera,
+        &config,
+        options.progress.then_some(&progress as _),
+    );
+    if options.progress {
+        eprintln!();
+    }
+    // cut down before denoising, so the black around the crop doesn't bleed into it
+    let mut buffer = cut(rendered, options.crop);
+    output::sanitize(&mut buffer);
+    let guides = (options.aovs || options.denoise.iterations > 0).then(|| {
+        let guides = Guides::render(&camera, &scene, &config);
+        Guides {
+            normals: cut(guides.normals, options.crop),
+            albedo: cut(guides.albedo, options.crop),
+        }
+    });
+    if let Some(guides) = guides.as_ref().filter(|_| options.denoise.iterations > 0) {
+        buffer = denoise::denoise(&buffer, guides, &options.denoise);
+    }
+    let encoding = output::Encoding {
+        dither: options.dither,
+        ..output::Encoding::default()
+    };
+    output::save_image(&buffer, encoding, &options.out)?;
+    log::info!("saved render to {}", options.out.display());
+
+    if let Some(guides) = guides.filter(|_| options.aovs) {
+        // the normals are data rather than a picture, so they're written out untouched
+        let raw = output::Encoding {
+            gamma_correct: false,
+            ..output::Encoding::default()
+        };
+        for (name, pass, encoding) in [
+            ("normal", &guides.normals, raw),
+            ("albedo", &guides.albedo, output::Encoding::default()),
+        ] {
+            let path = aov_path(&options.out, name);
+            output::save_image(pass, encoding, &path)?;
+            log::info!("saved the {} pass to {}", name, path.display());
+        }
+    }
+    return Ok(());
+}
+
+/// `out` with the pass name before the extension, so out.png gets out.normal.png
+fn aov_path(out: &Path, name: &str) -> PathBuf {
+    let stem = out.file_stem().unwrap_or_default().to_string_lossy();
+    let extension = out.extension().unwrap_or("png".as_ref()).to_string_lossy();
+    out.with_file_name(format!("{}.{}.{}", stem, name, extension))
+}
@@ -0,0 +1,64 @@
+use nalgebra::Vector3;
+
+/// Velocity of the flying camera, in its own forward, right and up axes. Held movement
+/// keys speed it up towards `max_speed` and letting go slows it back to a stop, both
+/// at `acceleration`, rather than starting and stopping dead
+pub struct Inertia {
+    pub velocity: Vector3<f32>,
+    pub max_speed: f32,    // units per second
+    pub acceleration: f32, // units per second squared
+}
+
+impl Inertia {
+    pub fn new(max_speed: f32, acceleration: f32) -> Inertia {
+        Inertia {
+            velocity: Vector3::zeros(),
+            max_speed,
+            acceleration,
+        }
+    }
+
+    /// Moves the velocity towards full speed along `input`, or towards rest when it's
+    /// zero, by no more than `delta_time` allows. Returns how far the camera travels
+    /// this frame in its own axes
+    pub fn update(&mut self, input: Vector3<f32>, delta_time: f32) -> Vector3<f32> {
+        let target = input.try_normalize(f32::EPSILON).unwrap_or_default() * self.max_speed;
+        let change = target - self.velocity;
+        let max_change = self.acceleration * delta_time;
+        if change.norm() <= max_change {
+            self.velocity = target;
+        } else {
+            self.velocity += change.normalize() * max_change;
+        }
+        self.velocity * delta_time
+    }
+
+    pub fn is_moving(&self) -> bool {
+        self.velocity != Vector3::zeros()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn holding_reaches_full_speed_and_letting_go_stops() {
+        let mut inertia = Inertia::new(4.0, 8.0);
+        let forward = Vector3::x();
+        let frame = 1.0 / 60.0;
+
+        let first_step = inertia.update(forward, frame);
+        assert!(first_step.x > 0.0 && inertia.velocity.x < inertia.max_speed);
+        for _ in 0..60 {
+            inertia.update(forward, frame);
+        }
+        assert_eq!(inertia.velocity, forward * inertia.max_speed);
+
+        // half a second to slow from 4 to 0 at 8 per second squared, so a second is plenty
+        for _ in 0..60 {
+            inertia.update(Vector3::zeros(), frame);
+        }
+        assert!(!inertia.is_moving());
+    }
+}
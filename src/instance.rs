@@ -0,0 +1,164 @@
+use std::sync::{Arc, OnceLock};
+
+use nalgebra::{Similarity3, Translation3, UnitQuaternion, Vector3};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    aabb::Aabb,
+    bvh::Bvh,
+    colour::Colour,
+    intersect::{Intersect, Intersectable, Intersection},
+    renderer::Ray,
+};
+
+/// Objects shared between instances, positioned in their own local space
+#[derive(Serialize, Deserialize)]
+#[serde(transparent)]
+pub struct Mesh {
+    objects: Vec<Intersectable>,
+    #[serde(skip)]
+    bvh: OnceLock<Bvh>, // built on first use, like the scene's
+}
+
+impl Mesh {
+    pub fn new(objects: Vec<Intersectable>) -> Mesh {
+        Mesh {
+            objects,
+            bvh: OnceLock::new(),
+        }
+    }
+
+    fn bvh(&self) -> &Bvh {
+        self.bvh.get_or_init(|| Bvh::build(&self.objects))
+    }
+
+    /// Box around every object, or `None` if the mesh is empty or any object goes on forever
+    pub fn bounds(&self) -> Option<Aabb> {
+        let mut boxes = self.objects.iter().map(|obj| obj.bounds());
+        let first = boxes.next()??;
+        boxes.try_fold(first, |acc, bounds| Some(Aabb::surrounding(&acc, &bounds?)))
+    }
+}
+
+/// A copy of a shared mesh moved, turned and uniformly scaled into place, so many
+/// copies cost one set of triangles. The mesh is scaled, then rotated, then translated
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Instance {
+    pub mesh: Arc<Mesh>,
+    #[serde(default = "Vector3::zeros")]
+    pub translation: Vector3<f32>,
+    #[serde(default = "UnitQuaternion::identity")]
+    pub rotation: UnitQuaternion<f32>,
+    #[serde(default = "default_scale")]
+    pub scale: f32,
+}
+
+fn default_scale() -> f32 {
+    1.0
+}
+
+impl Instance {
+    pub fn new(mesh: Arc<Mesh>) -> Instance {
+        Instance {
+            mesh,
+            translation: Vector3::zeros(),
+            rotation: UnitQuaternion::identity(),
+            scale: default_scale(),
+        }
+    }
+
+    pub fn with_translation(mut self, translation: Vector3<f32>) -> Instance {
+        self.translation = translation;
+        self
+    }
+
+    pub fn with_rotation(mut self, rotation: UnitQuaternion<f32>) -> Instance {
+        self.rotation = rotation;
+        self
+    }
+
+    pub fn with_scale(mut self, scale: f32) -> Instance {
+        self.scale = scale.max(f32::EPSILON);
+        self
+    }
+
+    /// Local to world transform
+    fn transform(&self) -> Similarity3<f32> {
+        Similarity3::from_parts(
+            Translation3::from(self.translation),
+            self.rotation,
+            self.scale,
+        )
+    }
+
+    /// Box around the eight transformed corners of the mesh's box
+    pub fn bounds(&self) -> Option<Aabb> {
+        let local = self.mesh.bounds()?;
+        let transform = self.transform();
+        let corners = (0..8).map(|i| {
+            let corner = Vector3::new(
+                if i & 1 == 0 { local.min.x } else { local.max.x },
+                if i & 2 == 0 { local.min.y } else { local.max.y },
+                if i & 4 == 0 { local.min.z } else { local.max.z },
+            );
+            transform.transform_point(&corner.into()).coords
+        });
+        let first = Vector3::repeat(f32::INFINITY);
+        let (min, max) = corners.fold((first, -first), |(min, max), corner| {
+            (min.inf(&corner), max.sup(&corner))
+        });
+        Some(Aabb::new(min, max))
+    }
+}
+
+impl Intersect for Instance {
+    fn test_intersection(&self, ray: &Ray) -> Intersection {
+        let transform = self.transform();
+        let inverse = transform.inverse();
+
+        // the local ray is normalised again, so local distances come back scaled
+        let local_ray = Ray::new(
+            inverse.transform_point(&ray.origin.into()).coords,
+            inverse.transform_vector(&ray.direction),
+        )
+        .with_time(ray.time);
+        let mut intersection = self.mesh.bvh().nearest(&self.mesh.objects, &local_ray);
+        let (Some(distance), Some(normal)) = (intersection.distance, intersection.normal) else {
+            return Intersection::new(Colour::from_gray(0.0), None, None);
+        };
+
+        intersection.distance = Some(distance * self.scale);
+        intersection.normal = Some(Ray::new(
+            transform.transform_point(&normal.origin.into()).coords,
+            self.rotation * normal.direction, // uniform scaling leaves normals alone
+        ));
+        intersection
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{material::Material, sphere::Sphere};
+
+    #[test]
+    fn translated_instance_hits_where_the_original_does_plus_the_offset() {
+        let ball = Intersectable::Sphere(Sphere {
+            origin: Vector3::zeros(),
+            radius: 1.0,
+            velocity: None,
+            material: Material::matte(Colour::from_gray(1.0)),
+        });
+        let mesh = Arc::new(Mesh::new(vec![ball]));
+        let offset = Vector3::new(0.0, 3.0, -2.0);
+        let moved = Instance::new(mesh.clone()).with_translation(offset);
+        let ray = Ray::new(Vector3::new(-5.0, 0.0, 0.0), Vector3::x());
+
+        let original = Instance::new(mesh).test_intersection(&ray).normal.unwrap();
+        let shifted = Ray::new(ray.origin + offset, ray.direction);
+        let hit = moved.test_intersection(&shifted).normal.unwrap();
+        assert!((hit.origin - (original.origin + offset)).norm() < 1e-5);
+        assert!((hit.direction - original.direction).norm() < 1e-5);
+    }
+}
@@ -1,28 +1,72 @@
 use std::cmp::Ordering;
 
-use eframe::egui::Rgba;
+use serde::{Deserialize, Serialize};
 
-use crate::{light, renderer::Ray, sphere};
+use crate::{
+    aabb::{self, Aabb},
+    colour::Colour,
+    csg::Csg,
+    cylinder, flat,
+    instance::Instance,
+    material::Material,
+    plane,
+    renderer::Ray,
+    sphere, torus, triangle,
+};
+
+const BOUNDING_PADDING: f32 = 1.001;
+
+#[cfg(test)]
+thread_local! {
+    // how many times this thread has run an exact test, to check the precheck skips them
+    static EXACT_TESTS: std::cell::Cell<u32> = const { std::cell::Cell::new(0) };
+}
 
 pub trait Intersect {
+    /// Nearest hit in front of the ray's origin
     fn test_intersection(&self, ray: &Ray) -> Intersection;
+
+    /// Nearest hit with a distance strictly between `t_min` and `t_max`, so a shadow ray
+    /// can ignore the surface it starts on and anything past its light. Shapes a ray
+    /// can cross more than once override this to fall back to a further crossing when
+    /// the nearest is out of range
+    fn intersect_within(&self, ray: &Ray, t_min: f32, t_max: f32) -> Intersection {
+        let intersection = self.test_intersection(ray);
+        match intersection.distance {
+            Some(t) if t > t_min && t < t_max => intersection,
+            _ => Intersection::new(Colour::from_gray(0.0), None, None),
+        }
+    }
 }
 
-#[derive(Clone, Copy, Debug)]
+#[derive(Clone, Debug)]
 pub struct Intersection {
-    pub colour: Rgba,
+    pub colour: Colour,
     pub distance: Option<f32>,
     pub normal: Option<Ray>,
+    pub material: Material,
+    pub barycentric: Option<nalgebra::Vector3<f32>>, // weights of each vertex, for triangles
+    pub uv: Option<nalgebra::Vector2<f32>>, // texture coordinates, for primitives that have them
+    pub front_face: bool, // whether the ray came from the side the normal points out of, not from inside
 }
 
 impl Intersection {
-    pub fn new(colour: Rgba, distance: Option<f32>, normal: Option<Ray>) -> Intersection {
+    pub fn new(colour: Colour, distance: Option<f32>, normal: Option<Ray>) -> Intersection {
         Intersection {
             colour,
             distance,
             normal,
+            material: Material::matte(colour),
+            barycentric: None,
+            uv: None,
+            front_face: true,
         }
     }
+
+    pub fn with_material(mut self, material: Material) -> Intersection {
+        self.material = material;
+        self
+    }
 }
 
 impl PartialEq for Intersection {
@@ -39,40 +83,262 @@ impl Eq for Intersection {}
 
 impl PartialOrd for Intersection {
     fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for Intersection {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
         match (self.distance, other.distance) {
             (Some(self_dist), Some(other_dist)) => {
                 if self_dist == other_dist {
-                    return Some(Ordering::Equal);
+                    return Ordering::Equal;
                 } else if self_dist < other_dist {
-                    return Some(Ordering::Less);
+                    return Ordering::Less;
                 } else {
-                    return Some(Ordering::Greater);
+                    return Ordering::Greater;
                 }
             }
-            (Some(_), _) => return Some(Ordering::Less),
-            (_, Some(_)) => return Some(Ordering::Greater),
-            (_, _) => return Some(Ordering::Equal),
+            (Some(_), _) => return Ordering::Less,
+            (_, Some(_)) => return Ordering::Greater,
+            (_, _) => return Ordering::Equal,
         }
     }
 }
 
-impl Ord for Intersection {
-    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
-        self.partial_cmp(other).unwrap_or(Ordering::Equal)
-    }
-}
-
-#[derive(Clone, Copy)]
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case")]
 pub enum Intersectable {
     Sphere(sphere::Sphere),
-    PointLight(light::PointLight),
+    Plane(plane::Plane),
+    Triangle(triangle::Triangle),
+    Cuboid(aabb::Cuboid),
+    Cylinder(cylinder::Cylinder),
+    Disk(flat::Disk),
+    Quad(flat::Quad),
+    Torus(torus::Torus),
+    Instance(Instance),
+    Csg(Csg),
 }
 
 impl Intersect for Intersectable {
     fn test_intersection(&self, ray: &Ray) -> Intersection {
+        self.intersect_within(ray, 0.0, f32::INFINITY)
+    }
+
+    fn intersect_within(&self, ray: &Ray, t_min: f32, t_max: f32) -> Intersection {
+        // only where the exact test costs more than the sphere test and the sphere is cheap
+        // to find, spheres being their own bounding sphere and instances' needing the mesh
+        let precheck = matches!(
+            self,
+            Intersectable::Triangle(_)
+                | Intersectable::Cylinder(_)
+                | Intersectable::Disk(_)
+                | Intersectable::Quad(_)
+                | Intersectable::Torus(_)
+                | Intersectable::Csg(_)
+        );
+        if precheck
+            && self
+                .bounding_sphere()
+                .is_some_and(|bounds| !bounds.may_hit(ray))
+        {
+            return Intersection::new(Colour::from_gray(0.0), None, None);
+        }
+        self.exact_intersect_within(ray, t_min, t_max)
+    }
+}
+
+impl Intersectable {
+    /// The shape's own test, without checking the bounding sphere first
+    fn exact_intersect_within(&self, ray: &Ray, t_min: f32, t_max: f32) -> Intersection {
+        #[cfg(test)]
+        EXACT_TESTS.with(|count| count.set(count.get() + 1));
+
+        let mut intersection = match self {
+            Intersectable::Sphere(s) => return s.intersect_within(ray, t_min, t_max),
+            Intersectable::Instance(i) => return i.intersect_within(ray, t_min, t_max),
+            Intersectable::Csg(c) => return c.intersect_within(ray, t_min, t_max),
+            Intersectable::Plane(p) => p.intersect_within(ray, t_min, t_max),
+            Intersectable::Triangle(t) => t.intersect_within(ray, t_min, t_max),
+            Intersectable::Cuboid(c) => c.intersect_within(ray, t_min, t_max),
+            Intersectable::Cylinder(c) => c.intersect_within(ray, t_min, t_max),
+            Intersectable::Disk(d) => d.intersect_within(ray, t_min, t_max),
+            Intersectable::Quad(q) => q.intersect_within(ray, t_min, t_max),
+            Intersectable::Torus(t) => t.intersect_within(ray, t_min, t_max),
+        };
+        // spheres know which crossing they hit and the composites pass on what their parts
+        // found, the rest go by which way the normal faces the ray
+        if let Some(normal) = &intersection.normal {
+            intersection.front_face = ray.direction.dot(&normal.direction) < 0.0;
+        }
+        intersection
+    }
+
+    /// What sort of primitive this is, as written in scene files
+    pub fn kind(&self) -> &'static str {
         match self {
-            Intersectable::Sphere(s) => s.test_intersection(ray),
-            Intersectable::PointLight(l) => l.test_intersection(ray),
+            Intersectable::Sphere(_) => "sphere",
+            Intersectable::Plane(_) => "plane",
+            Intersectable::Triangle(_) => "triangle",
+            Intersectable::Cuboid(_) => "cuboid",
+            Intersectable::Cylinder(_) => "cylinder",
+            Intersectable::Disk(_) => "disk",
+            Intersectable::Quad(_) => "quad",
+            Intersectable::Torus(_) => "torus",
+            Intersectable::Instance(_) => "instance",
+            Intersectable::Csg(_) => "csg",
         }
     }
+
+    /// Sphere enclosing the whole object, or `None` if it goes on forever. Anything but a
+    /// sphere gets the one around its `bounds`
+    pub fn bounding_sphere(&self) -> Option<BoundingSphere> {
+        if let Intersectable::Sphere(s) = self {
+            // a moving sphere is swept out to cover everywhere it goes in the exposure
+            let travel = s.velocity.map_or(0.0, |velocity| velocity.norm());
+            return Some(BoundingSphere {
+                center: s.centre_at(0.5),
+                radius: s.radius + travel / 2.0,
+            });
+        }
+        let bounds = self.bounds()?;
+        Some(BoundingSphere {
+            center: bounds.centroid(),
+            radius: (bounds.max - bounds.min).norm() / 2.0,
+        })
+    }
+
+    /// Box enclosing the whole object, or `None` if it goes on forever
+    pub fn bounds(&self) -> Option<Aabb> {
+        match self {
+            Intersectable::Sphere(s) => {
+                let extent = nalgebra::Vector3::repeat(s.radius);
+                let (start, end) = (s.centre_at(0.0), s.centre_at(1.0));
+                Some(Aabb::surrounding(
+                    &Aabb::new(start - extent, start + extent),
+                    &Aabb::new(end - extent, end + extent),
+                ))
+            }
+            Intersectable::Plane(_) => None,
+            Intersectable::Triangle(t) => {
+                // padded so axis aligned triangles don't get a box with no thickness
+                let padding = nalgebra::Vector3::repeat(1e-4);
+                Some(Aabb::new(
+                    t.v0.inf(&t.v1).inf(&t.v2) - padding,
+                    t.v0.sup(&t.v1).sup(&t.v2) + padding,
+                ))
+            }
+            Intersectable::Cuboid(c) => Some(c.bounds),
+            Intersectable::Cylinder(c) => Some(c.bounds()),
+            Intersectable::Disk(d) => Some(d.bounds()),
+            Intersectable::Quad(q) => Some(q.bounds()),
+            Intersectable::Torus(t) => Some(t.bounds()),
+            Intersectable::Instance(i) => i.bounds(),
+            Intersectable::Csg(c) => c.bounds(),
+        }
+    }
+}
+
+/// A cheap and conservative stand in for an object's shape, to rule out rays that
+/// can't possibly hit it before doing the exact test
+#[derive(Clone, Copy, Debug)]
+pub struct BoundingSphere {
+    pub center: nalgebra::Vector3<f32>,
+    pub radius: f32,
+}
+
+impl BoundingSphere {
+    /// False only if the ray is sure to miss. Padded a little so rounding never turns
+    /// a grazing hit into a miss
+    pub fn may_hit(&self, ray: &Ray) -> bool {
+        let radius_squared = (self.radius * BOUNDING_PADDING).powi(2);
+        let to_center = self.center - ray.origin;
+        let along = to_center.dot(&ray.direction); // direction needn't be normalised
+        let distance_squared = to_center.norm_squared();
+        if along < 0.0 && distance_squared > radius_squared {
+            return false; // heading away from a sphere it starts outside of
+        }
+        distance_squared - along.powi(2) / ray.direction.norm_squared() <= radius_squared
+    }
+}
+
+/// Whether anything blocks the ray between `t_min` and `t_max`. Stops at the first
+/// blocker found rather than looking for the nearest, which is all shadow rays need.
+/// Shadow rays pass the shadow epsilon as `t_min` so they can't hit the surface they
+/// leave. Tests every object, the scene goes through its `Bvh` instead
+pub fn occluded(objects: &[Intersectable], ray: &Ray, t_min: f32, t_max: f32) -> bool {
+    objects
+        .iter()
+        .any(|obj| obj.intersect_within(ray, t_min, t_max).distance.is_some())
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+
+    use super::*;
+
+    fn unit_sphere() -> BoundingSphere {
+        BoundingSphere {
+            center: Vector3::zeros(),
+            radius: 1.0,
+        }
+    }
+
+    #[test]
+    fn bounding_sphere_only_rules_out_certain_misses() {
+        let bounds = unit_sphere();
+        let ray = |origin: [f32; 3], direction: [f32; 3]| {
+            Ray::new(Vector3::from(origin), Vector3::from(direction))
+        };
+        assert!(bounds.may_hit(&ray([-5.0, 0.0, 0.0], [1.0, 0.0, 0.0])));
+        assert!(
+            bounds.may_hit(&ray([-5.0, 0.0, 1.0], [1.0, 0.0, 0.0])),
+            "grazing"
+        );
+        assert!(
+            bounds.may_hit(&ray([0.0, 0.0, 0.0], [0.0, 1.0, 0.0])),
+            "inside"
+        );
+        assert!(
+            !bounds.may_hit(&ray([-5.0, 0.0, 2.0], [1.0, 0.0, 0.0])),
+            "passing by"
+        );
+        assert!(
+            !bounds.may_hit(&ray([-5.0, 0.0, 0.0], [-1.0, 0.0, 0.0])),
+            "away"
+        );
+    }
+
+    #[test]
+    fn rays_missing_the_bounds_miss_the_shape() {
+        let triangle = Intersectable::Triangle(triangle::Triangle::new(
+            Vector3::new(0.0, -1.0, -1.0),
+            Vector3::new(0.0, 1.0, -1.0),
+            Vector3::new(0.0, 0.0, 1.0),
+            Material::matte(Colour::from_gray(1.0)),
+        ));
+        let torus = Intersectable::Torus(torus::Torus {
+            major_radius: 2.0,
+            minor_radius: 0.5,
+            material: Material::matte(Colour::from_gray(1.0)),
+        });
+        let exact_tests = || EXACT_TESTS.with(|count| count.get());
+        let past = Ray::new(Vector3::new(-5.0, 0.0, 10.0), Vector3::x());
+        for shape in [&triangle, &torus] {
+            let bounds = shape.bounding_sphere().unwrap();
+            assert!(!bounds.may_hit(&past), "{}", shape.kind());
+            let before = exact_tests();
+            assert_eq!(shape.test_intersection(&past).distance, None);
+            assert_eq!(exact_tests(), before, "{} ran its exact test", shape.kind());
+        }
+
+        // down through the hole, inside the bounding sphere but clear of the tube
+        let through_hole = Ray::new(Vector3::new(0.0, 0.0, 5.0), -Vector3::z());
+        assert!(torus.bounding_sphere().unwrap().may_hit(&through_hole));
+        let before = exact_tests();
+        assert_eq!(torus.test_intersection(&through_hole).distance, None);
+        assert_eq!(exact_tests(), before + 1);
+    }
 }
@@ -0,0 +1,203 @@
+use std::{fs::File, io::BufReader, path::Path};
+
+use cgraphics::camera::Camera;
+use eframe::egui::Key;
+use nalgebra::Vector3;
+use serde::{Deserialize, Serialize};
+
+use crate::orbit::Orbit;
+
+/// Something a held key does to the camera
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Action {
+    Forward,
+    Back,
+    Left,
+    Right,
+    Up,
+    Down,
+    YawLeft,
+    YawRight,
+    PitchUp,
+    PitchDown,
+    RollLeft,
+    RollRight,
+}
+
+impl Action {
+    /// Which way a movement action flies the camera, in its forward, right and up axes.
+    /// Zero for the ones that turn it instead
+    pub fn push(self) -> Vector3<f32> {
+        match self {
+            Action::Forward => Vector3::x(),
+            Action::Back => -Vector3::x(),
+            Action::Left => -Vector3::y(),
+            Action::Right => Vector3::y(),
+            Action::Up => Vector3::z(),
+            Action::Down => -Vector3::z(),
+            _ => Vector3::zeros(),
+        }
+    }
+}
+
+/// Which key does each camera movement. Written in the file by egui's names for the
+/// keys, e.g. `"W"` or `"ArrowLeft"`, and any left out keep their default
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(default, deny_unknown_fields)]
+pub struct KeyBindings {
+    pub forward: Key,
+    pub back: Key,
+    pub left: Key,
+    pub right: Key,
+    pub up: Key,
+    pub down: Key,
+    pub yaw_left: Key,
+    pub yaw_right: Key,
+    pub pitch_up: Key,
+    pub pitch_down: Key,
+    pub roll_left: Key,
+    pub roll_right: Key,
+}
+
+impl Default for KeyBindings {
+    fn default() -> Self {
+        KeyBindings {
+            forward: Key::W,
+            back: Key::S,
+            left: Key::A,
+            right: Key::D,
+            up: Key::Z,
+            down: Key::X,
+            yaw_left: Key::ArrowLeft,
+            yaw_right: Key::ArrowRight,
+            pitch_up: Key::ArrowUp,
+            pitch_down: Key::ArrowDown,
+            roll_left: Key::Q,
+            roll_right: Key::E,
+        }
+    }
+}
+
+impl KeyBindings {
+    /// Every binding, so the keys held down can be checked in one loop
+    pub fn actions(&self) -> [(Key, Action); 12] {
+        [
+            (self.forward, Action::Forward),
+            (self.back, Action::Back),
+            (self.left, Action::Left),
+            (self.right, Action::Right),
+            (self.up, Action::Up),
+            (self.down, Action::Down),
+            (self.yaw_left, Action::YawLeft),
+            (self.yaw_right, Action::YawRight),
+            (self.pitch_up, Action::PitchUp),
+            (self.pitch_down, Action::PitchDown),
+            (self.roll_left, Action::RollLeft),
+            (self.roll_right, Action::RollRight),
+        ]
+    }
+
+    /// The actions whose keys `key_down` says are held
+    pub fn held(&self, key_down: impl Fn(Key) -> bool) -> impl Iterator<Item = Action> {
+        self.actions()
+            .into_iter()
+            .filter(move |&(key, _)| key_down(key))
+            .map(|(_, action)| action)
+    }
+
+    /// Does what the held keys ask for a frame. Turns go straight to the camera, or the
+    /// orbit while there is one, and the moves come back summed in the camera's forward,
+    /// right and up axes for the inertia to carry out. Also says whether anything was held
+    pub fn steer(
+        &self,
+        key_down: impl Fn(Key) -> bool,
+        camera: &mut Camera,
+        orbit: &mut Option<Orbit>,
+    ) -> (Vector3<f32>, bool) {
+        let mut movement = Vector3::zeros();
+        let mut steered = false;
+        for action in self.held(key_down) {
+            match (action, &mut *orbit) {
+                // orbiting, turning swings round the target and forward and back move
+                // in and out. Moving sideways or up would take the camera off it
+                (Action::YawLeft, Some(orbit)) => orbit.rotate(-0.01, 0.0),
+                (Action::YawRight, Some(orbit)) => orbit.rotate(0.01, 0.0),
+                (Action::PitchUp, Some(orbit)) => orbit.rotate(0.0, 0.01),
+                (Action::PitchDown, Some(orbit)) => orbit.rotate(0.0, -0.01),
+                (Action::Forward, Some(orbit)) => orbit.zoom(0.98),
+                (Action::Back, Some(orbit)) => orbit.zoom(1.0 / 0.98),
+                (Action::Left | Action::Right | Action::Up | Action::Down, Some(_)) => continue,
+                (
+                    Action::Forward
+                    | Action::Back
+                    | Action::Left
+                    | Action::Right
+                    | Action::Up
+                    | Action::Down,
+                    None,
+                ) => movement += action.push(),
+                (Action::YawLeft, None) => camera.rotate(-0.01, 0.0),
+                (Action::YawRight, None) => camera.rotate(0.01, 0.0),
+                (Action::PitchUp, None) => camera.rotate(0.0, 0.01),
+                (Action::PitchDown, None) => camera.rotate(0.0, -0.01),
+                (Action::RollLeft, _) => camera.roll(0.01),
+                (Action::RollRight, _) => camera.roll(-0.01),
+            }
+            steered = true;
+        }
+        (movement, steered)
+    }
+}
+
+/// Reads the bindings, a missing file meaning the defaults
+pub fn load(path: &Path) -> Result<KeyBindings, String> {
+    if !path.exists() {
+        return Ok(KeyBindings::default());
+    }
+    let file = File::open(path).map_err(|e| format!("failed to open {}: {}", path.display(), e))?;
+    serde_json::from_reader(BufReader::new(file))
+        .map_err(|e| format!("failed to parse {}: {}", path.display(), e))
+}
+
+#[cfg(test)]
+mod tests {
+    use cgraphics::camera::Camera;
+
+    use super::*;
+
+    #[test]
+    fn remapped_forward_key_flies_the_camera_forward() {
+        let bindings = KeyBindings {
+            forward: Key::I,
+            ..KeyBindings::default()
+        };
+        let mut camera = Camera::default_view(16, 16);
+        let start = camera.location;
+
+        let (push, steered) = bindings.steer(|key| key == Key::W, &mut camera, &mut None);
+        assert_eq!((push, steered), (Vector3::zeros(), false));
+
+        let (push, steered) = bindings.steer(|key| key == Key::I, &mut camera, &mut None);
+        assert!(steered);
+        assert_eq!(push, Vector3::x());
+        assert_eq!(camera.location.direction, start.direction);
+        camera.translate(push.x, push.y, push.z);
+        assert_eq!(camera.location.origin - start.origin, start.direction);
+    }
+
+    #[test]
+    fn remapped_forward_key_zooms_the_orbit_in() {
+        let bindings = KeyBindings {
+            forward: Key::I,
+            ..KeyBindings::default()
+        };
+        let mut camera = Camera::default_view(16, 16);
+        let mut orbit = Some(Orbit::around(&camera, Vector3::zeros()));
+
+        let (push, steered) = bindings.steer(|key| key == Key::I, &mut camera, &mut orbit);
+        assert!(steered);
+        assert_eq!(push, Vector3::zeros());
+        orbit.unwrap().apply(&mut camera);
+        assert!((camera.location.origin.norm() - 10.0 * 0.98).abs() < 1e-4);
+    }
+}
@@ -0,0 +1,55 @@
+//! A ray tracer and path tracer for scenes of spheres, planes, meshes and the like, lit by
+//! point, spot, area and directional lights. The `cgraphics` binary wraps it in a window
+//! to fly around in, or renders single frames with `--headless`
+#![allow(clippy::needless_return)]
+
+use camera::{Camera, Progress};
+use renderer::RenderConfig;
+use scene::Scene;
+
+use crate::colour::Colour;
+
+pub mod aabb;
+pub mod accumulator;
+pub mod background;
+pub mod bvh;
+pub mod camera;
+pub mod colour;
+pub mod csg;
+pub mod cylinder;
+pub mod denoise;
+pub mod environment;
+pub mod flat;
+pub mod instance;
+pub mod intersect;
+pub mod light;
+pub mod material;
+pub mod math;
+pub mod obj;
+pub mod output;
+pub mod path_tracer;
+pub mod plane;
+pub mod renderer;
+pub mod reproject;
+pub mod rng;
+pub mod scene;
+pub mod sphere;
+pub mod texture;
+pub mod tile;
+pub mod timing;
+pub mod tonemap;
+pub mod torus;
+pub mod triangle;
+
+/// Renders the whole of `camera`'s view of `scene` with `config`'s settings, spread over
+/// rayon's thread pool. Rows run top to bottom and each pixel is linear, premultiplied
+/// colour, ready for `output::save_png`. `progress`, if given, hears the fraction done
+/// after every tile, ending at 1
+pub fn render(
+    scene: &Scene,
+    camera: &Camera,
+    config: &RenderConfig,
+    progress: Option<&Progress>,
+) -> Vec<Vec<Colour>> {
+    camera.create_buffer_parallel(scene, config, progress)
+}
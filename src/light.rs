@@ -1,39 +1,318 @@
-use eframe::egui::Rgba;
+use nalgebra::Vector3;
+use serde::{Deserialize, Serialize};
 
-use crate::{
-    intersect::{Intersect, Intersection},
-    renderer,
-};
+use crate::colour::Colour;
+use crate::rng;
 
-#[derive(Clone, Copy)]
-pub struct PointLight {
-    origin: nalgebra::Vector3<f32>,
-    intensity: f32,
+/// Coefficients of `1 / (constant + linear * d + quadratic * d^2)` falloff
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Attenuation {
+    pub constant: f32,
+    pub linear: f32,
+    pub quadratic: f32,
+}
+
+impl Attenuation {
+    pub fn factor(&self, distance: f32) -> f32 {
+        1.0 / (self.constant + self.linear * distance + self.quadratic * distance.powi(2))
+    }
 }
 
-impl PointLight {
-    pub fn new(origin: nalgebra::Vector3<f32>, intensity: f32) -> PointLight {
-        PointLight { origin, intensity }
+impl Default for Attenuation {
+    // no falloff at all
+    fn default() -> Self {
+        Attenuation {
+            constant: 1.0,
+            linear: 0.0,
+            quadratic: 0.0,
+        }
     }
 }
 
-#[allow(non_snake_case)]
-impl Intersect for PointLight {
-    fn test_intersection(&self, ray: &renderer::Ray) -> crate::intersect::Intersection {
-        let L = self.origin - ray.origin;
-        let t_ca = L.dot(&ray.direction);
+#[derive(Clone, Copy, Serialize, Deserialize)]
+#[serde(tag = "type", rename_all = "snake_case", deny_unknown_fields)]
+pub enum Light {
+    /// Shines in every direction from a single point, fading with distance
+    Point {
+        origin: Vector3<f32>,
+        colour: Colour,
+        intensity: f32,
+        #[serde(default)]
+        attenuation: Attenuation,
+    },
+    /// A point light that only shines within a cone around `direction`, fading out
+    /// between the inner and outer half angles (in degrees)
+    Spot {
+        origin: Vector3<f32>,
+        direction: Vector3<f32>,
+        colour: Colour,
+        intensity: f32,
+        inner_angle: f32,
+        outer_angle: f32,
+        #[serde(default)]
+        attenuation: Attenuation,
+    },
+    /// A rectangle spanned by `edge_u` and `edge_v` from `corner`, giving soft
+    /// shadows. Shadow rays go to `samples` jittered points on it, more samples
+    /// meaning smoother penumbras at a higher cost
+    Area {
+        corner: Vector3<f32>,
+        edge_u: Vector3<f32>,
+        edge_v: Vector3<f32>,
+        colour: Colour,
+        intensity: f32,
+        samples: u32,
+        #[serde(default)]
+        attenuation: Attenuation,
+    },
+    /// Parallel rays from infinitely far away, like the sun. Never fades
+    Directional {
+        direction: Vector3<f32>, // the way the light travels, not the way to the light
+        colour: Colour,
+        intensity: f32,
+    },
+}
+
+/// What a light contributes at one point on a surface
+pub struct Illumination {
+    pub to_light: Vector3<f32>, // unit vector from the point towards the light
+    pub distance: f32, // how far a shadow ray has to travel, infinite for directional lights
+    pub colour: Colour,
+    pub intensity: f32,
+}
+
+impl Light {
+    pub fn point(origin: Vector3<f32>, colour: Colour, intensity: f32) -> Light {
+        Light::Point {
+            origin,
+            colour,
+            intensity,
+            attenuation: Attenuation::default(),
+        }
+    }
+
+    pub fn white(origin: Vector3<f32>, intensity: f32) -> Light {
+        Light::point(origin, Colour::from_gray(1.0), intensity)
+    }
+
+    pub fn directional(direction: Vector3<f32>, colour: Colour, intensity: f32) -> Light {
+        Light::Directional {
+            direction: direction.normalize(),
+            colour,
+            intensity,
+        }
+    }
+
+    pub fn spot(
+        origin: Vector3<f32>,
+        direction: Vector3<f32>,
+        colour: Colour,
+        intensity: f32,
+        inner_angle: f32,
+        outer_angle: f32,
+    ) -> Light {
+        Light::Spot {
+            origin,
+            direction: direction.normalize(),
+            colour,
+            intensity,
+            inner_angle,
+            outer_angle,
+            attenuation: Attenuation::default(),
+        }
+    }
+
+    pub fn area(
+        corner: Vector3<f32>,
+        edge_u: Vector3<f32>,
+        edge_v: Vector3<f32>,
+        colour: Colour,
+        intensity: f32,
+        samples: u32,
+    ) -> Light {
+        Light::Area {
+            corner,
+            edge_u,
+            edge_v,
+            colour,
+            intensity,
+            samples: samples.max(1),
+            attenuation: Attenuation::default(),
+        }
+    }
+
+    /// Sets the falloff of a light, directional lights are left unchanged
+    pub fn with_attenuation(mut self, constant: f32, linear: f32, quadratic: f32) -> Light {
+        if let Light::Point { attenuation, .. }
+        | Light::Spot { attenuation, .. }
+        | Light::Area { attenuation, .. } = &mut self
+        {
+            *attenuation = Attenuation {
+                constant,
+                linear,
+                quadratic,
+            };
+        }
+        self
+    }
 
-        let background = 0.0;
+    /// How many shadow rays shading a point against this light takes
+    pub fn samples(&self) -> u32 {
+        match *self {
+            Light::Area { samples, .. } => samples.max(1),
+            _ => 1,
+        }
+    }
 
-        if t_ca < 0.0 {
-            return Intersection::new(Rgba::from_gray(background), None, None);
+    /// Direction, shadow ray length and strength of the light arriving at `point`
+    /// for shadow ray `sample` of `samples()`. Each sample carries the light's full
+    /// intensity, averaging them is up to the caller
+    pub fn illuminate(&self, point: &Vector3<f32>, sample: u32) -> Illumination {
+        match *self {
+            Light::Point {
+                origin,
+                colour,
+                intensity,
+                attenuation,
+            } => point_illumination(point, origin, colour, intensity, attenuation),
+            Light::Area {
+                corner,
+                edge_u,
+                edge_v,
+                colour,
+                intensity,
+                samples,
+                attenuation,
+            } => {
+                let (u, v) = area_sample_offset(sample, samples.max(1));
+                let origin = corner + edge_u * u + edge_v * v;
+                point_illumination(point, origin, colour, intensity, attenuation)
+            }
+            Light::Spot {
+                origin,
+                direction,
+                colour,
+                intensity,
+                inner_angle,
+                outer_angle,
+                attenuation,
+            } => {
+                let to_light = origin - point;
+                let distance = to_light.norm();
+                let to_light = to_light / distance;
+                let cone = cone_falloff(
+                    (-to_light).angle(&direction).to_degrees(),
+                    inner_angle,
+                    outer_angle,
+                );
+                Illumination {
+                    to_light,
+                    distance,
+                    colour,
+                    intensity: intensity * cone * attenuation.factor(distance),
+                }
+            }
+            Light::Directional {
+                direction,
+                colour,
+                intensity,
+            } => Illumination {
+                to_light: -direction.normalize(),
+                distance: f32::INFINITY,
+                colour,
+                intensity,
+            },
         }
+    }
+}
 
-        let close_approach_point = ray.at_point(t_ca); // closest approach
-        let distance = (close_approach_point - self.origin).norm();
+/// 1 inside the inner cone, 0 outside the outer one and a smoothstep in between
+fn cone_falloff(angle: f32, inner_angle: f32, outer_angle: f32) -> f32 {
+    if angle <= inner_angle {
+        return 1.0;
+    }
+    if angle >= outer_angle {
+        return 0.0;
+    }
+    let t = (outer_angle - angle) / (outer_angle - inner_angle);
+    t * t * (3.0 - 2.0 * t)
+}
+
+fn point_illumination(
+    point: &Vector3<f32>,
+    origin: Vector3<f32>,
+    colour: Colour,
+    intensity: f32,
+    attenuation: Attenuation,
+) -> Illumination {
+    let to_light = origin - point;
+    let distance = to_light.norm();
+    Illumination {
+        to_light: to_light / distance,
+        distance,
+        colour,
+        intensity: intensity * attenuation.factor(distance),
+    }
+}
+
+/// Where sample `i` of `n` lands on an area light, as fractions along its two edges.
+/// The light is split into a grid of roughly `n` cells and each sample goes to a random
+/// spot in its own cell, so samples don't bunch up. A single sample takes the centre
+fn area_sample_offset(i: u32, n: u32) -> (f32, f32) {
+    if n == 1 {
+        return (0.5, 0.5);
+    }
+    let columns = (n as f32).sqrt().ceil() as u32;
+    let rows = n.div_ceil(columns);
+    let (column, row) = (i % columns, i / columns);
+    let u = (column as f32 + rng::random()) / columns as f32;
+    let v = (row as f32 + rng::random()) / rows as f32;
+    (u, v)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn twice_as_far_is_a_quarter_as_bright_with_quadratic_falloff() {
+        let light = Light::white(Vector3::zeros(), 1.0).with_attenuation(0.0, 0.0, 1.0);
+        let near = light.illuminate(&Vector3::new(2.0, 0.0, 0.0), 0).intensity;
+        let far = light.illuminate(&Vector3::new(4.0, 0.0, 0.0), 0).intensity;
+        assert!((far - near / 4.0).abs() < 1e-6, "{} then {}", near, far);
+
+        // the default falls off not at all
+        let flat = Light::white(Vector3::zeros(), 1.0);
+        assert_eq!(
+            flat.illuminate(&Vector3::new(4.0, 0.0, 0.0), 0).intensity,
+            1.0
+        );
+    }
+
+    #[test]
+    fn sun_overhead_lights_what_faces_up() {
+        let sun = Light::directional(-Vector3::z(), Colour::from_gray(1.0), 1.0);
+        let illumination = sun.illuminate(&Vector3::new(3.0, -2.0, 0.0), 0);
+        assert_eq!(illumination.distance, f32::INFINITY);
+
+        let diffuse = |normal: Vector3<f32>| normal.dot(&illumination.to_light).max(0.0);
+        assert_eq!(diffuse(Vector3::z()), 1.0);
+        assert_eq!(diffuse(-Vector3::z()), 0.0);
+    }
 
-        let brightness = t_ca * self.intensity / distance.powi(2);
+    #[test]
+    fn spotlight_lights_its_axis_and_not_past_its_cone() {
+        let white = Colour::from_gray(1.0);
+        let spot = Light::spot(Vector3::zeros(), -Vector3::z(), white, 1.0, 15.0, 30.0);
+        let on_axis = spot.illuminate(&Vector3::new(0.0, 0.0, -2.0), 0);
+        assert_eq!(on_axis.intensity, 1.0);
 
-        return Intersection::new(Rgba::from_gray(brightness), Some(10.0), None);
+        // 45 degrees off the axis
+        let outside = spot.illuminate(&Vector3::new(2.0, 0.0, -2.0), 0);
+        assert_eq!(outside.intensity, 0.0);
+        // halfway between the cones
+        let edge = spot.illuminate(&Vector3::new(22.5f32.to_radians().tan(), 0.0, -1.0), 0);
+        assert!((edge.intensity - 0.5).abs() < 1e-3, "{}", edge.intensity);
     }
 }
@@ -1,45 +1,86 @@
-use camera::Camera;
-use eframe::egui::{self, Key, Rgba};
-use renderer::Ray;
-use scene::Scene;
-mod camera;
-mod intersect;
-mod light;
-mod renderer;
-mod scene;
-mod sphere;
-
-use std::{cell::RefCell, time::Instant};
+#![allow(clippy::needless_return)]
 
+use cgraphics::{
+    accumulator::Accumulator,
+    camera::{Camera, CameraSettings, ProjectionKind},
+    colour::Colour,
+    denoise::{self, DenoiseSettings, Guides},
+    intersect::Intersectable,
+    output,
+    renderer::RenderConfig,
+    reproject::{self, GBuffer},
+    scene::Scene,
+    timing::FrameTiming,
+};
+use display::Display;
+use eframe::egui::{self, Key};
+use inertia::Inertia;
+use keybindings::KeyBindings;
+use orbit::Orbit;
+use render_thread::RenderThread;
+use scene_watcher::SceneWatcher;
+use viewpoint::{Viewpoint, Viewpoints};
+mod display;
+mod editor;
+mod headless;
+mod inertia;
+mod keybindings;
+mod orbit;
+mod render_thread;
+mod scene_watcher;
+mod viewpoint;
+
+use std::{
+    cell::RefCell,
+    collections::VecDeque,
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, Instant},
+};
+
+const FPS_WINDOW: Duration = Duration::from_secs(1);
+
+/// Frames per second over the last `FPS_WINDOW`, so slowdowns show up as they happen
 struct FrameCounter {
-    start_time: Instant,
-    frame_count: u32,
-    last_update: Instant,
+    frame_times: VecDeque<Instant>, // when each frame in the window was drawn, oldest first
 }
 
 impl FrameCounter {
     fn new() -> Self {
         Self {
-            start_time: Instant::now(),
-            frame_count: 0,
-            last_update: Instant::now(),
+            frame_times: VecDeque::new(),
         }
     }
 
-    fn update(&mut self, frame_count: u32) {
-        self.frame_count = frame_count;
-        if Instant::now() - self.last_update > std::time::Duration::from_secs(1) {
-            self.last_update = Instant::now();
+    fn update(&mut self) {
+        self.record(Instant::now());
+    }
+
+    /// Counts a frame drawn at `now` and forgets any that have fallen out of the window
+    fn record(&mut self, now: Instant) {
+        self.frame_times.push_back(now);
+        while let Some(&oldest) = self.frame_times.front() {
+            if now.duration_since(oldest) <= FPS_WINDOW {
+                break;
+            }
+            self.frame_times.pop_front();
         }
     }
 
     fn fps(&self) -> f32 {
-        let elapsed = self.last_update.duration_since(self.start_time);
-        self.frame_count as f32 / elapsed.as_secs_f32()
+        // n timestamps span n - 1 frame intervals
+        let (Some(first), Some(last)) = (self.frame_times.front(), self.frame_times.back()) else {
+            return 0.0;
+        };
+        let span = last.duration_since(*first).as_secs_f32();
+        if span <= 0.0 {
+            return 0.0;
+        }
+        (self.frame_times.len() - 1) as f32 / span
     }
 
     fn show(&mut self, ctx: &egui::Context, frame_count: u32) {
-        self.update(frame_count);
+        self.update();
         egui::Area::new(egui::Id::new("fps_area"))
             .fixed_pos(egui::pos2(10.0, 10.0))
             .show(ctx, |ui| {
@@ -48,10 +89,27 @@ impl FrameCounter {
                 ui.label(format!("Frame Count: {:3.2}", frame_count));
             });
     }
-
 }
 
 fn main() -> eframe::Result {
+    // saves and loads by default, RUST_LOG=debug adds every camera move and resize
+    env_logger::Builder::from_env(env_logger::Env::default().default_filter_or("info")).init();
+
+    match headless::parse_args(std::env::args().skip(1)) {
+        Ok(Some(options)) => {
+            if let Err(e) = headless::run(&options) {
+                log::error!("{}", e);
+                std::process::exit(1);
+            }
+            return Ok(());
+        }
+        Ok(None) => {}
+        Err(e) => {
+            eprintln!("{}\n{}", e, headless::USAGE);
+            std::process::exit(2);
+        }
+    }
+
     let options = eframe::NativeOptions {
         viewport: egui::ViewportBuilder::default().with_inner_size([820.0, 820.0]),
         ..Default::default()
@@ -59,131 +117,781 @@ fn main() -> eframe::Result {
     eframe::run_native(
         "renderer",
         options,
-        Box::new(|_| {
+        Box::new(|creation| {
             // This gives us image support:
-            Ok(Box::<RenderApp>::default())
+            let mut app = RenderApp::default();
+            app.watch_scene(&creation.egui_ctx);
+            Ok(Box::new(app))
         }),
     )
 }
 
+const NUMBER_KEYS: [Key; 9] = [
+    Key::Num1,
+    Key::Num2,
+    Key::Num3,
+    Key::Num4,
+    Key::Num5,
+    Key::Num6,
+    Key::Num7,
+    Key::Num8,
+    Key::Num9,
+];
+
+const SCREENSHOT_TIMEOUT: Duration = Duration::from_secs(2);
+const ORBIT_ZOOM_SPEED: f32 = 0.002; // fraction of the radius per point scrolled
+const RENDER_SCALES: [f32; 3] = [1.0, 0.5, 0.25]; // cycled with R, of the panel's width and height
+
+/// Width and height of a buffer rendered at `scale` times the given size, never empty
+fn scaled_size(width: u32, height: u32, scale: f32) -> (u32, u32) {
+    let scaled = |length: u32| ((length as f32 * scale).round() as u32).max(1);
+    (scaled(width), scaled(height))
+}
+
+/// Writes a capture of the window, which egui hands over already encoded for display
+/// Swaps `scene` for the one in the file at `path`. If the file can't be read, say
+/// because it was caught half saved, `scene` is left as it was and the error returned
+fn reload_scene(scene: &mut Arc<Scene>, path: &Path) -> Result<(), String> {
+    *scene = Arc::new(Scene::from_json_file(path)?);
+    Ok(())
+}
+
+fn save_screenshot(image: &egui::ColorImage, path: &Path) -> Result<(), String> {
+    let pixels: Vec<u8> = image
+        .pixels
+        .iter()
+        .flat_map(|pixel| pixel.to_srgba_unmultiplied())
+        .collect();
+    image::save_buffer(
+        path,
+        &pixels,
+        image.width() as u32,
+        image.height() as u32,
+        image::ColorType::Rgba8,
+    )
+    .map_err(|e| format!("failed to write {}: {}", path.display(), e))
+}
+
 struct RenderApp {
-    buffer: Vec<Vec<Rgba>>,
+    buffer: Vec<Vec<Colour>>,
     camera: Camera,
-    scene: Scene,
+    scene: Arc<Scene>,       // shared with the render thread
+    selected: Option<usize>, // object being edited in the side panel
+    scene_path: PathBuf,
+    scene_watcher: Option<SceneWatcher>, // reloads `scene_path` whenever it changes on disk
+    scene_error: Option<String>,         // why the scene file last failed to load, shown in the HUD
+    loaded_view: Option<CameraSettings>, // camera of the scene file as last loaded
+    viewpoints: Viewpoints, // saved with ctrl (cmd on mac) and a number key, recalled with the number alone
+    viewpoints_path: PathBuf,
+    viewpoints_changed: bool, // since they were loaded, so they're written out on exit
+    render_config: RenderConfig,
+    render_scale: f32, // of the panel's size, used while the camera moves. Still views render in full
+    inertia: Inertia,  // smooths out movement with the keyboard
+    last_frame: Instant,
+    mouse_sensitivity: f32,    // radians turned per point the pointer moves
+    key_bindings: KeyBindings, // from keybindings.json if there is one
+    orbit: Option<Orbit>,      // circling a target instead of flying, toggled with C
+    accumulator: Accumulator,
+    render_thread: RenderThread,
+    tiles_remaining: usize, // of the sample in flight, 0 when nothing is rendering
+    sample: Vec<Vec<Colour>>, // each sample's tiles are collected here before accumulating
+    sample_timing: FrameTiming, // of the tiles of the sample in flight so far
+    timing: FrameTiming,    // of the last whole sample, shown in the HUD
+    display: Display,       // the buffer as egui shows it, uploaded when it changes
+    max_accumulated_frames: u32, // stop refining after this many samples per pixel
+    encoding: output::Encoding,
+    dirty: bool, // set whenever the camera or scene changes so the next frame re-renders
+    camera_moved: bool, // a nudge that can reuse what's accumulated, see `reproject`
+    rendered_camera: Camera, // the view the accumulated samples are of
+    gbuffer: Option<Arc<GBuffer>>, // of `rendered_camera`, kept while the camera keeps moving
+    reprojecting: bool, // waiting on the render thread to move the accumulated colours to a new view
+    denoise: bool,      // filter the noise out of each finished sample before showing it
+    guides: Option<Guides>, // for denoising `rendered_camera`'s view, rendered when first needed
+    screenshot: Option<(u64, Instant)>, // id and time of the window capture being waited on
+    screenshots_requested: u64,
     frame_counter: RefCell<FrameCounter>,
     frame_count: RefCell<u32>,
 }
 
 impl Default for RenderApp {
     fn default() -> Self {
-        let width = 500;
-        let height = 800;
+        let width = 800;
+        let height = 500;
 
         let row = (0..width)
-            .map(|_| Rgba::from_gray(0.0))
-            .collect::<Vec<Rgba>>();
+            .map(|_| Colour::from_gray(0.0))
+            .collect::<Vec<Colour>>();
 
-        let buffer = (0..height).map(|_| row.clone()).collect::<Vec<Vec<Rgba>>>();
+        let buffer = (0..height)
+            .map(|_| row.clone())
+            .collect::<Vec<Vec<Colour>>>();
 
-        let ray_location = nalgebra::Vector3::new(-10.0, 0.0, 0.0);
-        let ray_direction = nalgebra::Vector3::new(1.0, 0.0, 0.0);
-        let origin_ray = Ray::new_preserve(ray_location, ray_direction);
+        let camera = Camera::default_view(width, height);
+        let rendered_camera = camera.clone();
 
-        let camera = Camera {
-            location: origin_ray,
-            width,
-            height,
-        };
+        let viewpoints_path = PathBuf::from("viewpoints.json");
+        let viewpoints = viewpoint::load(&viewpoints_path).unwrap_or_else(|e| {
+            log::error!("{}", e);
+            Viewpoints::new()
+        });
+        let key_bindings = keybindings::load(Path::new("keybindings.json")).unwrap_or_else(|e| {
+            log::error!("{}", e);
+            KeyBindings::default()
+        });
 
         RenderApp {
+            sample: buffer.clone(),
             buffer,
             camera,
-            scene: Scene::pondering_orbs(),
+            scene: Arc::new(Scene::pondering_orbs()),
+            selected: None,
+            scene_path: PathBuf::from("scene.json"),
+            scene_watcher: None,
+            scene_error: None,
+            loaded_view: None,
+            viewpoints,
+            viewpoints_path,
+            viewpoints_changed: false,
+            render_config: RenderConfig::default(),
+            render_scale: 1.0,
+            inertia: Inertia::new(3.0, 12.0),
+            last_frame: Instant::now(),
+            mouse_sensitivity: 0.005,
+            key_bindings,
+            orbit: None,
+            accumulator: Accumulator::new(width, height),
+            render_thread: RenderThread::spawn(),
+            tiles_remaining: 0,
+            sample_timing: FrameTiming::default(),
+            timing: FrameTiming::default(),
+            display: Display::new(),
+            max_accumulated_frames: 64,
+            encoding: output::Encoding::default(),
+            dirty: true,
+            camera_moved: false,
+            rendered_camera,
+            gbuffer: None,
+            reprojecting: false,
+            denoise: false,
+            guides: None,
+            screenshot: None,
+            screenshots_requested: 0,
             frame_counter: RefCell::new(FrameCounter::new()),
             frame_count: RefCell::new(0),
         }
     }
 }
 
-#[allow(deprecated)]
 impl eframe::App for RenderApp {
     fn update(&mut self, ctx: &egui::Context, _: &mut eframe::Frame) {
+        // capped so the first key press after sitting idle doesn't teleport the camera
+        let now = Instant::now();
+        let delta_time = now
+            .duration_since(self.last_frame)
+            .min(Duration::from_millis(100))
+            .as_secs_f32();
+        self.last_frame = now;
+
+        self.handle_screenshot(ctx);
+        if self
+            .scene_watcher
+            .as_ref()
+            .is_some_and(|watcher| watcher.take_change())
+        {
+            self.load_scene(true);
+        }
+        self.show_object_panel(ctx);
         egui::CentralPanel::default().show(ctx, |ui| {
-            self.update_buffer_sharedstate();
-            let img =
-                egui_extras::image::RetainedImage::from_color_image("text", self.buffer_to_image());
-            img.show(ui);
+            let available = ui.available_size();
+            // the inputs of the last frame are what decide whether the camera is moving
+            let navigating = self.camera_moved || self.inertia.is_moving();
+            let scale = if navigating { self.render_scale } else { 1.0 };
+            let (width, height) = scaled_size(available.x as u32, available.y as u32, scale);
+            self.resize(width, height);
+            self.update_buffer_sharedstate(ctx);
+            let conversion_start = Instant::now();
+            let (texture, uploaded) = self.display.texture(ctx, &self.buffer, self.encoding);
+            if uploaded {
+                self.timing.conversion = conversion_start.elapsed();
+            }
+            // stretched over the panel whatever resolution it was rendered at
+            ui.image((
+                texture.id(),
+                egui::vec2(available.x.floor(), available.y.floor()),
+            ));
 
             *self.frame_count.borrow_mut() += 1;
-            self.frame_counter.borrow_mut().show(ctx, *self.frame_count.borrow());
+            self.frame_counter
+                .borrow_mut()
+                .show(ctx, *self.frame_count.borrow());
+            self.show_camera_hud(ctx);
+            // keys and drags meant for the side panel's widgets shouldn't move the camera too
+            if ctx.wants_keyboard_input() {
+                return;
+            }
+            let dragging_widget = ctx.is_using_pointer();
+            // scrolling the side panel shouldn't zoom
+            let pointer_over_view = ui.ui_contains_pointer();
             ctx.input(|inputs| {
-                for pressed in &inputs.keys_down {
-                    match pressed {
-                        Key::W => self.camera.location.origin.x += 0.1,
-                        Key::S => self.camera.location.origin.x -= 0.1,
-                        Key::A => self.camera.location.origin.y -= 0.1,
-                        Key::D => self.camera.location.origin.y += 0.1,
-                        Key::Z => self.camera.location.origin.z += 0.1,
-                        Key::X => self.camera.location.origin.z -= 0.1,
-                        Key::ArrowLeft => {
-                            let x = self.camera.location.direction.x;
-                            let y = self.camera.location.direction.y;
-
-                            let theta = y.atan2(x);
-                            let r = (x.powi(2) + y.powi(2)).sqrt();
-
-                            let theta_1 = theta - 0.01;
-
-                            let x_1 = theta_1.cos() * r;
-                            let y_1 = theta_1.sin() * r;
-
-                            self.camera.location.direction.x = x_1;
-                            self.camera.location.direction.y = y_1;
-                            println!("{:?}", self.camera.location.direction);
+                if inputs.key_pressed(Key::P) {
+                    let path = output::next_render_path();
+                    match output::save_png(&self.buffer, self.encoding, &path) {
+                        Ok(()) => log::info!("saved render to {}", path.display()),
+                        Err(e) => log::error!("{}", e),
+                    }
+                }
+                if inputs.key_pressed(Key::L) {
+                    self.load_scene(false);
+                }
+                if inputs.key_pressed(Key::K) {
+                    Arc::make_mut(&mut self.scene).set_camera(&self.camera);
+                    match self.scene.to_json_file(&self.scene_path) {
+                        Ok(()) => log::info!("saved scene to {}", self.scene_path.display()),
+                        Err(e) => log::error!("{}", e),
+                    }
+                    // so the reload this save sets off doesn't count it as a new view
+                    self.loaded_view = self.scene.camera_settings().cloned();
+                }
+                for (slot, key) in NUMBER_KEYS.iter().enumerate() {
+                    if !inputs.key_pressed(*key) {
+                        continue;
+                    }
+                    let slot = slot as u8 + 1;
+                    if inputs.modifiers.command {
+                        self.viewpoints.insert(slot, Viewpoint::of(&self.camera));
+                        self.viewpoints_changed = true;
+                        log::info!("saved viewpoint {}", slot);
+                    } else if let Some(viewpoint) = self.viewpoints.get(&slot) {
+                        viewpoint.apply(&mut self.camera);
+                        self.orbit = None;
+                        log::info!("jumped to viewpoint {}", slot);
+                        self.dirty = true;
+                    }
+                }
+                if inputs.key_pressed(Key::T) {
+                    self.encoding.tone_map = self.encoding.tone_map.next();
+                }
+                if inputs.key_pressed(Key::G) {
+                    self.encoding.gamma_correct = !self.encoding.gamma_correct;
+                }
+                if inputs.key_pressed(Key::B) {
+                    self.encoding.dither = !self.encoding.dither;
+                }
+                if inputs.key_pressed(Key::V) {
+                    self.denoise = !self.denoise;
+                    self.show_accumulated();
+                }
+                if inputs.key_pressed(Key::R) {
+                    let current = RENDER_SCALES
+                        .iter()
+                        .position(|&scale| scale == self.render_scale)
+                        .unwrap_or(0);
+                    self.render_scale = RENDER_SCALES[(current + 1) % RENDER_SCALES.len()];
+                    log::info!(
+                        "rendering at {:.0}% while moving",
+                        self.render_scale * 100.0
+                    );
+                }
+                if inputs.key_pressed(Key::N) {
+                    self.render_config.mode = self.render_config.mode.next();
+                    self.dirty = true;
+                }
+                if inputs.key_pressed(Key::F) {
+                    self.camera.frame(&self.scene);
+                    self.orbit = None;
+                    self.dirty = true;
+                }
+                if inputs.key_pressed(Key::C) {
+                    self.toggle_orbit();
+                }
+                if inputs.key_pressed(Key::O) {
+                    self.camera.projection = match self.camera.projection {
+                        ProjectionKind::Perspective => ProjectionKind::Orthographic { scale: 5.0 },
+                        ProjectionKind::Orthographic { .. } => ProjectionKind::Perspective,
+                    };
+                    self.dirty = true;
+                }
+                // the pointer delta is the whole movement since the last frame, so no
+                // frame time scaling is needed
+                if inputs.pointer.primary_down() && !dragging_widget {
+                    let delta = inputs.pointer.delta();
+                    if delta != egui::Vec2::ZERO {
+                        match &mut self.orbit {
+                            // dragging turns the target, so the camera swings the other way
+                            Some(orbit) => orbit.rotate(
+                                -delta.x * self.mouse_sensitivity,
+                                delta.y * self.mouse_sensitivity,
+                            ),
+                            None => self.camera.rotate(
+                                delta.x * self.mouse_sensitivity,
+                                -delta.y * self.mouse_sensitivity,
+                            ),
                         }
-                        Key::ArrowRight => {
-                            let x = self.camera.location.direction.x;
-                            let y = self.camera.location.direction.y;
+                        self.camera_moved = true;
+                    }
+                }
+                if let Some(orbit) = &mut self.orbit {
+                    let scroll = inputs.smooth_scroll_delta.y;
+                    if scroll != 0.0 && pointer_over_view {
+                        // scrolling up moves in, by the same fraction whatever the radius
+                        orbit.zoom((-scroll * ORBIT_ZOOM_SPEED).exp());
+                        self.camera_moved = true;
+                    }
+                }
+                // forward, right and up, handed to the inertia rather than moving directly
+                let (movement, steered) = self.key_bindings.steer(
+                    |key| inputs.key_down(key),
+                    &mut self.camera,
+                    &mut self.orbit,
+                );
+                if steered {
+                    self.camera_moved = true;
+                }
+                // + is a shifted = on most keyboards, so either narrows the view
+                if inputs.key_down(Key::Minus) {
+                    self.camera.set_fov(self.camera.fov_y + 1.0);
+                    self.camera_moved = true;
+                }
+                if inputs.key_down(Key::Equals) || inputs.key_down(Key::Plus) {
+                    self.camera.set_fov(self.camera.fov_y - 1.0);
+                    self.camera_moved = true;
+                }
+                let travel = self.inertia.update(movement, delta_time);
+                if self.inertia.is_moving() {
+                    self.camera.translate(travel.x, travel.y, travel.z);
+                    self.camera_moved = true;
+                    // keeps coasting after the keys are let go, when nothing else repaints
+                    ctx.request_repaint();
+                }
+                if let (Some(orbit), true) = (&self.orbit, self.camera_moved) {
+                    orbit.apply(&mut self.camera);
+                }
+                if self.dirty || self.camera_moved {
+                    log::debug!(
+                        "camera at {:?} looking along {:?}",
+                        self.camera.location.origin,
+                        self.camera.location.direction
+                    );
+                }
+            });
+        });
+    }
 
-                            let theta = y.atan2(x);
-                            let r = (x.powi(2) + y.powi(2)).sqrt();
+    fn on_exit(&mut self, _: Option<&eframe::glow::Context>) {
+        if !self.viewpoints_changed {
+            return;
+        }
+        match viewpoint::save(&self.viewpoints, &self.viewpoints_path) {
+            Ok(()) => log::info!("saved viewpoints to {}", self.viewpoints_path.display()),
+            Err(e) => log::error!("{}", e),
+        }
+    }
+}
 
-                            let theta_1 = theta + 0.01;
+impl RenderApp {
+    /// Starts reloading the scene whenever its file changes
+    fn watch_scene(&mut self, ctx: &egui::Context) {
+        match SceneWatcher::watch(&self.scene_path, ctx) {
+            Ok(watcher) => self.scene_watcher = Some(watcher),
+            Err(e) => log::error!("{}", e),
+        }
+    }
 
-                            let x_1 = theta_1.cos() * r;
-                            let y_1 = theta_1.sin() * r;
+    /// Swaps in the scene from `scene_path`, or keeps the current one and says why in the
+    /// HUD if it can't be read. Loading takes the view saved with the scene too. A reload
+    /// after the file changed only does if that view changed, so editing the scene in
+    /// another window doesn't throw away where the camera has been flown to
+    fn load_scene(&mut self, reload: bool) {
+        if let Err(e) = reload_scene(&mut self.scene, &self.scene_path) {
+            log::error!("{}", e);
+            self.scene_error = Some(e);
+            return;
+        }
+        log::info!("loaded scene from {}", self.scene_path.display());
 
-                            self.camera.location.direction.x = x_1;
-                            self.camera.location.direction.y = y_1;
-                            println!("{:?}", self.camera.location.direction);
-                        }
-                        _ => (),
-                    }
+        let view = self.scene.camera_settings().cloned();
+        if let Some(settings) = view
+            .as_ref()
+            .filter(|&view| !reload || Some(view) != self.loaded_view.as_ref())
+        {
+            // the panel decides the resolution, so only the view is taken
+            let (width, height) = (self.camera.width, self.camera.height);
+            settings.apply(&mut self.camera);
+            self.camera.width = width;
+            self.camera.height = height;
+            self.orbit = None;
+        }
+        self.loaded_view = view;
+        // the same object is probably still being edited, if it's still there
+        if !reload
+            || self
+                .selected
+                .is_some_and(|index| index >= self.scene.objects().len())
+        {
+            self.selected = None;
+        }
+        self.scene_error = None;
+        self.dirty = true;
+    }
+
+    /// Switches between flying and orbiting. The orbit is around the selected object, or
+    /// the whole scene if nothing is selected, or failing that a point ten units ahead
+    fn toggle_orbit(&mut self) {
+        if self.orbit.take().is_some() {
+            log::info!("flying");
+            return;
+        }
+        let selected = self
+            .selected
+            .and_then(|index| self.scene.objects().get(index))
+            .and_then(|object| object.shape.bounding_sphere())
+            .map(|sphere| sphere.center);
+        let target = selected
+            .or_else(|| self.scene.bounds().map(|bounds| bounds.centroid()))
+            .unwrap_or_else(|| {
+                self.camera.location.origin + self.camera.location.direction.normalize() * 10.0
+            });
+        let orbit = Orbit::around(&self.camera, target);
+        orbit.apply(&mut self.camera);
+        log::info!("orbiting {:?} at {:.2}", orbit.target, orbit.radius);
+        self.orbit = Some(orbit);
+        // coasting would carry the camera off the orbit
+        self.inertia.velocity = nalgebra::Vector3::zeros();
+        self.camera_moved = true;
+    }
+
+    /// Where the camera is and how it's rendering, shown under the frame counter
+    fn show_camera_hud(&self, ctx: &egui::Context) {
+        let origin = self.camera.location.origin;
+        let direction = self.camera.location.direction.normalize();
+        egui::Area::new(egui::Id::new("camera_area"))
+            .fixed_pos(egui::pos2(10.0, 55.0))
+            .show(ctx, |ui| {
+                ui.set_min_width(150.0);
+                ui.label(format!(
+                    "Position: ({:.2}, {:.2}, {:.2})",
+                    origin.x, origin.y, origin.z
+                ));
+                ui.label(format!(
+                    "Direction: ({:.2}, {:.2}, {:.2})",
+                    direction.x, direction.y, direction.z
+                ));
+                ui.label(format!("FOV: {:.0}°", self.camera.fov_y));
+                ui.label(format!("Roll: {:.0}°", self.camera.roll.to_degrees()));
+                ui.label(format!("Mode: {:?}", self.render_config.mode));
+                if self.denoise {
+                    ui.label("Denoised");
+                }
+                let milliseconds = |duration: Duration| duration.as_secs_f32() * 1000.0;
+                ui.label(format!(
+                    "Primary rays: {:.1} ms",
+                    milliseconds(self.timing.primary)
+                ));
+                ui.label(format!(
+                    "Shadow rays: {:.1} ms",
+                    milliseconds(self.timing.shadow)
+                ));
+                ui.label(format!(
+                    "Conversion: {:.1} ms",
+                    milliseconds(self.timing.conversion)
+                ));
+                if let Some(error) = &self.scene_error {
+                    ui.colored_label(ui.visuals().error_fg_color, error);
+                }
+                if self.orbit.is_some() {
+                    ui.label("Orbiting");
+                }
+                if self.render_scale < 1.0 {
+                    ui.label(format!("Moving scale: {:.0}%", self.render_scale * 100.0));
                 }
             });
+    }
+
+    /// F12 asks the backend for a capture of the whole window, overlays and all, which
+    /// turns up as an event a frame or two later. Each request is tagged with an id so
+    /// only the capture of the frame asked for is saved. Backends that can't capture never
+    /// answer, so the request is given up on after a while
+    fn handle_screenshot(&mut self, ctx: &egui::Context) {
+        if ctx.input(|inputs| inputs.key_pressed(Key::F12)) && self.screenshot.is_none() {
+            self.screenshots_requested += 1;
+            let id = self.screenshots_requested;
+            ctx.send_viewport_cmd(egui::ViewportCommand::Screenshot(egui::UserData::new(id)));
+            self.screenshot = Some((id, Instant::now()));
+        }
+        let Some((id, requested)) = self.screenshot else {
+            return;
+        };
+
+        let image = ctx.input(|inputs| {
+            inputs.raw.events.iter().find_map(|event| match event {
+                egui::Event::Screenshot {
+                    user_data, image, ..
+                } if user_data
+                    .data
+                    .as_ref()
+                    .and_then(|data| data.downcast_ref::<u64>())
+                    == Some(&id) =>
+                {
+                    Some(image.clone())
+                }
+                _ => None,
+            })
         });
+        match image {
+            Some(image) => {
+                self.screenshot = None;
+                let path = output::next_free_path("screenshot");
+                match save_screenshot(&image, &path) {
+                    Ok(()) => log::info!("saved screenshot to {}", path.display()),
+                    Err(e) => log::error!("{}", e),
+                }
+            }
+            None if requested.elapsed() > SCREENSHOT_TIMEOUT => {
+                self.screenshot = None;
+                log::warn!("no screenshot arrived, this backend may not support them");
+            }
+            // keep frames coming until the capture turns up
+            None => ctx.request_repaint(),
+        }
     }
-}
 
-impl RenderApp {
-    fn buffer_to_image(&self) -> egui::ColorImage {
-        let mut flattened = vec![];
-        for row in &self.buffer {
-            for pixel in row {
-                let values = pixel.to_srgba_unmultiplied();
-                for v in values {
-                    flattened.push(v);
+    /// The render settings, then a checkbox for every object in the scene, unticking one
+    /// hides it from the render. Clicking a sphere's name selects it for editing
+    fn show_object_panel(&mut self, ctx: &egui::Context) {
+        egui::SidePanel::right("objects_panel").show(ctx, |ui| {
+            ui.heading("Render");
+            if editor::render_settings(ui, &mut self.render_config) {
+                self.dirty = true;
+            }
+            ui.separator();
+
+            ui.heading("Objects");
+            ui.horizontal(|ui| {
+                if ui.button("Add sphere").clicked() {
+                    let (forward, _, _) = self.camera.basis();
+                    let center = self.camera.location.origin + forward * 5.0;
+                    let sphere = Intersectable::Sphere(editor::new_sphere(center));
+                    self.selected = Some(Arc::make_mut(&mut self.scene).add_object(sphere));
+                    self.dirty = true;
+                }
+                if let Some(index) = self.selected {
+                    if ui.button("Delete").clicked() {
+                        Arc::make_mut(&mut self.scene).remove_object(index);
+                        self.selected = None;
+                        self.dirty = true;
+                    }
+                }
+            });
+
+            if let Some(index) = self.selected {
+                if let Intersectable::Sphere(sphere) = &self.scene.objects()[index].shape {
+                    let mut sphere = sphere.clone();
+                    if editor::sphere_editor(ui, &mut sphere) {
+                        Arc::make_mut(&mut self.scene)
+                            .replace_object(index, Intersectable::Sphere(sphere));
+                        self.dirty = true;
+                    }
+                }
+            }
+            ui.separator();
+
+            egui::ScrollArea::vertical().show(ui, |ui| {
+                for i in 0..self.scene.objects().len() {
+                    ui.horizontal(|ui| {
+                        let mut visible = self.scene.objects()[i].visible;
+                        if ui.checkbox(&mut visible, "").changed() {
+                            Arc::make_mut(&mut self.scene).set_visible(i, visible);
+                            self.dirty = true;
+                        }
+                        let label = format!("{} {}", i, self.scene.objects()[i].shape.kind());
+                        if ui
+                            .selectable_label(self.selected == Some(i), label)
+                            .clicked()
+                        {
+                            self.selected = Some(i);
+                        }
+                    });
                 }
+            });
+        });
+    }
+
+    /// Matches the buffer and camera to the resolution being rendered, only reallocating
+    /// when the size actually changed
+    fn resize(&mut self, width: u32, height: u32) {
+        if width == 0 || height == 0 {
+            return;
+        }
+        if width == self.camera.width && height == self.camera.height {
+            return;
+        }
+        self.camera.width = width;
+        self.camera.height = height;
+        self.buffer = vec![vec![Colour::from_gray(0.0); width as usize]; height as usize];
+        self.sample = self.buffer.clone();
+        self.display.invalidate();
+        log::debug!("resized the buffer to {}x{}", width, height);
+        self.dirty = true;
+    }
+
+    /// Collects whatever tiles the render thread has finished, adding each complete
+    /// sample to the running average and asking for the next. Anything moving abandons
+    /// the sample in flight and starts over, except that small camera moves carry the
+    /// accumulated colours across to the new view, sampling again once they're there. The
+    /// first sample after a reset is drawn tile by tile as it arrives, later ones only
+    /// once they're whole
+    fn update_buffer_sharedstate(&mut self, ctx: &egui::Context) {
+        // a move made while the last one is still being reprojected waits for it to land,
+        // rather than abandoning it and never catching up while the camera keeps going
+        if self.camera_moved && !self.dirty && !self.reprojecting {
+            self.dirty = !self.reproject(ctx);
+            self.camera_moved = false;
+        }
+        if self.dirty {
+            self.camera_moved = false;
+        }
+        if self.dirty {
+            self.render_thread.cancel();
+            self.tiles_remaining = 0;
+            self.accumulator
+                .reset(self.camera.width, self.camera.height);
+            self.rendered_camera = self.camera.clone();
+            self.gbuffer = None;
+            self.reprojecting = false;
+            self.guides = None;
+            self.dirty = false;
+        }
+        if self.reprojecting {
+            if let Some(reprojected) = self.render_thread.finished_reprojection() {
+                self.accumulator.seed_history(&reprojected.history);
+                self.accumulator.average_into(&mut self.buffer);
+                self.display.invalidate();
+                self.rendered_camera = reprojected.camera;
+                self.gbuffer = Some(reprojected.gbuffer);
+                self.guides = None;
+                self.reprojecting = false;
+            }
+        }
+
+        for tile in self.render_thread.finished_tiles() {
+            tile.copy_into(&mut self.sample);
+            self.sample_timing += tile.timing;
+            // reprojected history looks better than a single noisy sample
+            if self.accumulator.frames() == 0 && self.gbuffer.is_none() {
+                tile.copy_into(&mut self.buffer);
+                self.display.invalidate();
             }
+            self.tiles_remaining -= 1;
+            if self.tiles_remaining == 0 {
+                // before it's summed in, where a NaN would never go away
+                output::sanitize(&mut self.sample);
+                self.accumulator.add(&self.sample);
+                self.show_accumulated();
+                self.timing = FrameTiming {
+                    conversion: self.timing.conversion,
+                    ..self.sample_timing
+                };
+            }
+        }
+
+        if self.tiles_remaining == 0 && !self.reprojecting && self.is_accumulating() {
+            self.sample_timing = FrameTiming::default();
+            self.tiles_remaining = self.render_thread.render_sample(
+                &self.camera,
+                &self.scene,
+                &self.render_config,
+                self.accumulator.frames(),
+                ctx,
+            );
+        }
+    }
+
+    /// Starts moving the accumulated colours to where they appear from the current
+    /// camera, abandoning the sample in flight. The ray casts that takes happen on the
+    /// render thread, the old view staying up until they're done. Returns false if the
+    /// camera went too far for that to be worthwhile, when everything should be rendered
+    /// afresh instead
+    fn reproject(&mut self, ctx: &egui::Context) -> bool {
+        if !self.accumulator.has_samples()
+            || !reproject::is_small_move(&self.rendered_camera, &self.camera)
+        {
+            return false;
+        }
+        self.tiles_remaining = 0;
+        self.render_thread.reproject(
+            &self.rendered_camera,
+            self.gbuffer.clone(),
+            &self.camera,
+            &self.scene,
+            &self.buffer,
+            ctx,
+        );
+        self.reprojecting = true;
+        true
+    }
+
+    /// Copies the running average into the buffer, denoised if that's turned on
+    fn show_accumulated(&mut self) {
+        self.accumulator.average_into(&mut self.buffer);
+        self.display.invalidate();
+        if !self.denoise || self.accumulator.frames() == 0 {
+            return;
+        }
+        let guides = self.guides.get_or_insert_with(|| {
+            Guides::render(&self.rendered_camera, &self.scene, &self.render_config)
+        });
+        self.buffer = denoise::denoise(&self.buffer, guides, &DenoiseSettings::default());
+    }
+
+    fn is_accumulating(&self) -> bool {
+        self.accumulator.frames() < self.max_accumulated_frames
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fps_follows_the_recent_frame_rate() {
+        let mut counter = FrameCounter::new();
+        // two seconds at 30 frames a second, then one at 10
+        let mut now = Instant::now();
+        for _ in 0..60 {
+            now += Duration::from_secs_f32(1.0 / 30.0);
+            counter.record(now);
+        }
+        assert!((counter.fps() - 30.0).abs() < 1.0, "{}", counter.fps());
+        for _ in 0..10 {
+            now += Duration::from_millis(100);
+            counter.record(now);
         }
-        egui::ColorImage::from_rgba_unmultiplied(
-            [self.buffer[0].len(), self.buffer.len()],
-            &flattened.as_slice(),
-        )
+        assert!((counter.fps() - 10.0).abs() < 1.0, "{}", counter.fps());
     }
 
-    fn update_buffer_sharedstate(&mut self) {
-        //self.buffer = self.camera.create_buffer_parallel(self.scene.clone());
-        self.buffer = self.camera.create_buffer(&self.scene);
+    #[test]
+    fn half_scale_halves_the_buffer() {
+        assert_eq!(scaled_size(800, 500, 0.5), (400, 250));
+        assert_eq!(scaled_size(800, 500, 1.0), (800, 500));
+        // a panel squashed to nothing still gets a pixel to render
+        assert_eq!(scaled_size(1, 0, 0.25), (1, 1));
+    }
+
+    #[test]
+    fn reloading_swaps_in_the_new_scene_and_keeps_it_through_a_bad_save() {
+        let path = std::env::temp_dir().join(format!("reload-{}.json", std::process::id()));
+        let mut scene = Arc::new(Scene::pondering_orbs());
+        let as_json = |scene: &Scene| serde_json::to_value(scene).unwrap();
+
+        let edited = Scene::eclipse();
+        edited.to_json_file(&path).unwrap();
+        reload_scene(&mut scene, &path).unwrap();
+        assert_eq!(as_json(&scene), as_json(&edited));
+
+        std::fs::write(&path, "{ half written").unwrap();
+        let error = reload_scene(&mut scene, &path);
+        std::fs::remove_file(&path).unwrap();
+        assert!(error.is_err());
+        assert_eq!(as_json(&scene), as_json(&edited));
     }
 }
@@ -0,0 +1,123 @@
+use std::path::Path;
+
+use nalgebra::{Vector2, Vector3};
+use serde::{Deserialize, Serialize};
+
+use crate::colour::Colour;
+use crate::texture::{ImageTexture, Texture};
+
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Material {
+    pub albedo: Texture,
+    pub specular: f32,  // strength of the Blinn-Phong highlight
+    pub shininess: f32, // highlight exponent, higher is tighter
+    pub reflectivity: f32,
+    #[serde(default)]
+    pub roughness: f32, // 0 for a perfect mirror up to 1 for reflections that are almost diffuse
+    #[serde(default = "vacuum_ior")]
+    pub ior: f32, // index of refraction
+    #[serde(default)]
+    pub transparency: f32,
+    #[serde(default = "no_emission")]
+    pub emission: Colour, // light given off by the surface itself, lit or not
+    #[serde(default = "two_sided")]
+    pub two_sided: bool, // back hits are lit as if seen from the front. Otherwise they're left black
+}
+
+fn vacuum_ior() -> f32 {
+    1.0
+}
+
+fn two_sided() -> bool {
+    true
+}
+
+fn no_emission() -> Colour {
+    Colour::from_gray(0.0)
+}
+
+impl Material {
+    /// Base colour of the surface at a point on it
+    pub fn colour_at(&self, point: &Vector3<f32>, uv: Option<Vector2<f32>>) -> Colour {
+        self.albedo.colour_at(point, uv)
+    }
+
+    pub fn matte(colour: Colour) -> Material {
+        Material {
+            albedo: colour.into(),
+            specular: 0.0,
+            shininess: 1.0,
+            reflectivity: 0.0,
+            roughness: 0.0,
+            ior: 1.0,
+            transparency: 0.0,
+            emission: no_emission(),
+            two_sided: two_sided(),
+        }
+    }
+
+    pub fn mirror(colour: Colour) -> Material {
+        Material {
+            albedo: colour.into(),
+            specular: 1.0,
+            shininess: 256.0,
+            reflectivity: 1.0,
+            roughness: 0.0,
+            ior: 1.0,
+            transparency: 0.0,
+            emission: no_emission(),
+            two_sided: two_sided(),
+        }
+    }
+
+    /// Mirror whose reflections blur more the rougher it is
+    pub fn metal(colour: Colour, roughness: f32) -> Material {
+        Material {
+            roughness: roughness.clamp(0.0, 1.0),
+            ..Material::mirror(colour)
+        }
+    }
+
+    pub fn glass(colour: Colour, ior: f32) -> Material {
+        Material {
+            albedo: colour.into(),
+            specular: 1.0,
+            shininess: 256.0,
+            reflectivity: 0.0,
+            roughness: 0.0,
+            ior,
+            transparency: 1.0,
+            emission: no_emission(),
+            two_sided: two_sided(),
+        }
+    }
+
+    /// False shades only the side the normals point to, for closed meshes whose insides
+    /// should never be seen
+    pub fn with_two_sided(mut self, two_sided: bool) -> Material {
+        self.two_sided = two_sided;
+        self
+    }
+
+    /// Matte surface coloured by an image, loaded once and shared by every copy of the material
+    pub fn textured(path: &Path) -> Result<Material, String> {
+        let texture = ImageTexture::load(path.to_path_buf())?;
+        Ok(Material {
+            albedo: Texture::Image(texture),
+            ..Material::matte(Colour::from_gray(1.0))
+        })
+    }
+
+    /// Surface that glows with `colour` scaled by `strength` and reflects nothing
+    pub fn emissive(colour: Colour, strength: f32) -> Material {
+        Material {
+            emission: Colour::from_rgb(
+                colour.r() * strength,
+                colour.g() * strength,
+                colour.b() * strength,
+            ),
+            ..Material::matte(Colour::from_gray(0.0))
+        }
+    }
+}
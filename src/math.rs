@@ -0,0 +1,107 @@
+use std::f32::consts::PI;
+
+use nalgebra::Vector3;
+
+// Sampling helpers take their random numbers from `random`, anything returning uniform
+// values in 0..1, normally `rng::random`
+
+/// Two unit vectors perpendicular to the unit vector `normal` and to each other, so
+/// `(tangent, bitangent, normal)` is a right handed frame around it
+pub fn onb_from_normal(normal: &Vector3<f32>) -> (Vector3<f32>, Vector3<f32>) {
+    // any axis not too close to the normal gives a well defined cross product
+    let helper = if normal.x.abs() > 0.9 {
+        Vector3::y()
+    } else {
+        Vector3::x()
+    };
+    let tangent = normal.cross(&helper).normalize();
+    (tangent, normal.cross(&tangent))
+}
+
+/// Direction in the hemisphere around the unit vector `normal`, more likely the closer
+/// it is to the normal. Uniform points on the disk projected up onto the hemisphere give
+/// exactly a cosine distribution
+pub fn cosine_sample_hemisphere(
+    normal: &Vector3<f32>,
+    mut random: impl FnMut() -> f32,
+) -> Vector3<f32> {
+    let (x, y) = random_in_disk(&mut random);
+    let z = (1.0 - x * x - y * y).max(0.0).sqrt();
+    let (tangent, bitangent) = onb_from_normal(normal);
+    (tangent * x + bitangent * y + normal * z).normalize()
+}
+
+/// Uniformly distributed unit vector on the side of the surface `normal` points to
+pub fn uniform_sample_hemisphere(
+    normal: &Vector3<f32>,
+    random: impl FnMut() -> f32,
+) -> Vector3<f32> {
+    let v = random_unit_vector(random);
+    if v.dot(normal) < 0.0 {
+        return -v;
+    }
+    return v;
+}
+
+/// Uniformly distributed direction, found by rejecting points outside the unit sphere
+pub fn random_unit_vector(mut random: impl FnMut() -> f32) -> Vector3<f32> {
+    loop {
+        let v = Vector3::new(
+            random() * 2.0 - 1.0,
+            random() * 2.0 - 1.0,
+            random() * 2.0 - 1.0,
+        );
+        let length_squared = v.norm_squared();
+        if (1e-6..=1.0).contains(&length_squared) {
+            return v / length_squared.sqrt();
+        }
+    }
+}
+
+/// Uniformly distributed point in the unit disk
+pub fn random_in_disk(mut random: impl FnMut() -> f32) -> (f32, f32) {
+    let radius = random().sqrt();
+    let angle = random() * 2.0 * PI;
+    (radius * angle.cos(), radius * angle.sin())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::rng;
+
+    fn normals() -> Vec<Vector3<f32>> {
+        vec![
+            Vector3::x(),
+            -Vector3::x(),
+            Vector3::z(),
+            Vector3::new(0.95, 0.1, -0.3).normalize(),
+            Vector3::new(-1.0, 2.0, 3.0).normalize(),
+        ]
+    }
+
+    #[test]
+    fn basis_is_orthonormal() {
+        for normal in normals() {
+            let (tangent, bitangent) = onb_from_normal(&normal);
+            for (a, b) in [(tangent, bitangent), (tangent, normal), (bitangent, normal)] {
+                assert!(a.dot(&b).abs() < 1e-6, "{} {}", a, b);
+            }
+            assert!((tangent.norm() - 1.0).abs() < 1e-6);
+            assert!((bitangent.norm() - 1.0).abs() < 1e-6);
+            assert!((tangent.cross(&bitangent) - normal).norm() < 1e-6);
+        }
+    }
+
+    #[test]
+    fn cosine_samples_stay_above_the_surface() {
+        rng::seed_pixel(1, 0, 0, 0);
+        for normal in normals() {
+            for _ in 0..1000 {
+                let direction = cosine_sample_hemisphere(&normal, rng::random);
+                assert!(direction.dot(&normal) >= 0.0, "{} {}", normal, direction);
+                assert!((direction.norm() - 1.0).abs() < 1e-5);
+            }
+        }
+    }
+}
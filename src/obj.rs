@@ -0,0 +1,158 @@
+use std::collections::BTreeMap;
+
+use nalgebra::Vector3;
+
+use crate::{material::Material, triangle::Triangle};
+
+/// Parses the vertex, vertex normal and face records of a Wavefront OBJ file into
+/// triangles. Polygons are split into a fan around their first vertex, and faces
+/// giving a normal for every corner are smooth shaded. Comments are ignored and
+/// any other record is skipped with a warning
+pub fn parse_obj(source: &str, material: Material) -> Result<Vec<Triangle>, String> {
+    let mut vertices: Vec<Vector3<f32>> = vec![];
+    let mut normals: Vec<Vector3<f32>> = vec![];
+    let mut triangles = vec![];
+    let mut skipped: BTreeMap<&str, usize> = BTreeMap::new();
+
+    for (line_index, line) in source.lines().enumerate() {
+        let line_number = line_index + 1;
+        let line = line.split('#').next().unwrap_or("").trim();
+        let mut fields = line.split_whitespace();
+        let Some(record) = fields.next() else {
+            continue;
+        };
+
+        match record {
+            "v" => vertices.push(
+                parse_vector(fields)
+                    .map_err(|e| format!("line {}: bad vertex: {}", line_number, e))?,
+            ),
+            "vn" => normals.push(
+                parse_vector(fields)
+                    .map_err(|e| format!("line {}: bad vertex normal: {}", line_number, e))?,
+            ),
+            "f" => {
+                let corners = fields
+                    .map(|field| corner_indices(field, vertices.len(), normals.len()))
+                    .collect::<Option<Vec<(usize, Option<usize>)>>>()
+                    .ok_or(format!("line {}: bad face index", line_number))?;
+                if corners.len() < 3 {
+                    return Err(format!(
+                        "line {}: face needs at least 3 vertices",
+                        line_number
+                    ));
+                }
+                let corner_normals = corners
+                    .iter()
+                    .map(|&(_, normal)| normal.map(|n| normals[n]))
+                    .collect::<Option<Vec<Vector3<f32>>>>();
+                for i in 1..corners.len() - 1 {
+                    let mut triangle = Triangle::new(
+                        vertices[corners[0].0],
+                        vertices[corners[i].0],
+                        vertices[corners[i + 1].0],
+                        material.clone(),
+                    );
+                    if let Some(n) = &corner_normals {
+                        triangle = triangle.with_normals([n[0], n[i], n[i + 1]]);
+                    }
+                    triangles.push(triangle);
+                }
+            }
+            _ => *skipped.entry(record).or_insert(0) += 1,
+        }
+    }
+
+    for (record, count) in skipped {
+        log::warn!("skipped {} unsupported `{}` records in obj", count, record);
+    }
+    Ok(triangles)
+}
+
+/// Reads the three coordinates of a `v` or `vn` record
+fn parse_vector<'a>(fields: impl Iterator<Item = &'a str>) -> Result<Vector3<f32>, String> {
+    let coordinates = fields
+        .take(3)
+        .map(|field| field.parse::<f32>())
+        .collect::<Result<Vec<f32>, _>>()
+        .map_err(|e| e.to_string())?;
+    if coordinates.len() != 3 {
+        return Err("needs 3 coordinates".to_string());
+    }
+    Ok(Vector3::new(coordinates[0], coordinates[1], coordinates[2]))
+}
+
+/// Converts a face corner like `3`, `3/1`, `3//2` or `3/1/2` into zero based vertex
+/// and vertex normal indices, the normal being `None` when the corner has none
+fn corner_indices(
+    field: &str,
+    vertex_count: usize,
+    normal_count: usize,
+) -> Option<(usize, Option<usize>)> {
+    let mut parts = field.split('/');
+    let vertex = resolve_index(parts.next()?, vertex_count)?;
+    let normal = match parts.nth(1) {
+        Some(part) if !part.is_empty() => Some(resolve_index(part, normal_count)?),
+        _ => None,
+    };
+    Some((vertex, normal))
+}
+
+/// One based OBJ index to a zero based one. Negative indices count back from the
+/// most recent record
+fn resolve_index(part: &str, count: usize) -> Option<usize> {
+    let index = part.parse::<i64>().ok()?;
+    let index = if index < 0 {
+        count as i64 + index
+    } else {
+        index - 1
+    };
+    if index < 0 || index >= count as i64 {
+        return None;
+    }
+    Some(index as usize)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::colour::Colour;
+
+    const QUAD: &str = "\
+# a unit square as two triangles
+v 0 0 0
+v 1 0 0
+v 1 1 0
+v 0 1 0
+vt 0 0
+f 1 2 3
+f 1 3 4
+";
+
+    #[test]
+    fn parses_a_two_triangle_quad() {
+        let triangles = parse_obj(QUAD, Material::matte(Colour::from_gray(1.0))).unwrap();
+        assert_eq!(triangles.len(), 2);
+        assert_eq!(triangles[1].v0, Vector3::new(0.0, 0.0, 0.0));
+        assert_eq!(triangles[1].v1, Vector3::new(1.0, 1.0, 0.0));
+        assert_eq!(triangles[1].v2, Vector3::new(0.0, 1.0, 0.0));
+        assert!(triangles.iter().all(|t| t.normals.is_none()));
+    }
+
+    #[test]
+    fn polygons_become_fans_and_bad_indices_are_errors() {
+        let material = Material::matte(Colour::from_gray(1.0));
+        let quad = "v 0 0 0\nv 1 0 0\nv 1 1 0\nv 0 1 0\nf 1 2 3 4\n";
+        assert_eq!(parse_obj(quad, material.clone()).unwrap().len(), 2);
+        let error = parse_obj("v 0 0 0\nf 1 2 3\n", material).err().unwrap();
+        assert!(error.contains("line 2"), "{}", error);
+    }
+
+    #[test]
+    fn faces_with_vertex_normals_are_smooth() {
+        let source = "v 0 0 0\nv 1 0 0\nv 0 1 0\nvn 0 0 1\nvn 1 0 0\nf 1//1 2//2 3//1\n";
+        let triangles = parse_obj(source, Material::matte(Colour::from_gray(1.0))).unwrap();
+        let normals = triangles[0].normals.unwrap();
+        assert_eq!(normals, [Vector3::z(), Vector3::x(), Vector3::z()]);
+    }
+}
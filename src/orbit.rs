@@ -0,0 +1,90 @@
+use nalgebra::Vector3;
+
+use cgraphics::camera::Camera;
+
+const MAX_ELEVATION: f32 = 89.0 * std::f32::consts::PI / 180.0; // straight above or below has no azimuth
+const MIN_RADIUS: f32 = 0.1;
+
+/// Camera control that circles `target` at `radius`, always looking at it. Azimuth is
+/// radians anticlockwise from +x seen from above, like the camera's yaw, and elevation
+/// radians above the target's horizon
+pub struct Orbit {
+    pub target: Vector3<f32>,
+    pub radius: f32,
+    pub azimuth: f32,
+    pub elevation: f32,
+}
+
+impl Orbit {
+    /// Orbit around `target` through wherever the camera is now, so switching to it only
+    /// turns the camera to face the target rather than moving it
+    pub fn around(camera: &Camera, target: Vector3<f32>) -> Orbit {
+        let offset = camera.location.origin - target;
+        let radius = offset.norm().max(MIN_RADIUS);
+        Orbit {
+            target,
+            radius,
+            azimuth: offset.y.atan2(offset.x),
+            elevation: (offset.z / radius)
+                .clamp(-1.0, 1.0)
+                .asin()
+                .clamp(-MAX_ELEVATION, MAX_ELEVATION),
+        }
+    }
+
+    /// Swings round the target, stopping short of passing over the top or underneath
+    pub fn rotate(&mut self, azimuth_delta: f32, elevation_delta: f32) {
+        self.azimuth += azimuth_delta;
+        self.elevation = (self.elevation + elevation_delta).clamp(-MAX_ELEVATION, MAX_ELEVATION);
+    }
+
+    /// Scales the distance to the target, below 1 moving in
+    pub fn zoom(&mut self, factor: f32) {
+        self.radius = (self.radius * factor).max(MIN_RADIUS);
+    }
+
+    /// Where on the sphere around the target the camera sits
+    pub fn origin(&self) -> Vector3<f32> {
+        let (sin_elevation, cos_elevation) = self.elevation.sin_cos();
+        let (sin_azimuth, cos_azimuth) = self.azimuth.sin_cos();
+        self.target
+            + Vector3::new(
+                cos_elevation * cos_azimuth,
+                cos_elevation * sin_azimuth,
+                sin_elevation,
+            ) * self.radius
+    }
+
+    /// Moves the camera onto the orbit, facing the target
+    pub fn apply(&self, camera: &mut Camera) {
+        let origin = self.origin();
+        camera.location.origin = origin;
+        camera.look_along(&(self.target - origin));
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::f32::consts::PI;
+
+    use super::*;
+
+    #[test]
+    fn full_turn_of_azimuth_comes_back_to_the_start() {
+        let mut camera = Camera::default_view(16, 16);
+        camera.location.origin = Vector3::new(-4.0, 3.0, 2.0);
+        let target = Vector3::new(1.0, 0.5, -1.0);
+        let mut orbit = Orbit::around(&camera, target);
+        let start = orbit.origin();
+        assert!((start - camera.location.origin).norm() < 1e-5, "{}", start);
+
+        for _ in 0..8 {
+            orbit.rotate(PI / 4.0, 0.0);
+            assert!(((orbit.origin() - target).norm() - orbit.radius).abs() < 1e-4);
+        }
+        assert!((orbit.origin() - start).norm() < 1e-4, "{}", orbit.origin());
+        orbit.apply(&mut camera);
+        let towards_target = (target - start).normalize();
+        assert!((camera.location.direction - towards_target).norm() < 1e-4);
+    }
+}
@@ -0,0 +1,281 @@
+use std::path::{Path, PathBuf};
+
+use crate::colour::Colour;
+use crate::tonemap::ToneMap;
+
+/// How buffer colours become 8 bit display values.
+/// Render buffers hold linear light (`Colour` is linear and premultiplied), so
+/// shading maths can add and scale colours directly. Gamma is only applied here, on the way out
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Encoding {
+    pub tone_map: ToneMap,
+    pub gamma: f32,
+    pub gamma_correct: bool, // when off the linear values are written out untouched, looking too dark
+    pub dither: bool,        // nudge values by up to half a step in a fixed pattern to hide banding
+}
+
+// anything brighter is almost certainly a bug rather than a highlight
+const MAX_CHANNEL: f32 = 1e4;
+
+/// 4x4 Bayer matrix, every threshold appearing once so the nudges average out to nothing
+const BAYER: [[u8; 4]; 4] = [[0, 8, 2, 10], [12, 4, 14, 6], [3, 11, 1, 9], [15, 7, 13, 5]];
+
+impl Default for Encoding {
+    fn default() -> Self {
+        Encoding {
+            tone_map: ToneMap::None,
+            gamma: 2.2,
+            gamma_correct: true,
+            dither: false,
+        }
+    }
+}
+
+impl Encoding {
+    /// Tone maps then gamma encodes a linear colour, `c^(1/gamma)` per channel clamped to 0-1
+    pub fn encode(&self, pixel: Colour) -> [u8; 4] {
+        self.encode_with_offset(pixel, 0.0)
+    }
+
+    /// Same as `encode`, dithered by where the pixel is in the image if dithering is on
+    pub fn encode_at(&self, pixel: Colour, x: usize, y: usize) -> [u8; 4] {
+        if !self.dither {
+            return self.encode(pixel);
+        }
+        let threshold = BAYER[y % 4][x % 4] as f32;
+        self.encode_with_offset(pixel, (threshold + 0.5) / 16.0 - 0.5)
+    }
+
+    /// `offset` is in quantisation steps and added to the colour channels just before rounding
+    fn encode_with_offset(&self, pixel: Colour, offset: f32) -> [u8; 4] {
+        let alpha = pixel.a();
+        let unmultiplied = if alpha > 0.0 {
+            pixel * (1.0 / alpha)
+        } else {
+            pixel
+        };
+        let mapped = self.tone_map.apply(unmultiplied);
+        let exponent = if self.gamma_correct {
+            1.0 / self.gamma
+        } else {
+            1.0
+        };
+        let quantise = |c: f32| {
+            (c.clamp(0.0, 1.0).powf(exponent) * 255.0 + offset)
+                .round()
+                .clamp(0.0, 255.0) as u8
+        };
+        [
+            quantise(mapped.r()),
+            quantise(mapped.g()),
+            quantise(mapped.b()),
+            (alpha.clamp(0.0, 1.0) * 255.0).round() as u8,
+        ]
+    }
+}
+
+/// Replaces NaN and infinite channels with 0 and clamps the rest to 0..MAX_CHANNEL, so one
+/// bad division in the shading shows up as a single dark pixel instead of spreading
+/// through accumulation and filtering. Warns where the first bad pixel was, to find the bug
+pub fn sanitize(buffer: &mut [Vec<Colour>]) {
+    let mut first_bad = None;
+    let mut bad_count = 0;
+    for (y, row) in buffer.iter_mut().enumerate() {
+        for (x, pixel) in row.iter_mut().enumerate() {
+            let channels = pixel.to_array();
+            if !channels.iter().all(|c| c.is_finite()) {
+                first_bad.get_or_insert((x, y));
+                bad_count += 1;
+            }
+            let [r, g, b, a] = channels.map(|c| {
+                if c.is_finite() {
+                    c.clamp(0.0, MAX_CHANNEL)
+                } else {
+                    0.0
+                }
+            });
+            *pixel = Colour::from_rgba_premultiplied(r, g, b, a);
+        }
+    }
+    if let Some((x, y)) = first_bad {
+        log::warn!(
+            "replaced {} pixels with NaN or infinite values, the first at ({}, {})",
+            bad_count,
+            x,
+            y
+        );
+    }
+}
+
+/// Encodes a buffer into rows of 8 bit RGBA, the layout both egui and png expect
+pub fn flatten(buffer: &[Vec<Colour>], encoding: Encoding) -> Vec<u8> {
+    let mut flattened = vec![];
+    flatten_into(buffer, encoding, &mut flattened);
+    flattened
+}
+
+/// Same as `flatten`, reusing `flattened`'s allocation when it is already big enough
+pub fn flatten_into(buffer: &[Vec<Colour>], encoding: Encoding, flattened: &mut Vec<u8>) {
+    flattened.clear();
+    for (y, row) in buffer.iter().enumerate() {
+        for (x, pixel) in row.iter().enumerate() {
+            flattened.extend_from_slice(&encoding.encode_at(*pixel, x, y));
+        }
+    }
+}
+
+pub fn save_png(buffer: &[Vec<Colour>], encoding: Encoding, path: &Path) -> Result<(), String> {
+    let height = buffer.len();
+    let width = buffer.first().map_or(0, |row| row.len());
+    if width == 0 || height == 0 {
+        return Err(format!(
+            "nothing to save to {}, the buffer is empty",
+            path.display()
+        ));
+    }
+
+    image::save_buffer(
+        path,
+        &flatten(buffer, encoding),
+        width as u32,
+        height as u32,
+        image::ColorType::Rgba8,
+    )
+    .map_err(|e| format!("failed to write {}: {}", path.display(), e))
+}
+
+/// 32 bit float OpenEXR of the buffer's linear values, with nothing clamped, tone mapped
+/// or gamma encoded, for compositing. Alpha above 1 is brought back to 1, scaling the
+/// colour down with it so it looks as it does in a PNG
+pub fn save_exr(buffer: &[Vec<Colour>], path: &Path) -> Result<(), String> {
+    let height = buffer.len();
+    let width = buffer.first().map_or(0, |row| row.len());
+    if width == 0 || height == 0 {
+        return Err(format!(
+            "nothing to save to {}, the buffer is empty",
+            path.display()
+        ));
+    }
+
+    exr::prelude::write_rgba_file(path, width, height, |x, y| {
+        let pixel = buffer[y][x];
+        let scale = if pixel.a() > 1.0 {
+            1.0 / pixel.a()
+        } else {
+            1.0
+        };
+        (
+            pixel.r() * scale,
+            pixel.g() * scale,
+            pixel.b() * scale,
+            pixel.a().min(1.0),
+        )
+    })
+    .map_err(|e| format!("failed to write {}: {}", path.display(), e))
+}
+
+/// `save_exr` if the path ends in `.exr`, otherwise `save_png` with `encoding`
+pub fn save_image(buffer: &[Vec<Colour>], encoding: Encoding, path: &Path) -> Result<(), String> {
+    let is_exr = path
+        .extension()
+        .is_some_and(|extension| extension.eq_ignore_ascii_case("exr"));
+    if is_exr {
+        return save_exr(buffer, path);
+    }
+    save_png(buffer, encoding, path)
+}
+
+/// First `render_NNNN.png` in the working directory that doesn't exist yet
+pub fn next_render_path() -> PathBuf {
+    next_free_path("render")
+}
+
+/// First `<prefix>_NNNN.png` in the working directory that doesn't exist yet
+pub fn next_free_path(prefix: &str) -> PathBuf {
+    (1..)
+        .map(|i| PathBuf::from(format!("{}_{:04}.png", prefix, i)))
+        .find(|path| !path.exists())
+        .unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn dithering_keeps_the_mean_of_a_block() {
+        // a quarter of the way between two 8 bit steps, so plain rounding loses the quarter
+        let linear = Encoding {
+            gamma_correct: false,
+            ..Encoding::default()
+        };
+        let pixel = Colour::from_gray(100.25 / 255.0);
+        let dithered = Encoding {
+            dither: true,
+            ..linear
+        };
+
+        let mut total = 0.0;
+        for y in 0..4 {
+            for x in 0..4 {
+                assert_eq!(linear.encode_at(pixel, x, y), linear.encode(pixel));
+                assert_eq!(linear.encode(pixel)[0], 100);
+                total += dithered.encode_at(pixel, x, y)[0] as f32;
+            }
+        }
+        assert_eq!(total / 16.0, 100.25);
+    }
+
+    #[test]
+    fn sanitizing_zeroes_nan_and_infinite_channels() {
+        let good = Colour::from_rgb(0.25, 0.5, 0.75);
+        let mut buffer = vec![vec![
+            good,
+            Colour::from_rgba_premultiplied(f32::NAN, 0.5, 0.5, 1.0),
+            Colour::from_rgba_premultiplied(f32::INFINITY, f32::NEG_INFINITY, 0.5, 1.0),
+            Colour::from_rgba_premultiplied(1e9, -1.0, 0.5, 1.0),
+        ]];
+        sanitize(&mut buffer);
+
+        assert!(buffer[0]
+            .iter()
+            .flat_map(|pixel| pixel.to_array())
+            .all(|c| c.is_finite() && (0.0..=MAX_CHANNEL).contains(&c)));
+        assert_eq!(buffer[0][0], good);
+        assert_eq!(buffer[0][1].to_array(), [0.0, 0.5, 0.5, 1.0]);
+        assert_eq!(buffer[0][2].to_array(), [0.0, 0.0, 0.5, 1.0]);
+        assert_eq!(buffer[0][3].to_array(), [MAX_CHANNEL, 0.0, 0.5, 1.0]);
+    }
+
+    #[test]
+    fn hdr_values_survive_a_round_trip_through_exr() {
+        let buffer = vec![
+            vec![Colour::from_rgb(12.5, 0.25, 3.0), Colour::from_gray(0.0)],
+            vec![Colour::default(), Colour::from_rgb(1.5, 100.0, 0.001)],
+        ];
+        let path = std::env::temp_dir().join(format!("hdr-{}.exr", std::process::id()));
+        save_image(&buffer, Encoding::default(), &path).unwrap();
+        let read = exr::prelude::read_first_rgba_layer_from_file(
+            &path,
+            |resolution, _| vec![vec![[0.0; 4]; resolution.width()]; resolution.height()],
+            |pixels: &mut Vec<Vec<[f32; 4]>>, position, (r, g, b, a): (f32, f32, f32, f32)| {
+                pixels[position.y()][position.x()] = [r, g, b, a]
+            },
+        );
+        std::fs::remove_file(&path).unwrap();
+
+        let pixels = read.unwrap().layer_data.channel_data.pixels;
+        for (row, read_row) in buffer.iter().zip(&pixels) {
+            for (pixel, read_pixel) in row.iter().zip(read_row) {
+                for (expected, channel) in pixel.to_array().iter().zip(read_pixel) {
+                    assert!(
+                        (expected - channel).abs() <= expected.abs() * 1e-6,
+                        "{:?} {:?}",
+                        pixel,
+                        read_pixel
+                    );
+                }
+            }
+        }
+    }
+}
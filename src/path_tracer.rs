@@ -0,0 +1,415 @@
+use std::f32::consts::PI;
+
+use nalgebra::Vector3;
+
+use crate::{
+    colour::Colour,
+    intersect::Intersect,
+    math,
+    renderer::{self, Ray, RenderConfig, RAY_EPSILON, SHADOW_EPSILON},
+    rng,
+    scene::Scene,
+    sphere::Sphere,
+    timing,
+};
+
+// floor on the chance a path survives Russian roulette, so the boost given to survivors
+// can't blow up into fireflies
+const MIN_SURVIVAL: f32 = 0.05;
+
+/// Monte Carlo estimate of the light arriving along `ray`, following a single path that
+/// bounces diffusely off matte surfaces and picks reflection or refraction on mirrors and
+/// glass in proportion to the material. Emissive surfaces and the background are the only
+/// light, the scene's lights (and fog) are ignored. One sample is very noisy, so this is
+/// meant to be averaged over many frames by the accumulator.
+/// Glowing spheres are also aimed at directly from every diffuse bounce, which finds
+/// small ones far more often than bouncing at random. Light reached both ways is weighted
+/// by the power heuristic so it's only counted once between them, keeping it unbiased.
+/// Past `config.roulette_depth` bounces paths are randomly ended, more likely the less
+/// light they could still carry, and survivors are boosted to make up for the ones lost
+pub fn path_traced(scene: &Scene, ray: Ray, config: &RenderConfig) -> Colour {
+    let mut ray = ray;
+    let mut throughput = Vector3::new(1.0, 1.0, 1.0);
+    let mut radiance = Vector3::zeros();
+    let mut bounce_pdf = None; // of the diffuse bounce `ray` came from, if it came from one
+
+    for depth in 0..=config.max_depth {
+        let intersection = if depth == 0 {
+            renderer::primary_hit(scene, &ray, config)
+        } else {
+            scene.nearest_intersection(&ray)
+        };
+        let Some(normal) = intersection.normal else {
+            let weight = match (bounce_pdf, scene.environment()) {
+                (Some(pdf), Some(environment)) => {
+                    power_heuristic(pdf, environment.pdf(&ray.direction))
+                }
+                _ => 1.0,
+            };
+            radiance += throughput.component_mul(&rgb(scene.background(&ray.direction))) * weight;
+            break;
+        };
+        let material = &intersection.material;
+        let emitted = rgb(material.emission);
+        if emitted != Vector3::zeros() {
+            let weight = match bounce_pdf {
+                Some(pdf) => power_heuristic(pdf, light_pdf(scene, &ray, intersection.distance)),
+                None => 1.0,
+            };
+            radiance += throughput.component_mul(&emitted) * weight;
+        }
+
+        let exiting = !intersection.front_face;
+        let facing = if exiting {
+            -normal.direction
+        } else {
+            normal.direction
+        };
+
+        let choice = rng::random();
+        bounce_pdf = None;
+        let bounced = if choice < material.reflectivity {
+            Ray::new(
+                normal.origin + facing * RAY_EPSILON,
+                renderer::rough_reflect(&ray.direction, &facing, material.roughness),
+            )
+        } else if choice < material.reflectivity + material.transparency {
+            let eta = if exiting {
+                material.ior
+            } else {
+                1.0 / material.ior
+            };
+            let fresnel = renderer::schlick(&ray.direction, &facing, eta);
+            match renderer::refract(&ray.direction, &facing, eta) {
+                Some(direction) if rng::random() >= fresnel => {
+                    Ray::new(normal.origin - facing * RAY_EPSILON, direction)
+                }
+                _ => Ray::new(
+                    normal.origin + facing * RAY_EPSILON,
+                    renderer::reflect(&ray.direction, &facing),
+                ),
+            }
+        } else if exiting && !material.two_sided {
+            break; // the unlit back of a one sided surface
+        } else {
+            let origin = normal.origin + facing * RAY_EPSILON;
+            let albedo = rgb(intersection.colour);
+            let direct = sample_emitter(scene, &origin, &facing, ray.time)
+                + sample_environment(scene, &origin, &facing, ray.time);
+            radiance += throughput.component_mul(&albedo).component_mul(&direct);
+
+            // the albedo / pi BRDF times the cosine term over the pdf of cosine weighted
+            // sampling, cos / pi, leaves just the albedo, which never adds energy
+            throughput = throughput.component_mul(&albedo);
+            let direction = math::cosine_sample_hemisphere(&facing, rng::random);
+            bounce_pdf = Some(facing.dot(&direction).max(0.0) / PI);
+            Ray::new(origin, direction)
+        };
+        // the whole path happens at the moment the camera ray was sent
+        ray = bounced.with_time(ray.time);
+
+        if throughput == Vector3::zeros() {
+            break;
+        }
+        if depth >= config.roulette_depth {
+            let survival = throughput.max().clamp(MIN_SURVIVAL, 1.0);
+            if rng::random() >= survival {
+                break;
+            }
+            throughput /= survival;
+        }
+    }
+    Colour::from_rgb(radiance.x, radiance.y, radiance.z)
+}
+
+/// Light from one randomly picked glowing sphere reaching `origin` on a diffuse surface
+/// facing `normal` at `time`, as a fraction of the albedo: the Lambertian BRDF is albedo / pi
+fn sample_emitter(
+    scene: &Scene,
+    origin: &Vector3<f32>,
+    normal: &Vector3<f32>,
+    time: f32,
+) -> Vector3<f32> {
+    let emitters = scene.emissive_spheres();
+    if emitters.is_empty() {
+        return Vector3::zeros();
+    }
+    let emitter =
+        &emitters[((rng::random() * emitters.len() as f32) as usize).min(emitters.len() - 1)];
+    let Some((direction, cone_pdf)) = sample_cone(origin, emitter, time) else {
+        return Vector3::zeros();
+    };
+    let cosine = normal.dot(&direction);
+    if cosine <= 0.0 {
+        return Vector3::zeros();
+    }
+
+    let shadow_ray = Ray::new(*origin, direction).with_time(time);
+    let Some(distance) = emitter.test_intersection(&shadow_ray).distance else {
+        return Vector3::zeros();
+    };
+    if timing::shadow(|| scene.occluded(&shadow_ray, SHADOW_EPSILON, distance - RAY_EPSILON)) {
+        return Vector3::zeros();
+    }
+    let pdf = cone_pdf / emitters.len() as f32;
+    let weight = power_heuristic(pdf, cosine / PI);
+    rgb(emitter.material.emission) * (cosine / PI / pdf * weight)
+}
+
+/// Light from the background reaching `origin` on a diffuse surface facing `normal`,
+/// along a direction picked by how bright the environment map is that way. Like
+/// `sample_emitter` it's a fraction of the albedo, and 0 without an environment map
+fn sample_environment(
+    scene: &Scene,
+    origin: &Vector3<f32>,
+    normal: &Vector3<f32>,
+    time: f32,
+) -> Vector3<f32> {
+    let Some(environment) = scene.environment() else {
+        return Vector3::zeros();
+    };
+    let (direction, pdf) = environment.sample(rng::random);
+    let cosine = normal.dot(&direction);
+    if cosine <= 0.0 || pdf <= 0.0 {
+        return Vector3::zeros();
+    }
+
+    let shadow_ray = Ray::new(*origin, direction).with_time(time);
+    if timing::shadow(|| scene.occluded(&shadow_ray, SHADOW_EPSILON, f32::INFINITY)) {
+        return Vector3::zeros();
+    }
+    let weight = power_heuristic(pdf, cosine / PI);
+    rgb(scene.background(&direction)) * (cosine / PI / pdf * weight)
+}
+
+/// How likely `sample_emitter` was to have picked the direction of `ray`, which hit
+/// something glowing `distance` away. 0 unless what it hit is one of the spheres it aims at
+fn light_pdf(scene: &Scene, ray: &Ray, distance: Option<f32>) -> f32 {
+    let Some(distance) = distance else {
+        return 0.0;
+    };
+    let emitters = scene.emissive_spheres();
+    let hit = emitters.iter().find(|emitter| {
+        emitter
+            .test_intersection(ray)
+            .distance
+            .is_some_and(|t| (t - distance).abs() <= RAY_EPSILON)
+    });
+    match hit.and_then(|emitter| cone_pdf(&ray.origin, emitter, ray.time)) {
+        Some(pdf) => pdf / emitters.len() as f32,
+        None => 0.0,
+    }
+}
+
+/// 1 - the cosine of the half angle `sphere` covers seen from `origin` at `time`, worked
+/// out without cancelling away small cones. `None` from inside the sphere
+fn cone_gap(origin: &Vector3<f32>, sphere: &Sphere, time: f32) -> Option<f32> {
+    let sin_squared = sphere.radius.powi(2) / (sphere.centre_at(time) - origin).norm_squared();
+    if sin_squared >= 1.0 {
+        return None;
+    }
+    Some(sin_squared / (1.0 + (1.0 - sin_squared).sqrt()))
+}
+
+/// Density of directions uniformly spread over the cone of `sphere` seen from `origin`
+fn cone_pdf(origin: &Vector3<f32>, sphere: &Sphere, time: f32) -> Option<f32> {
+    Some(1.0 / (2.0 * PI * cone_gap(origin, sphere, time)?))
+}
+
+/// Direction towards a uniformly random point of `sphere`'s cone seen from `origin`,
+/// and the density of picking it
+fn sample_cone(origin: &Vector3<f32>, sphere: &Sphere, time: f32) -> Option<(Vector3<f32>, f32)> {
+    let gap = cone_gap(origin, sphere, time)?;
+    let axis = (sphere.centre_at(time) - origin).normalize();
+    let cos_theta = 1.0 - rng::random() * gap;
+    let sin_theta = (1.0 - cos_theta.powi(2)).max(0.0).sqrt();
+    let phi = 2.0 * PI * rng::random();
+    let (tangent, bitangent) = math::onb_from_normal(&axis);
+    let direction = axis * cos_theta + (tangent * phi.cos() + bitangent * phi.sin()) * sin_theta;
+    Some((direction, 1.0 / (2.0 * PI * gap)))
+}
+
+/// Weight for a sample taken with density `pdf` when another strategy could have found
+/// it with density `other`, the two weights always adding up to 1
+fn power_heuristic(pdf: f32, other: f32) -> f32 {
+    let (a, b) = (pdf * pdf, other * other);
+    if a + b == 0.0 {
+        return 0.0;
+    }
+    a / (a + b)
+}
+
+fn rgb(colour: Colour) -> Vector3<f32> {
+    Vector3::new(colour.r(), colour.g(), colour.b())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        aabb::{Aabb, Cuboid},
+        background::Background,
+        camera::Camera,
+        intersect::Intersectable,
+        material::Material,
+        renderer::RenderMode,
+    };
+
+    /// A white matte sphere filling most of the view, under an even grey sky
+    fn furnace(sky: Colour) -> (Scene, Camera) {
+        let sphere = Intersectable::Sphere(Sphere {
+            origin: Vector3::zeros(),
+            radius: 1.0,
+            velocity: None,
+            material: Material::matte(Colour::from_gray(1.0)),
+        });
+        let scene = Scene::new(vec![sphere], vec![])
+            .with_background(Background::Solid { colour: sky })
+            .with_ambient(sky);
+        let camera = Camera::default_view(16, 12).with_fov(10.0).with_samples(4);
+        (scene, camera)
+    }
+
+    fn assert_uniform(buffer: &[Vec<Colour>], expected: f32) {
+        for pixel in buffer.iter().flatten() {
+            for channel in [pixel.r(), pixel.g(), pixel.b()] {
+                assert!((channel - expected).abs() < 1e-4, "{:?}", pixel);
+            }
+        }
+    }
+
+    #[test]
+    fn white_sphere_vanishes_into_an_even_sky() {
+        let (scene, camera) = furnace(Colour::from_gray(0.5));
+        let config = RenderConfig {
+            mode: RenderMode::PathTraced,
+            max_depth: 16,
+            ..RenderConfig::default()
+        };
+        assert_uniform(&camera.create_buffer(&scene, &config), 0.5);
+    }
+
+    #[test]
+    fn white_sphere_lit_by_matching_ambient_vanishes_when_shaded() {
+        let (scene, camera) = furnace(Colour::from_gray(0.5));
+        assert_uniform(&camera.create_buffer(&scene, &RenderConfig::default()), 0.5);
+    }
+
+    /// Inside a glowing box, every wall giving off `emission` and reflecting `albedo`
+    /// of what reaches it, so radiance everywhere inside is emission / (1 - albedo)
+    fn glowing_box(emission: f32, albedo: f32) -> Scene {
+        let material = Material {
+            emission: Colour::from_gray(emission),
+            ..Material::matte(Colour::from_gray(albedo))
+        };
+        let walls = Intersectable::Cuboid(Cuboid {
+            bounds: Aabb::new(Vector3::repeat(-2.0), Vector3::repeat(2.0)),
+            material,
+        });
+        Scene::new(vec![walls], vec![])
+    }
+
+    /// Mean of `paths` estimates looking out from the middle of the box along `direction`
+    fn mean_radiance(scene: &Scene, direction: Vector3<f32>, config: &RenderConfig) -> f32 {
+        let paths = 2000;
+        let total: f32 = (0..paths)
+            .map(|i| {
+                rng::seed_pixel(config.seed, 0, 0, i);
+                path_traced(scene, Ray::new(Vector3::zeros(), direction), config).r()
+            })
+            .sum();
+        total / paths as f32
+    }
+
+    fn path_config(roulette_depth: u32) -> RenderConfig {
+        RenderConfig {
+            mode: RenderMode::PathTraced,
+            max_depth: 32,
+            roulette_depth,
+            ..RenderConfig::default()
+        }
+    }
+
+    #[test]
+    fn closed_glowing_box_converges_to_an_even_glow() {
+        let scene = glowing_box(0.5, 0.5);
+        let config = path_config(RenderConfig::default().roulette_depth);
+        for direction in [Vector3::x(), -Vector3::y(), Vector3::new(1.0, 0.6, -0.3)] {
+            let radiance = mean_radiance(&scene, direction, &config);
+            assert!(
+                (radiance - 1.0).abs() < 0.05,
+                "{} along {:?}",
+                radiance,
+                direction
+            );
+        }
+    }
+
+    #[test]
+    fn russian_roulette_leaves_the_estimate_unchanged() {
+        // bright walls, so paths run long enough for roulette to cut plenty of them short
+        let scene = glowing_box(0.2, 0.8);
+        let without = mean_radiance(&scene, Vector3::x(), &path_config(32));
+        let with = mean_radiance(&scene, Vector3::x(), &path_config(1));
+        assert!((without - 1.0).abs() < 0.05, "{}", without);
+        assert!((with - without).abs() < 0.1, "{} against {}", with, without);
+    }
+
+    #[test]
+    fn aiming_at_a_small_light_agrees_with_bouncing_at_random() {
+        // a white floor under a small bright ball, which covers 1/16 of its sky when
+        // weighted by cosine, so leaves the floor at about 1
+        let ball = Sphere {
+            origin: Vector3::new(0.0, 0.0, 4.0),
+            radius: 1.0,
+            velocity: None,
+            material: Material::emissive(Colour::from_gray(1.0), 16.0),
+        };
+        let floor = Intersectable::Plane(crate::plane::Plane {
+            point: Vector3::zeros(),
+            normal: Vector3::z(),
+            material: Material::matte(Colour::from_gray(1.0)),
+        });
+        let scene = Scene::new(vec![floor, Intersectable::Sphere(ball.clone())], vec![]);
+
+        // brute force: cosine weighted bounces off the floor, counting the light they find
+        let bounces = 100_000;
+        rng::seed_pixel(5, 0, 0, 0);
+        let found = (0..bounces)
+            .filter(|_| {
+                let direction = math::cosine_sample_hemisphere(&Vector3::z(), rng::random);
+                ball.test_intersection(&Ray::new(Vector3::zeros(), direction))
+                    .distance
+                    .is_some()
+            })
+            .count();
+        let brute_force = 16.0 * found as f32 / bounces as f32;
+        let brute_force_variance = 16.0 * brute_force - brute_force.powi(2);
+
+        let config = path_config(RenderConfig::default().roulette_depth);
+        let paths = 400;
+        let estimates: Vec<f32> = (0..paths)
+            .map(|i| {
+                rng::seed_pixel(config.seed, 0, 0, i);
+                let ray = Ray::new(Vector3::new(-1.0, 0.0, 1.0), Vector3::new(1.0, 0.0, -1.0));
+                path_traced(&scene, ray, &config).r()
+            })
+            .collect();
+        let mean = estimates.iter().sum::<f32>() / paths as f32;
+        let variance = estimates.iter().map(|e| (e - mean).powi(2)).sum::<f32>() / paths as f32;
+
+        assert!((brute_force - 1.0).abs() < 0.1, "{}", brute_force);
+        assert!(
+            (mean - brute_force).abs() < 0.05,
+            "{} against {}",
+            mean,
+            brute_force
+        );
+        assert!(
+            variance * 100.0 < brute_force_variance,
+            "{} against {}",
+            variance,
+            brute_force_variance
+        );
+    }
+}
@@ -0,0 +1,76 @@
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    colour::Colour,
+    intersect::{Intersect, Intersection},
+    material::Material,
+    renderer::Ray,
+};
+
+const PARALLEL_EPSILON: f32 = 1e-6;
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Plane {
+    pub point: nalgebra::Vector3<f32>,
+    pub normal: nalgebra::Vector3<f32>,
+    pub material: Material,
+}
+
+/// Distance along the ray to the plane through `point` facing `normal` (unit length),
+/// or `None` if the plane is behind the ray or the ray runs alongside it
+pub fn plane_hit(
+    point: &nalgebra::Vector3<f32>,
+    normal: &nalgebra::Vector3<f32>,
+    ray: &Ray,
+) -> Option<f32> {
+    let denominator = ray.direction.dot(normal);
+    if denominator.abs() < PARALLEL_EPSILON {
+        return None;
+    }
+    let t = (point - ray.origin).dot(normal) / denominator;
+    if t <= 0.0 {
+        return None;
+    }
+    Some(t)
+}
+
+impl Intersect for Plane {
+    fn test_intersection(&self, ray: &Ray) -> Intersection {
+        let normal = self.normal.normalize();
+        let Some(t) = plane_hit(&self.point, &normal, ray) else {
+            return Intersection::new(Colour::from_gray(0.0), None, None);
+        };
+
+        let normal_ray = Ray::new(ray.at_point(t), normal);
+        Intersection::new(
+            self.material.colour_at(&normal_ray.origin, None),
+            Some(t),
+            Some(normal_ray),
+        )
+        .with_material(self.material.clone())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
+
+    use super::*;
+
+    #[test]
+    fn downward_ray_hits_the_ground_where_expected() {
+        let ground = Plane {
+            point: Vector3::new(0.0, 0.0, -1.0),
+            normal: Vector3::z(),
+            material: Material::matte(Colour::from_gray(1.0)),
+        };
+        let ray = Ray::new(Vector3::new(2.0, 3.0, 4.0), -Vector3::z());
+        let hit = ground.test_intersection(&ray);
+        assert_eq!(hit.distance, Some(5.0));
+        assert_eq!(hit.normal.unwrap().origin, Vector3::new(2.0, 3.0, -1.0));
+
+        let sideways = Ray::new(Vector3::new(2.0, 3.0, 4.0), Vector3::x());
+        assert_eq!(ground.test_intersection(&sideways).distance, None);
+    }
+}
@@ -0,0 +1,236 @@
+use std::{
+    sync::{
+        atomic::{AtomicU64, Ordering},
+        mpsc::{self, Receiver, Sender},
+        Arc,
+    },
+    thread,
+};
+
+use eframe::egui;
+use rayon::prelude::*;
+
+use cgraphics::{
+    camera::Camera,
+    colour::Colour,
+    renderer::RenderConfig,
+    reproject::{self, GBuffer},
+    scene::Scene,
+    tile::{self, Tile},
+    timing::{self, FrameTiming},
+};
+
+/// Work to be done away from the UI thread
+enum Job {
+    Sample(SampleJob),
+    Reproject(ReprojectJob),
+}
+
+/// One sample of every pixel
+struct SampleJob {
+    generation: u64,
+    camera: Camera,
+    scene: Arc<Scene>,
+    config: RenderConfig,
+    sample_index: u32,
+    repaint: egui::Context, // woken after every tile so it shows up straight away
+}
+
+/// Moving accumulated colours from one view to another, which needs a ray cast for
+/// every pixel of the new view and maybe the old one too
+struct ReprojectJob {
+    generation: u64,
+    from: Camera,
+    previous: Option<Arc<GBuffer>>, // of `from`, captured here if there isn't one yet
+    to: Camera,
+    scene: Arc<Scene>,
+    colours: Vec<Vec<Colour>>, // what `from` has accumulated
+    repaint: egui::Context,
+}
+
+/// Accumulated colours moved to a new view, for the view most recently asked about
+pub struct Reprojected {
+    generation: u64,
+    pub camera: Camera,
+    pub history: Vec<Vec<Option<Colour>>>, // see `reproject::reproject`
+    pub gbuffer: Arc<GBuffer>,             // of `camera`, for moving on from it next time
+}
+
+/// A finished tile of the sample most recently asked for
+pub struct RenderedTile {
+    generation: u64,
+    tile: Tile,
+    pixels: Vec<Colour>, // row by row, like `Tile::pixels`
+    pub timing: FrameTiming,
+}
+
+impl RenderedTile {
+    pub fn copy_into(&self, buffer: &mut [Vec<Colour>]) {
+        let rows = buffer[self.tile.y as usize..].iter_mut();
+        for (row, pixels) in rows.zip(self.pixels.chunks(self.tile.width as usize)) {
+            row[self.tile.x as usize..][..pixels.len()].copy_from_slice(pixels);
+        }
+    }
+}
+
+/// Renders samples on a background thread, streaming tiles back as they finish so the
+/// UI never waits on a whole frame. Asking for a new sample abandons the one in flight,
+/// and tiles already on their way from it are thrown away rather than handed out
+pub struct RenderThread {
+    jobs: Sender<Job>,
+    tiles: Receiver<RenderedTile>,
+    reprojections: Receiver<Reprojected>,
+    generation: Arc<AtomicU64>, // bumped for every job, the worker gives up on older ones
+}
+
+impl RenderThread {
+    pub fn spawn() -> RenderThread {
+        let (jobs, job_receiver) = mpsc::channel::<Job>();
+        let (tile_sender, tiles) = mpsc::channel();
+        let (reprojection_sender, reprojections) = mpsc::channel();
+        let generation = Arc::new(AtomicU64::new(0));
+
+        let current = generation.clone();
+        thread::Builder::new()
+            .name("render".to_string())
+            .spawn(move || {
+                // stops once the app drops its end of the job channel
+                while let Ok(mut job) = job_receiver.recv() {
+                    // only the newest of any jobs that piled up is worth rendering
+                    while let Ok(newer) = job_receiver.try_recv() {
+                        job = newer;
+                    }
+                    match job {
+                        Job::Sample(job) => render_job(&job, &current, &tile_sender),
+                        Job::Reproject(job) => reproject_job(job, &current, &reprojection_sender),
+                    }
+                }
+            })
+            .expect("failed to spawn the render thread");
+
+        RenderThread {
+            jobs,
+            tiles,
+            reprojections,
+            generation,
+        }
+    }
+
+    /// Starts rendering the `sample_index`th sample, abandoning whatever was in flight.
+    /// Returns how many tiles it will arrive in
+    pub fn render_sample(
+        &self,
+        camera: &Camera,
+        scene: &Arc<Scene>,
+        config: &RenderConfig,
+        sample_index: u32,
+        repaint: &egui::Context,
+    ) -> usize {
+        let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+        let job = Job::Sample(SampleJob {
+            generation,
+            camera: camera.clone(),
+            scene: Arc::clone(scene), // another reference to the same scene, nothing is copied
+            config: *config,
+            sample_index,
+            repaint: repaint.clone(),
+        });
+        let tile_count = tile::tiles(camera.width, camera.height, config.tile_size).len();
+        if self.jobs.send(job).is_err() {
+            log::error!("the render thread has stopped");
+        }
+        tile_count
+    }
+
+    /// Starts moving `colours`, accumulated from `from`, over to the view from `to`,
+    /// abandoning whatever was in flight. The result turns up in `finished_reprojection`
+    pub fn reproject(
+        &self,
+        from: &Camera,
+        previous: Option<Arc<GBuffer>>,
+        to: &Camera,
+        scene: &Arc<Scene>,
+        colours: &[Vec<Colour>],
+        repaint: &egui::Context,
+    ) {
+        let generation = self.generation.fetch_add(1, Ordering::SeqCst) + 1;
+        let job = Job::Reproject(ReprojectJob {
+            generation,
+            from: from.clone(),
+            previous,
+            to: to.clone(),
+            scene: Arc::clone(scene),
+            colours: colours.to_vec(),
+            repaint: repaint.clone(),
+        });
+        if self.jobs.send(job).is_err() {
+            log::error!("the render thread has stopped");
+        }
+    }
+
+    /// Abandons the sample in flight without starting another
+    pub fn cancel(&self) {
+        self.generation.fetch_add(1, Ordering::SeqCst);
+    }
+
+    /// Tiles of the latest sample that have finished since the last call
+    pub fn finished_tiles(&self) -> Vec<RenderedTile> {
+        let generation = self.generation.load(Ordering::SeqCst);
+        self.tiles
+            .try_iter()
+            .filter(|tile| tile.generation == generation)
+            .collect()
+    }
+
+    /// The reprojection asked for last, once it's done
+    pub fn finished_reprojection(&self) -> Option<Reprojected> {
+        let generation = self.generation.load(Ordering::SeqCst);
+        self.reprojections
+            .try_iter()
+            .filter(|reprojected| reprojected.generation == generation)
+            .last()
+    }
+}
+
+fn render_job(job: &SampleJob, current: &AtomicU64, tiles: &Sender<RenderedTile>) {
+    let camera = &job.camera;
+    tile::tiles(camera.width, camera.height, job.config.tile_size)
+        .into_par_iter()
+        .for_each(|tile| {
+            if current.load(Ordering::SeqCst) != job.generation {
+                return;
+            }
+            let (pixels, timing) = timing::time_render(|| {
+                camera.render_tile(&job.scene, &job.config, job.sample_index, &tile)
+            });
+            let rendered = RenderedTile {
+                generation: job.generation,
+                tile,
+                pixels,
+                timing,
+            };
+            if tiles.send(rendered).is_ok() {
+                job.repaint.request_repaint();
+            }
+        });
+}
+
+fn reproject_job(job: ReprojectJob, current: &AtomicU64, reprojections: &Sender<Reprojected>) {
+    let previous = job
+        .previous
+        .unwrap_or_else(|| Arc::new(GBuffer::capture(&job.from, &job.scene)));
+    if current.load(Ordering::SeqCst) != job.generation {
+        return;
+    }
+    let gbuffer = GBuffer::capture(&job.to, &job.scene);
+    let history = reproject::reproject(&previous, &gbuffer, &job.colours);
+    let reprojected = Reprojected {
+        generation: job.generation,
+        camera: job.to,
+        history,
+        gbuffer: Arc::new(gbuffer),
+    };
+    if reprojections.send(reprojected).is_ok() {
+        job.repaint.request_repaint();
+    }
+}
@@ -1,14 +1,31 @@
 use std::cmp::Ordering;
 
-use eframe::egui::Rgba;
-use nalgebra::{self, Vector3};
+use nalgebra::Vector3;
+use serde::{Deserialize, Serialize};
 
+use crate::colour::Colour;
 use crate::intersect::{Intersect, Intersection};
+use crate::material::Material;
+use crate::math;
+use crate::path_tracer;
+use crate::rng;
+use crate::scene::Scene;
 use crate::sphere::Sphere;
+use crate::timing;
+
+pub const RAY_EPSILON: f32 = 1e-3; // how far bounced rays start off the surface to avoid hitting it again
+pub const SHADOW_EPSILON: f32 = 1e-3; // raise this if lit surfaces get speckled with shadow acne
+const SOFT_SHADOW_ANGLE: f32 = 0.05; // radians shadow rays spread from the light with `shadow_samples`
+const WIREFRAME_WIDTH: f32 = 0.02; // barycentric weight below which a pixel counts as on an edge
+const WIREFRAME_TINT: f32 = 0.8;
+const WIREFRAME_COLOUR: Colour = Colour::from_rgb(1.0, 0.5, 0.0);
+const TOON_OUTLINE_ANGLE: f32 = 0.004; // radians off the primary ray at which outline probes look, about a line's width
+const TOON_OUTLINE_DEPTH: f32 = 0.02; // how far off the surface's plane a probe may land, relative to distance
+const TOON_HIGHLIGHT: f32 = 0.5; // specular brightness above which a toon highlight shows
+const FOG_MISS_DISTANCE: f32 = 100.0; // how much fog lies between the camera and the background
 
-#[allow(dead_code)]
 #[allow(non_snake_case)]
-pub fn single_sphere(x: f32, y: f32) -> Rgba {
+pub fn single_sphere(x: f32, y: f32) -> Colour {
     // https://www.scratchapixel.com/lessons/3d-basic-rendering/minimal-ray-tracer-rendering-simple-shapes/ray-Sphere-intersection.html
     let C = nalgebra::Vector3::new(2.0, 0.0, 0.0); // center of Sphere
     let r = 0.5;
@@ -18,34 +35,34 @@ pub fn single_sphere(x: f32, y: f32) -> Rgba {
     let t_ca = L.dot(&D);
 
     if t_ca < 0.0 {
-        return Rgba::from_gray(1.0);
+        return Colour::from_gray(1.0);
     }
 
     let CLApp = O + D * t_ca; // closest approach
     let distance = (CLApp - C).norm();
     if distance < r {
-        return Rgba::from_rgb(CLApp.x, CLApp.y, CLApp.z);
+        return Colour::from_rgb(CLApp.x, CLApp.y, CLApp.z);
     }
-    return Rgba::from_gray(1.0);
+    return Colour::from_gray(1.0);
 }
 
-#[allow(dead_code)]
-pub fn coordinates(x: f32, y: f32) -> Rgba {
-    return Rgba::from_rgb(x, y, 0.0);
+pub fn coordinates(x: f32, y: f32) -> Colour {
+    return Colour::from_rgb(x, y, 0.0);
 }
 
-#[allow(dead_code)]
-pub fn many_spheres(x: f32, y: f32) -> Rgba {
+pub fn many_spheres(x: f32, y: f32) -> Colour {
     let first_sphere = Sphere {
         origin: nalgebra::Vector3::new(3.0, 1.5, 3.0),
         radius: 0.75,
-        colour: Rgba::from_white_alpha(1.0),
+        velocity: None,
+        material: Material::matte(Colour::from_white_alpha(1.0)),
     };
 
     let second_sphere = Sphere {
         origin: nalgebra::Vector3::new(3.0, -1.5, 3.0),
         radius: 0.75,
-        colour: Rgba::from_white_alpha(1.0),
+        velocity: None,
+        material: Material::matte(Colour::from_white_alpha(1.0)),
     };
 
     let mut objects = vec![first_sphere, second_sphere];
@@ -53,43 +70,50 @@ pub fn many_spheres(x: f32, y: f32) -> Rgba {
     objects.push(Sphere {
         origin: nalgebra::Vector3::new(3.0, -3.0, 0.0),
         radius: 0.5,
-        colour: Rgba::from_white_alpha(1.0),
+        velocity: None,
+        material: Material::matte(Colour::from_white_alpha(1.0)),
     });
 
     objects.push(Sphere {
         origin: nalgebra::Vector3::new(3.0, 3.0, 0.0),
         radius: 0.5,
-        colour: Rgba::from_white_alpha(1.0),
+        velocity: None,
+        material: Material::matte(Colour::from_white_alpha(1.0)),
     });
 
     objects.push(Sphere {
         origin: nalgebra::Vector3::new(3.0, 2.0, -1.0),
         radius: 0.5,
-        colour: Rgba::from_white_alpha(1.0),
+        velocity: None,
+        material: Material::matte(Colour::from_white_alpha(1.0)),
     });
 
     objects.push(Sphere {
         origin: nalgebra::Vector3::new(3.0, -2.0, -1.0),
         radius: 0.5,
-        colour: Rgba::from_white_alpha(1.0),
+        velocity: None,
+        material: Material::matte(Colour::from_white_alpha(1.0)),
     });
 
     objects.push(Sphere {
         origin: nalgebra::Vector3::new(3.0, -1.0, -1.0),
         radius: 0.5,
-        colour: Rgba::from_white_alpha(1.0),
+        velocity: None,
+        material: Material::matte(Colour::from_white_alpha(1.0)),
     });
 
     objects.push(Sphere {
         origin: nalgebra::Vector3::new(3.0, 1.0, -1.0),
         radius: 0.5,
-        colour: Rgba::from_white_alpha(1.0),
+        velocity: None,
+        material: Material::matte(Colour::from_white_alpha(1.0)),
     });
 
     objects.push(Sphere {
         origin: nalgebra::Vector3::new(3.0, 0.0, -1.0),
         radius: 0.5,
-        colour: Rgba::from_white_alpha(1.0),
+        velocity: None,
+        material: Material::matte(Colour::from_white_alpha(1.0)),
     });
 
     let direction = nalgebra::Vector3::new(0.5, y, x + 0.1);
@@ -112,33 +136,826 @@ pub fn many_spheres(x: f32, y: f32) -> Rgba {
         }
         return Ordering::Greater;
     });
-    if let Some(_) = unsorted[0].normal {
-        println!("{:?}", unsorted[0].normal);
+    if unsorted[0].normal.is_some() {
+        log::debug!("{:?}", unsorted[0].normal);
         return unsorted[0].colour;
     } else {
-        return unsorted[0].colour + Rgba::from_gray(0.8);
+        return unsorted[0].colour + Colour::from_gray(0.8);
     }
 }
 
-#[derive(Debug, Clone, Copy)]
+#[derive(Debug, Clone, Copy, Serialize, Deserialize)]
 pub struct Ray {
     pub origin: Vector3<f32>,
     pub direction: Vector3<f32>,
+    #[serde(default)]
+    pub time: f32, // when in the exposure it was sent, 0..1, moving objects are seen where they are then
 }
 
 impl Ray {
+    /// Ray along the normalised `direction`. A direction too short to normalise would
+    /// turn every intersection test into NaNs, so it's swapped for +x instead
     pub fn new(origin: Vector3<f32>, direction: Vector3<f32>) -> Ray {
-        Ray {
-            direction: direction / direction.norm(),
+        Ray::new_normalized(origin, direction).unwrap_or(Ray {
             origin,
+            direction: Vector3::x(),
+            time: 0.0,
+        })
+    }
+
+    /// Like `new`, but refuses a zero length direction rather than picking one
+    pub fn new_normalized(origin: Vector3<f32>, direction: Vector3<f32>) -> Result<Ray, String> {
+        match direction.try_normalize(f32::EPSILON) {
+            Some(direction) => Ok(Ray {
+                origin,
+                direction,
+                time: 0.0,
+            }),
+            None => Err(format!("ray direction {:?} has no length", direction)),
         }
     }
 
     pub fn new_preserve(origin: Vector3<f32>, direction: Vector3<f32>) -> Ray {
-        Ray { direction, origin }
+        Ray {
+            direction,
+            origin,
+            time: 0.0,
+        }
+    }
+
+    /// The same ray sent at another moment, which secondary rays take from their parent
+    pub fn with_time(mut self, time: f32) -> Ray {
+        self.time = time;
+        self
     }
 
     pub fn at_point(&self, t: f32) -> Vector3<f32> {
         self.origin + (self.direction * t)
     }
 }
+
+pub fn reflect(direction: &Vector3<f32>, normal: &Vector3<f32>) -> Vector3<f32> {
+    direction - normal * (2.0 * direction.dot(normal))
+}
+
+/// Mirror reflection nudged by a random amount up to `roughness`, giving blurry
+/// reflections once many samples are averaged. Exactly `reflect` at roughness 0, and
+/// never sent back into the surface
+pub fn rough_reflect(
+    direction: &Vector3<f32>,
+    normal: &Vector3<f32>,
+    roughness: f32,
+) -> Vector3<f32> {
+    let mirrored = reflect(direction, normal);
+    if roughness <= 0.0 {
+        return mirrored;
+    }
+    let perturbed = mirrored + math::random_unit_vector(rng::random) * roughness;
+    if perturbed.dot(normal) <= 0.0 {
+        return mirrored;
+    }
+    perturbed.normalize()
+}
+
+/// Bends a ray through an interface with Snell's law, `eta` being the ratio of the
+/// index being left to the index being entered and `normal` facing the incoming ray.
+/// Returns `None` on total internal reflection
+pub fn refract(direction: &Vector3<f32>, normal: &Vector3<f32>, eta: f32) -> Option<Vector3<f32>> {
+    let cos_incident = -direction.dot(normal);
+    let sin2_transmitted = eta.powi(2) * (1.0 - cos_incident.powi(2));
+    if sin2_transmitted > 1.0 {
+        return None;
+    }
+    let cos_transmitted = (1.0 - sin2_transmitted).sqrt();
+    Some(direction * eta + normal * (eta * cos_incident - cos_transmitted))
+}
+
+/// What each primary ray is turned into. Normals, albedo, depth and wireframe are for
+/// debugging geometry
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum RenderMode {
+    Shaded,
+    Normals, // surface normal mapped from -1..1 onto 0..1 RGB, so flipped normals stand out
+    Albedo,  // unlit surface colour, what a denoiser is given alongside the normals
+    Depth,   // hit distance as grey, white at `depth_near` fading to black at `depth_far`
+    PathTraced, // noisy global illumination lit by emissive surfaces and the background
+    Wireframe, // shaded, with triangle edges drawn over the top to check how meshes were split
+    Toon,    // cartoon look, flat bands of light with outlined silhouettes
+}
+
+impl RenderMode {
+    pub fn next(self) -> RenderMode {
+        match self {
+            RenderMode::Shaded => RenderMode::Normals,
+            RenderMode::Normals => RenderMode::Albedo,
+            RenderMode::Albedo => RenderMode::Depth,
+            RenderMode::Depth => RenderMode::PathTraced,
+            RenderMode::PathTraced => RenderMode::Wireframe,
+            RenderMode::Wireframe => RenderMode::Toon,
+            RenderMode::Toon => RenderMode::Shaded,
+        }
+    }
+
+    /// The function run for every primary ray in this mode, picked once per frame
+    /// rather than matched on for every pixel
+    pub fn integrator(self) -> Integrator {
+        match self {
+            RenderMode::Shaded => shaded,
+            RenderMode::Normals => normals,
+            RenderMode::Albedo => albedo,
+            RenderMode::Depth => depth,
+            RenderMode::PathTraced => path_tracer::path_traced,
+            RenderMode::Wireframe => wireframe,
+            RenderMode::Toon => toon,
+        }
+    }
+}
+
+pub type Integrator = fn(&Scene, Ray, &RenderConfig) -> Colour;
+
+/// Settings that change how a frame is rendered but not what is in it
+#[derive(Clone, Copy, Debug)]
+pub struct RenderConfig {
+    pub mode: RenderMode,
+    pub max_depth: u32, // bounces before reflection and refraction stop, 0 for none at all
+    pub depth_near: f32, // distances mapped to white and black in `RenderMode::Depth`
+    pub depth_far: f32,
+    pub ao_samples: u32,     // rays per hit for ambient occlusion, 0 turns it off
+    pub ao_radius: f32,      // geometry further away than this doesn't occlude
+    pub roulette_depth: u32, // bounces a path tracer path is guaranteed before it may be cut short
+    pub tile_size: u32,      // side of the square blocks of pixels handed to each thread
+    pub seed: u64,           // every random choice follows from this, see `rng::seed_pixel`
+    pub near: f32, // primary rays only hit between these, the camera sets them from its clip planes
+    pub far: f32,
+    pub toon_bands: u32, // levels the diffuse light is snapped to in `RenderMode::Toon`
+    pub toon_outline: bool, // whether `RenderMode::Toon` draws silhouettes in black
+    pub shadow_samples: u32, // spread out rays per shadow test of a point light, 1 for hard edges
+    // x0, y0, x1, y1 of the only pixels the whole buffer renders fill in, the far edges
+    // exclusive. Everything outside is left as it was
+    pub crop: Option<(u32, u32, u32, u32)>,
+}
+
+impl Default for RenderConfig {
+    fn default() -> Self {
+        RenderConfig {
+            mode: RenderMode::Shaded,
+            max_depth: 8,
+            depth_near: 0.0,
+            depth_far: 30.0,
+            ao_samples: 0,
+            ao_radius: 1.0,
+            roulette_depth: 3,
+            tile_size: 32,
+            seed: 0,
+            near: 0.0,
+            far: f32::INFINITY,
+            toon_bands: 3,
+            toon_outline: true,
+            shadow_samples: 1,
+            crop: None,
+        }
+    }
+}
+
+impl RenderConfig {
+    /// Whether pixel (x, y) is inside `crop`, which without one is everywhere
+    pub fn in_crop(&self, x: u32, y: u32) -> bool {
+        self.crop
+            .is_none_or(|(x0, y0, x1, y1)| (x0..x1).contains(&x) && (y0..y1).contains(&y))
+    }
+}
+
+fn shaded(scene: &Scene, ray: Ray, config: &RenderConfig) -> Colour {
+    trace(scene, ray, 0, config)
+}
+
+/// What the camera sees along a primary ray, within the clip range
+pub fn primary_hit(scene: &Scene, ray: &Ray, config: &RenderConfig) -> Intersection {
+    scene.nearest_within(ray, config.near, config.far)
+}
+
+fn normals(scene: &Scene, ray: Ray, config: &RenderConfig) -> Colour {
+    let Some(normal) = primary_hit(scene, &ray, config).normal else {
+        return Colour::from_gray(0.0);
+    };
+    let n = normal.direction * 0.5 + Vector3::repeat(0.5);
+    Colour::from_rgb(n.x, n.y, n.z)
+}
+
+fn albedo(scene: &Scene, ray: Ray, config: &RenderConfig) -> Colour {
+    let intersection = primary_hit(scene, &ray, config);
+    if intersection.distance.is_none() {
+        return Colour::from_gray(0.0);
+    }
+    intersection.colour
+}
+
+fn depth(scene: &Scene, ray: Ray, config: &RenderConfig) -> Colour {
+    let Some(distance) = primary_hit(scene, &ray, config).distance else {
+        return Colour::from_gray(0.0);
+    };
+    // the shading runs from white to black over whatever of the range isn't clipped
+    let near = config.depth_near.max(config.near);
+    let far = config.depth_far.min(config.far);
+    let range = (far - near).max(f32::EPSILON);
+    Colour::from_gray(1.0 - ((distance - near) / range).clamp(0.0, 1.0))
+}
+
+/// Shaded colour, tinted wherever the nearest hit is a triangle and one of its barycentric
+/// weights is close to 0, i.e. near an edge. The lines are a fixed fraction of each
+/// triangle rather than a fixed number of pixels, so big triangles get thick edges
+fn wireframe(scene: &Scene, ray: Ray, config: &RenderConfig) -> Colour {
+    let colour = trace(scene, ray, 0, config);
+    let near_edge = primary_hit(scene, &ray, config)
+        .barycentric
+        .is_some_and(|weights| weights.min() < WIREFRAME_WIDTH);
+    if !near_edge {
+        return colour;
+    }
+    colour * (1.0 - WIREFRAME_TINT) + WIREFRAME_COLOUR * WIREFRAME_TINT
+}
+
+/// Cel shading: the same lights as `Shaded`, but with the diffuse term snapped to
+/// `toon_bands` flat levels, highlights either fully on or off, and outlines in black
+/// along silhouettes and creases
+fn toon(scene: &Scene, ray: Ray, config: &RenderConfig) -> Colour {
+    let intersection = primary_hit(scene, &ray, config);
+    let (Some(distance), Some(normal)) = (intersection.distance, intersection.normal) else {
+        return scene.fog(scene.background(&ray.direction), FOG_MISS_DISTANCE);
+    };
+    if config.toon_outline && on_outline(scene, &ray, &normal, distance, config) {
+        return Colour::from_gray(0.0);
+    }
+
+    let (diffuse, specular) = match shading_normal(&ray, &intersection, &normal) {
+        Some(shading) => direct_lighting(scene, &ray, &intersection, &shading, config),
+        None => (Colour::from_gray(0.0), Colour::from_gray(0.0)),
+    };
+    // banded on the brightest channel and scaled back, so coloured lights keep their tint
+    let level = diffuse.r().max(diffuse.g()).max(diffuse.b());
+    let banded = if level > 0.0 {
+        diffuse * (quantize(level, config.toon_bands) / level)
+    } else {
+        diffuse
+    };
+    let highlight = if specular.r().max(specular.g()).max(specular.b()) > TOON_HIGHLIGHT {
+        Colour::from_gray(intersection.material.specular)
+    } else {
+        Colour::from_gray(0.0)
+    };
+    let shaded = intersection.colour * (scene.ambient() + banded) + highlight;
+    let emission = intersection.material.emission;
+    let colour = Colour::from_rgb(
+        shaded.r() + emission.r(),
+        shaded.g() + emission.g(),
+        shaded.b() + emission.b(),
+    );
+    scene.fog(colour, distance)
+}
+
+/// Whether rays just beside this one land somewhere other than the plane the hit lies
+/// in: past the edge of the object, on something in front or behind, or across a fold
+fn on_outline(
+    scene: &Scene,
+    ray: &Ray,
+    normal: &Ray,
+    distance: f32,
+    config: &RenderConfig,
+) -> bool {
+    let (tangent, bitangent) = math::onb_from_normal(&ray.direction);
+    let offset = TOON_OUTLINE_ANGLE.tan();
+    [tangent, -tangent, bitangent, -bitangent]
+        .iter()
+        .any(|side| {
+            let probe = Ray::new(ray.origin, ray.direction + side * offset).with_time(ray.time);
+            let Some(probe_distance) = primary_hit(scene, &probe, config).distance else {
+                return true;
+            };
+            let off_plane = (probe.at_point(probe_distance) - normal.origin).dot(&normal.direction);
+            off_plane.abs() > distance * TOON_OUTLINE_DEPTH
+        })
+}
+
+/// `value` clamped to 0..1 and snapped to one of `bands` evenly spaced levels, running
+/// from 0 for the darkest band to 1 for the brightest. A single band is always fully lit
+pub fn quantize(value: f32, bands: u32) -> f32 {
+    if bands <= 1 {
+        return 1.0;
+    }
+    let band = (value.clamp(0.0, 1.0) * bands as f32)
+        .floor()
+        .min((bands - 1) as f32);
+    band / (bands - 1) as f32
+}
+
+/// Schlick's approximation of the fraction of light reflected rather than refracted at
+/// an interface, `eta` being the ratio of indices as in `refract`. Leaving a denser medium
+/// the angle that matters is the transmitted one, and past the critical angle everything
+/// is reflected
+pub fn schlick(direction: &Vector3<f32>, normal: &Vector3<f32>, eta: f32) -> f32 {
+    let mut cosine = -direction.dot(normal);
+    if eta > 1.0 {
+        let sin2_transmitted = eta.powi(2) * (1.0 - cosine.powi(2));
+        if sin2_transmitted > 1.0 {
+            return 1.0;
+        }
+        cosine = (1.0 - sin2_transmitted).sqrt();
+    }
+    let r0 = ((1.0 - eta) / (1.0 + eta)).powi(2);
+    r0 + (1.0 - r0) * (1.0 - cosine).powi(5)
+}
+
+/// Follows a ray into the scene, bouncing off reflective surfaces and through transparent
+/// ones until `config.max_depth` is reached
+pub fn trace(scene: &Scene, ray: Ray, depth: u32, config: &RenderConfig) -> Colour {
+    // only what the camera sees directly is clipped, reflections still see everything
+    let intersection = if depth == 0 {
+        primary_hit(scene, &ray, config)
+    } else {
+        scene.nearest_intersection(&ray)
+    };
+    let (Some(distance), Some(normal)) = (intersection.distance, intersection.normal) else {
+        return scene.fog(scene.background(&ray.direction), FOG_MISS_DISTANCE);
+    };
+    let colour = trace_hit(scene, &ray, &intersection, &normal, depth, config);
+    scene.fog(colour, distance)
+}
+
+/// Colour of the surface a ray hit, before any fog between it and the ray's origin
+fn trace_hit(
+    scene: &Scene,
+    ray: &Ray,
+    intersection: &Intersection,
+    normal: &Ray,
+    depth: u32,
+    config: &RenderConfig,
+) -> Colour {
+    let material = &intersection.material;
+    let mut local = match shading_normal(ray, intersection, normal) {
+        Some(shading) => {
+            let mut lit = shade(scene, ray, intersection, &shading, config);
+            if config.ao_samples > 0 {
+                lit = lit
+                    * ambient_occlusion(
+                        scene,
+                        &shading.at_point(SHADOW_EPSILON),
+                        &shading.direction,
+                        config.ao_samples,
+                        config.ao_radius,
+                        ray.time,
+                    );
+            }
+            lit
+        }
+        None => Colour::from_gray(0.0),
+    };
+    // added on rgb only, the emission's alpha would otherwise stack onto the surface's
+    let emission = material.emission;
+    local = Colour::from_rgb(
+        local.r() + emission.r(),
+        local.g() + emission.g(),
+        local.b() + emission.b(),
+    );
+    if (material.reflectivity <= 0.0 && material.transparency <= 0.0) || depth >= config.max_depth {
+        return local;
+    }
+
+    // normals point out of the surface, so a ray leaving an object sees the back of it
+    let exiting = !intersection.front_face;
+    let facing = if exiting {
+        -normal.direction
+    } else {
+        normal.direction
+    };
+    let reflected_ray = Ray::new(
+        normal.origin + facing * RAY_EPSILON,
+        reflect(&ray.direction, &facing),
+    )
+    .with_time(ray.time);
+
+    let mut colour = local * (1.0 - material.reflectivity - material.transparency).max(0.0);
+    if material.reflectivity > 0.0 {
+        let glossy_ray = Ray::new(
+            reflected_ray.origin,
+            rough_reflect(&ray.direction, &facing, material.roughness),
+        )
+        .with_time(ray.time);
+        let reflected = trace(scene, glossy_ray, depth + 1, config);
+        colour = colour + reflected * material.reflectivity;
+    }
+    if material.transparency > 0.0 {
+        let eta = if exiting {
+            material.ior
+        } else {
+            1.0 / material.ior
+        };
+        // glass reflects more the closer to grazing it's seen, leaving less to pass through
+        let fresnel = schlick(&ray.direction, &facing, eta);
+        let transmitted = match refract(&ray.direction, &facing, eta) {
+            Some(direction) if fresnel < 1.0 => {
+                let transmitted_ray =
+                    Ray::new(normal.origin - facing * RAY_EPSILON, direction).with_time(ray.time);
+                trace(scene, transmitted_ray, depth + 1, config) * (1.0 - fresnel)
+            }
+            _ => Colour::from_gray(0.0),
+        };
+        let surface_reflection = trace(scene, reflected_ray, depth + 1, config) * fresnel;
+        colour = colour + (transmitted + surface_reflection) * material.transparency;
+    }
+    colour
+}
+
+/// The normal to light a hit with. On the back of a two sided material it's turned round
+/// to face the ray, on the back of a one sided one there's none and the hit stays unlit
+pub fn shading_normal(ray: &Ray, intersection: &Intersection, normal: &Ray) -> Option<Ray> {
+    if ray.direction.dot(&normal.direction) <= 0.0 {
+        return Some(*normal);
+    }
+    if !intersection.material.two_sided {
+        return None;
+    }
+    Some(Ray::new_preserve(normal.origin, -normal.direction))
+}
+
+/// Blinn-Phong shading of a surface, summing every light in the scene that it can see.
+/// The diffuse part is the energy conserving Lambertian albedo / pi: a light's intensity,
+/// like the ambient, is the radiance a white surface facing it gives back, so the
+/// irradiance it delivers is pi times that and the pi cancels. A white surface lit only
+/// by an ambient matching the background disappears into it, as it does in path mode
+fn shade(
+    scene: &Scene,
+    ray: &Ray,
+    intersection: &Intersection,
+    normal: &Ray,
+    config: &RenderConfig,
+) -> Colour {
+    let (diffuse_lighting, specular_lighting) =
+        direct_lighting(scene, ray, intersection, normal, config);
+    let shaded = intersection.colour * (scene.ambient() + diffuse_lighting) + specular_lighting;
+    Colour::from_rgb(shaded.r(), shaded.g(), shaded.b())
+}
+
+/// Blinn-Phong light reaching a surface from every light that isn't shadowed, split into
+/// the diffuse term, still to be multiplied by the surface colour, and the highlights
+fn direct_lighting(
+    scene: &Scene,
+    ray: &Ray,
+    intersection: &Intersection,
+    normal: &Ray,
+    config: &RenderConfig,
+) -> (Colour, Colour) {
+    let material = &intersection.material;
+    let mut diffuse_lighting = Colour::from_gray(0.0);
+    let mut specular_lighting = Colour::from_gray(0.0);
+    let shadow_origin = normal.at_point(SHADOW_EPSILON);
+    for light in scene.lights() {
+        let samples = light.samples();
+        // area lights soften their own shadows by being sampled all over
+        let shadow_samples = if samples == 1 {
+            config.shadow_samples
+        } else {
+            1
+        };
+        for sample in 0..samples {
+            let illumination = light.illuminate(&shadow_origin, sample);
+            let shadow_ray =
+                Ray::new_preserve(shadow_origin, illumination.to_light).with_time(ray.time);
+            let visible = visibility(scene, &shadow_ray, illumination.distance, shadow_samples);
+            if visible == 0.0 {
+                continue;
+            }
+            let intensity = illumination.intensity * visible / samples as f32;
+            let diffuse = normal.direction.dot(&shadow_ray.direction).max(0.0);
+            diffuse_lighting = diffuse_lighting + illumination.colour * (diffuse * intensity);
+
+            if material.specular > 0.0 && diffuse > 0.0 {
+                let half_vector = (shadow_ray.direction - ray.direction).normalize();
+                let highlight = normal
+                    .direction
+                    .dot(&half_vector)
+                    .max(0.0)
+                    .powf(material.shininess);
+                specular_lighting = specular_lighting
+                    + illumination.colour * (material.specular * highlight * intensity);
+            }
+        }
+    }
+    (diffuse_lighting, specular_lighting)
+}
+
+/// Fraction of `samples` shadow rays that reach a light `distance` along `shadow_ray`,
+/// each turned a little off it at random so the edges of shadows blur, as if the light
+/// had some size. A single sample goes straight at it, so is either 0 or 1
+fn visibility(scene: &Scene, shadow_ray: &Ray, distance: f32, samples: u32) -> f32 {
+    if samples <= 1 {
+        return if timing::shadow(|| scene.occluded(shadow_ray, SHADOW_EPSILON, distance)) {
+            0.0
+        } else {
+            1.0
+        };
+    }
+    let (tangent, bitangent) = math::onb_from_normal(&shadow_ray.direction);
+    let spread = SOFT_SHADOW_ANGLE.tan();
+    let open = (0..samples)
+        .filter(|_| {
+            let (x, y) = math::random_in_disk(rng::random);
+            let direction =
+                (shadow_ray.direction + (tangent * x + bitangent * y) * spread).normalize();
+            let jittered =
+                Ray::new_preserve(shadow_ray.origin, direction).with_time(shadow_ray.time);
+            !timing::shadow(|| scene.occluded(&jittered, SHADOW_EPSILON, distance))
+        })
+        .count();
+    open as f32 / samples as f32
+}
+
+/// Fraction of `samples` rays sent out over the hemisphere around `normal` that escape
+/// without hitting anything within `radius` at `time`. 1 means fully open, 0 fully enclosed
+pub fn ambient_occlusion(
+    scene: &Scene,
+    point: &Vector3<f32>,
+    normal: &Vector3<f32>,
+    samples: u32,
+    radius: f32,
+    time: f32,
+) -> f32 {
+    if samples == 0 {
+        return 1.0;
+    }
+    let open = (0..samples)
+        .filter(|_| {
+            let direction = math::uniform_sample_hemisphere(normal, rng::random);
+            !scene.occluded(
+                &Ray::new_preserve(*point, direction).with_time(time),
+                SHADOW_EPSILON,
+                radius,
+            )
+        })
+        .count();
+    open as f32 / samples as f32
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{intersect::Intersectable, light::Light};
+
+    /// A grey ball at the origin in front of everything
+    fn ball(material: Material, lights: Vec<Light>) -> Scene {
+        let sphere = Intersectable::Sphere(Sphere {
+            origin: Vector3::zeros(),
+            radius: 1.0,
+            velocity: None,
+            material,
+        });
+        Scene::new(vec![sphere], lights).with_ambient(Colour::from_gray(0.1))
+    }
+
+    fn grey() -> Material {
+        Material::matte(Colour::from_gray(0.5))
+    }
+
+    /// What a ray along +x sees of the ball, hitting it square on at (-1, 0, 0)
+    fn seen(scene: &Scene) -> Colour {
+        let ray = Ray::new(Vector3::new(-5.0, 0.0, 0.0), Vector3::x());
+        trace(scene, ray, 0, &RenderConfig::default())
+    }
+
+    #[test]
+    fn no_lights_leaves_only_the_ambient() {
+        assert_eq!(seen(&ball(grey(), vec![])), Colour::from_gray(0.05));
+    }
+
+    #[test]
+    fn unlit_surface_shows_the_ambient_times_its_albedo() {
+        let tinted = Material::matte(Colour::from_rgb(0.5, 1.0, 0.25));
+        let scene = ball(tinted, vec![]).with_ambient(Colour::from_rgb(0.5, 0.25, 1.0));
+        assert_eq!(seen(&scene), Colour::from_rgb(0.25, 0.25, 0.25));
+    }
+
+    #[test]
+    fn second_light_doubles_the_direct_lighting() {
+        let light = || Light::white(Vector3::new(-5.0, 0.0, 0.0), 1.0);
+        let ambient = seen(&ball(grey(), vec![])).r();
+        let one = seen(&ball(grey(), vec![light()])).r() - ambient;
+        let two = seen(&ball(grey(), vec![light(), light()])).r() - ambient;
+        assert!(one > 0.0);
+        assert!((two - 2.0 * one).abs() < 1e-5, "{} then {}", one, two);
+    }
+
+    #[test]
+    fn higher_shininess_concentrates_the_highlight() {
+        let light = Light::white(Vector3::new(-5.0, 0.0, 0.0), 1.0);
+        // black so only the highlight shows
+        let glossy = |shininess| Material {
+            specular: 1.0,
+            shininess,
+            ..Material::matte(Colour::from_gray(0.0))
+        };
+        let highlight = |shininess, height| {
+            let scene = ball(glossy(shininess), vec![light]);
+            let ray = Ray::new(Vector3::new(-5.0, 0.0, height), Vector3::x());
+            trace(&scene, ray, 0, &RenderConfig::default()).r()
+        };
+
+        let (head_on, off_centre) = (0.0, 0.4);
+        assert!((highlight(8.0, head_on) - highlight(64.0, head_on)).abs() < 1e-4);
+        assert!(highlight(64.0, off_centre) < highlight(8.0, off_centre) * 0.5);
+    }
+
+    #[test]
+    fn head_on_ray_passes_straight_through_glass() {
+        let direction = -Vector3::z();
+        let bent = refract(&direction, &Vector3::z(), 1.0 / 1.5).unwrap();
+        assert!((bent - direction).norm() < 1e-6, "{:?}", bent);
+
+        // while a shallow one leaving glass is reflected back inside
+        let grazing = Vector3::new(1.0, 0.0, -0.2).normalize();
+        assert_eq!(refract(&grazing, &Vector3::z(), 1.5), None);
+    }
+
+    #[test]
+    fn single_sample_area_light_acts_like_a_point_at_its_centre() {
+        let white = Colour::from_gray(1.0);
+        let area = Light::area(
+            Vector3::new(-5.0, -1.0, 1.0),
+            Vector3::new(0.0, 2.0, 0.0),
+            Vector3::new(0.0, 0.0, 2.0),
+            white,
+            1.0,
+            1,
+        );
+        let point = Light::point(Vector3::new(-5.0, 0.0, 2.0), white, 1.0);
+        let lit = |light| seen(&ball(grey(), vec![light]));
+        assert_eq!(lit(area), lit(point));
+    }
+
+    #[test]
+    fn nothing_nearby_means_no_occlusion() {
+        let scene = ball(grey(), vec![]);
+        let surface = Vector3::new(-1.0 - SHADOW_EPSILON, 0.0, 0.0);
+        let open = ambient_occlusion(&scene, &surface, &-Vector3::x(), 64, 1.0, 0.0);
+        assert_eq!(open, 1.0);
+
+        // facing the ball from just off it, most rays run into it
+        let facing = Vector3::new(-1.01, 0.0, 0.0);
+        let closed = ambient_occlusion(&scene, &facing, &Vector3::x(), 64, 1.0, 0.0);
+        assert!(closed < 0.5, "{}", closed);
+    }
+
+    #[test]
+    fn emissive_surface_shows_its_emission_whatever_the_lighting() {
+        let glow = Material::emissive(Colour::from_rgb(1.0, 0.5, 0.25), 2.0);
+        let expected = Colour::from_rgb(2.0, 1.0, 0.5);
+        assert_eq!(seen(&ball(glow.clone(), vec![])), expected);
+        let light = Light::white(Vector3::new(-5.0, 0.0, 0.0), 1.0);
+        assert_eq!(seen(&ball(glow, vec![light])), expected);
+    }
+
+    #[test]
+    fn smooth_metal_reflects_exactly_like_a_mirror() {
+        let direction = Vector3::new(1.0, 0.0, -1.0).normalize();
+        let mirrored = reflect(&direction, &Vector3::z());
+        assert_eq!(rough_reflect(&direction, &Vector3::z(), 0.0), mirrored);
+        assert!((mirrored - Vector3::new(1.0, 0.0, 1.0).normalize()).norm() < 1e-6);
+
+        // rough ones scatter, but never into the surface
+        let scattered = rough_reflect(&direction, &Vector3::z(), 1.0);
+        assert!(scattered.z > 0.0);
+    }
+
+    #[test]
+    fn schlick_is_r0_head_on_and_nearly_everything_at_grazing() {
+        let n: f32 = 1.5;
+        let r0 = ((1.0 - n) / (1.0 + n)).powi(2);
+        let head_on = schlick(&-Vector3::z(), &Vector3::z(), 1.0 / n);
+        assert!((head_on - r0).abs() < 1e-6, "{} against {}", head_on, r0);
+
+        let grazing = Vector3::new(1.0, 0.0, -1e-3).normalize();
+        assert!(schlick(&grazing, &Vector3::z(), 1.0 / n) > 0.99);
+    }
+
+    #[test]
+    fn zero_length_direction_never_reaches_intersection_as_nan() {
+        let origin = Vector3::new(-5.0, 0.0, 0.0);
+        assert!(Ray::new_normalized(origin, Vector3::zeros()).is_err());
+
+        let ray = Ray::new(origin, Vector3::zeros());
+        assert!(ray.direction.iter().all(|c| c.is_finite()));
+        let scene = ball(grey(), vec![]);
+        let hit = scene.nearest_intersection(&ray);
+        assert!(hit.distance.is_none_or(|t| t.is_finite()));
+        let colour = trace(&scene, ray, 0, &RenderConfig::default());
+        assert!(colour.to_array().iter().all(|c| c.is_finite()));
+    }
+
+    #[test]
+    fn misses_see_the_scene_background() {
+        let away = Ray::new(Vector3::new(-5.0, 0.0, 0.0), -Vector3::x());
+        let sky = Colour::from_rgb(0.2, 0.4, 0.8);
+        let plain = ball(grey(), vec![]);
+        let blue = ball(grey(), vec![]).with_background(sky.into());
+        let config = RenderConfig::default();
+        assert_eq!(trace(&plain, away, 0, &config), Colour::from_gray(0.0));
+        assert_eq!(trace(&blue, away, 0, &config), sky);
+    }
+
+    #[test]
+    fn toon_shading_snaps_light_to_exactly_the_configured_bands() {
+        for bands in 2..=6 {
+            let mut levels: Vec<f32> = (0..=1000)
+                .map(|i| quantize(i as f32 / 1000.0, bands))
+                .collect();
+            levels.dedup();
+            assert_eq!(levels.len(), bands as usize);
+            assert_eq!((levels[0], levels[levels.len() - 1]), (0.0, 1.0));
+        }
+        assert_eq!(quantize(0.3, 1), 1.0);
+    }
+
+    #[test]
+    fn back_of_a_two_sided_surface_is_lit_facing_the_ray() {
+        let floor = |two_sided| crate::plane::Plane {
+            point: Vector3::zeros(),
+            normal: Vector3::z(),
+            material: grey().with_two_sided(two_sided),
+        };
+        let from_below = Ray::new(Vector3::new(0.0, 0.0, -2.0), Vector3::new(1.0, 0.0, 1.0));
+
+        let hit = floor(true).test_intersection(&from_below);
+        let normal = hit.normal.unwrap();
+        let shading = shading_normal(&from_below, &hit, &normal).unwrap();
+        assert!(shading.direction.dot(&from_below.direction) < 0.0);
+        assert_eq!(shading.direction, -Vector3::z());
+
+        let hit = floor(false).test_intersection(&from_below);
+        assert!(shading_normal(&from_below, &hit, &hit.normal.unwrap()).is_none());
+    }
+
+    #[test]
+    fn no_bounces_means_a_mirror_reflects_nothing() {
+        // looking head on, the mirror would show the red sky behind the camera
+        let scene = ball(Material::mirror(Colour::from_gray(1.0)), vec![])
+            .with_background(Colour::from_rgb(1.0, 0.0, 0.0).into());
+        let ray = Ray::new(Vector3::new(-5.0, 0.0, 0.0), Vector3::x());
+        let seen_with = |max_depth| {
+            let config = RenderConfig {
+                max_depth,
+                ..RenderConfig::default()
+            };
+            trace(&scene, ray, 0, &config)
+        };
+        let direct = seen_with(0);
+        assert_eq!(direct.r(), direct.g());
+        let reflected = seen_with(1);
+        assert!(reflected.r() > direct.r() + 0.5, "{:?}", reflected);
+    }
+
+    #[test]
+    fn single_shadow_sample_is_the_hard_shadow() {
+        // the sphere at x = 3 shades a disk about 1.5 across on the x = 0 plane
+        let scene = Scene::eclipse();
+        let light = Vector3::new(9.0, 0.0, 0.0);
+        for i in 0..=40 {
+            let point = Vector3::new(0.0, i as f32 * 0.05, 0.0);
+            let shadow_ray = Ray::new(point, light - point);
+            let hard = if scene.occluded(&shadow_ray, 0.0, 9.0) {
+                0.0
+            } else {
+                1.0
+            };
+            assert_eq!(visibility(&scene, &shadow_ray, 9.0, 1), hard, "{}", point);
+        }
+
+        // and more of them soften its edge
+        rng::seed_pixel(0, 0, 0, 0);
+        let edge = Vector3::new(0.0, 1.5, 0.0);
+        let soft = visibility(&scene, &Ray::new(edge, light - edge), 9.0, 64);
+        assert!(soft > 0.0 && soft < 1.0, "{}", soft);
+    }
+
+    #[test]
+    fn wireframe_marks_only_the_edges_of_a_big_triangle() {
+        // 40 pixels across 20 units, so pixel centres sit every half unit from z = 9.75
+        // down, and row 25's at z = -2.75 lands just inside the bottom edge
+        let triangle = Intersectable::Triangle(crate::triangle::Triangle::new(
+            Vector3::new(0.0, -4.0, -2.8),
+            Vector3::new(0.0, 4.0, -2.8),
+            Vector3::new(0.0, 0.0, 4.0),
+            Material::matte(Colour::from_gray(0.5)),
+        ));
+        let scene = Scene::new(vec![triangle], vec![]);
+        let camera = crate::camera::Camera::default_view(40, 40);
+        let wireframe = RenderConfig {
+            mode: RenderMode::Wireframe,
+            ..RenderConfig::default()
+        };
+        let plain = camera.create_buffer(&scene, &RenderConfig::default());
+        let marked = camera.create_buffer(&scene, &wireframe);
+        let is_marked = |row: usize| marked[row][20] != plain[row][20];
+
+        assert!(is_marked(25));
+        assert!(!is_marked(20), "the middle");
+        assert!(!is_marked(26), "below the triangle");
+    }
+}
@@ -0,0 +1,126 @@
+use nalgebra::Vector3;
+use rayon::prelude::*;
+
+use crate::{camera::Camera, colour::Colour, scene::Scene};
+
+// how far a reprojected point may land from what the old view saw there, relative to
+// its distance, before it counts as newly uncovered
+const DEPTH_TOLERANCE: f32 = 0.02;
+// stands in for infinity so the background reprojects by direction
+const MISS_DISTANCE: f32 = 1e4;
+// further than this between views and too little of the old one is usable to bother
+const MAX_MOVE_DISTANCE: f32 = 1.0;
+const MAX_TURN_DEGREES: f32 = 10.0;
+
+/// Whether `to` is close enough to `from` for its accumulated samples to be carried over
+pub fn is_small_move(from: &Camera, to: &Camera) -> bool {
+    if from.width != to.width || from.height != to.height || from.projection != to.projection {
+        return false;
+    }
+    let moved = (to.location.origin - from.location.origin).norm();
+    let turned = from.location.direction.angle(&to.location.direction);
+    moved <= MAX_MOVE_DISTANCE && turned <= MAX_TURN_DEGREES.to_radians()
+}
+
+/// What each pixel centre of a view sees, enough to find the same point in another view
+pub struct GBuffer {
+    camera: Camera,
+    depth: Vec<Vec<f32>>, // distance along each pixel's centre ray, infinite for misses
+}
+
+impl GBuffer {
+    pub fn capture(camera: &Camera, scene: &Scene) -> GBuffer {
+        let depth = (0..camera.height)
+            .into_par_iter()
+            .map(|y| {
+                (0..camera.width)
+                    .map(|x| {
+                        let ray = camera.pinhole_ray(x as f32 + 0.5, y as f32 + 0.5);
+                        scene
+                            .nearest_within(&ray, camera.near, camera.far)
+                            .distance
+                            .unwrap_or(f32::INFINITY)
+                    })
+                    .collect()
+            })
+            .collect();
+        GBuffer {
+            camera: camera.clone(),
+            depth,
+        }
+    }
+
+    /// Centre of pixel (x, y) in world space
+    fn point(&self, x: u32, y: u32) -> Vector3<f32> {
+        let ray = self.camera.pinhole_ray(x as f32 + 0.5, y as f32 + 0.5);
+        ray.at_point(self.depth[y as usize][x as usize].min(MISS_DISTANCE))
+    }
+}
+
+/// For every pixel of `current`, the colour `previous` saw at the same point in
+/// `colours`, or `None` where that point was off screen or hidden behind something
+pub fn reproject(
+    previous: &GBuffer,
+    current: &GBuffer,
+    colours: &[Vec<Colour>],
+) -> Vec<Vec<Option<Colour>>> {
+    let (width, height) = (previous.camera.width, previous.camera.height);
+    (0..current.camera.height)
+        .into_par_iter()
+        .map(|y| {
+            (0..current.camera.width)
+                .map(|x| {
+                    let point = current.point(x, y);
+                    let (old_x, old_y) = previous.camera.project(&point)?;
+                    if old_x < 0.0 || old_y < 0.0 {
+                        return None;
+                    }
+                    let (old_x, old_y) = (old_x as u32, old_y as u32);
+                    if old_x >= width || old_y >= height {
+                        return None;
+                    }
+
+                    let was_miss = previous.depth[old_y as usize][old_x as usize].is_infinite();
+                    let is_miss = current.depth[y as usize][x as usize].is_infinite();
+                    if was_miss != is_miss {
+                        return None;
+                    }
+                    if !is_miss {
+                        let seen = previous.point(old_x, old_y);
+                        let distance = (point - previous.camera.location.origin).norm();
+                        if (seen - point).norm() > distance * DEPTH_TOLERANCE {
+                            return None;
+                        }
+                    }
+                    Some(colours[old_y as usize][old_x as usize])
+                })
+                .collect()
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn staying_put_reprojects_every_pixel_onto_itself() {
+        let scene = Scene::pondering_orbs();
+        let camera = Camera::default_view(40, 25);
+        let gbuffer = GBuffer::capture(&camera, &scene);
+        let colours: Vec<Vec<Colour>> = (0..camera.height)
+            .map(|y| {
+                (0..camera.width)
+                    .map(|x| Colour::from_rgb(x as f32, y as f32, 0.0))
+                    .collect()
+            })
+            .collect();
+
+        let history = reproject(&gbuffer, &gbuffer, &colours);
+        for (history_row, colour_row) in history.iter().zip(&colours) {
+            for (reprojected, colour) in history_row.iter().zip(colour_row) {
+                assert_eq!(*reprojected, Some(*colour));
+            }
+        }
+    }
+}
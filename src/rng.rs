@@ -0,0 +1,35 @@
+use std::cell::Cell;
+
+thread_local! {
+    static STATE: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Restarts this thread's random numbers from a state derived from the render seed and
+/// the pixel's sample. Every pixel sample then sees the same sequence whichever thread
+/// renders it, so the same seed always gives the same image
+pub fn seed_pixel(seed: u64, x: u32, y: u32, sample_index: u32) {
+    let mut state = mix(seed);
+    for value in [x, y, sample_index] {
+        state = mix(state ^ value as u64);
+    }
+    STATE.with(|cell| cell.set(state));
+}
+
+/// Uniformly distributed in 0..1, from this thread's sequence
+pub fn random() -> f32 {
+    STATE.with(|cell| {
+        let state = cell.get().wrapping_add(GOLDEN_GAMMA);
+        cell.set(state);
+        // the top 24 bits are as many as an f32 can hold below 1
+        (mix(state) >> 40) as f32 / (1u64 << 24) as f32
+    })
+}
+
+const GOLDEN_GAMMA: u64 = 0x9E37_79B9_7F4A_7C15;
+
+/// SplitMix64's finaliser, scrambling every bit of the input into every bit of the output
+fn mix(mut z: u64) -> u64 {
+    z = (z ^ (z >> 30)).wrapping_mul(0xBF58_476D_1CE4_E5B9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94D0_49BB_1331_11EB);
+    z ^ (z >> 31)
+}
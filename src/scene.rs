@@ -1,51 +1,316 @@
-use eframe::egui::Rgba;
+use std::{borrow::Borrow, fs::File, io::BufReader, path::Path, sync::OnceLock};
+
+use rand::{Rng, SeedableRng};
+use rand_chacha::ChaCha8Rng;
+use serde::{Deserialize, Serialize};
 
 use crate::{
-    intersect::{Intersect, Intersectable, Intersection},
-    light::{self, PointLight},
+    aabb::Aabb,
+    background::{self, Background},
+    bvh::Bvh,
+    camera::{Camera, CameraSettings},
+    colour::Colour,
+    environment::EnvironmentSampler,
+    intersect::{Intersectable, Intersection},
+    light::Light,
+    material::Material,
+    obj,
+    plane::Plane,
     renderer::Ray,
     sphere::Sphere,
+    texture::{Checker, Texture},
 };
 
-#[derive(Clone)]
+/// One of the things in a scene, which can be hidden without taking it out. In a scene
+/// file it's the object as usual, with `"visible": false` added to hide it
+#[derive(Clone, Serialize, Deserialize)]
+pub struct SceneObject {
+    #[serde(flatten)]
+    pub shape: Intersectable,
+    #[serde(default = "visible", skip_serializing_if = "is_visible")]
+    pub visible: bool, // hidden objects are left out of the render, rays pass straight through them
+}
+
+fn visible() -> bool {
+    true
+}
+
+fn is_visible(visible: &bool) -> bool {
+    *visible
+}
+
+impl From<Intersectable> for SceneObject {
+    fn from(shape: Intersectable) -> Self {
+        SceneObject {
+            shape,
+            visible: true,
+        }
+    }
+}
+
+impl Borrow<Intersectable> for SceneObject {
+    fn borrow(&self) -> &Intersectable {
+        &self.shape
+    }
+}
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Scene {
-    objects: Vec<Intersectable>,
-    max_depth: u8,
+    objects: Vec<SceneObject>,
+    lights: Vec<Light>,
+    #[serde(default, deserialize_with = "background::colour_or_background")]
+    background: Background, // what rays that hit nothing see, black unless set
+    #[serde(default = "default_fog_colour")]
+    fog_colour: Colour,
+    #[serde(default)]
+    fog_density: f32, // 0 means no fog
+    #[serde(default = "default_ambient")]
+    ambient: Colour, // flat fill light every surface gets, shadowed or not, standing in for bounced light
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    camera: Option<CameraSettings>, // where the shot is taken from, if the file says
+    #[serde(skip)]
+    bvh: OnceLock<Bvh>, // built on first use, reset whenever objects change
+    #[serde(skip)]
+    emitters: OnceLock<Vec<Sphere>>, // likewise, the glowing spheres the path tracer aims at
+    #[serde(skip)]
+    environment: OnceLock<Option<EnvironmentSampler>>, // for an image background, reset with it
 }
 
-const DEPTH: u8 = 8;
+// rendering hands every rayon thread the same `&Scene` rather than a copy each, so it has
+// to stay safe to read from many threads at once. Anything added to a scene that isn't,
+// like an `Rc` or a `Cell`, fails to compile here instead of somewhere in the renderer
+const _: () = {
+    const fn assert_sync<T: Sync + Send>() {}
+    assert_sync::<Scene>();
+};
+
+// resolution of a shot whose file doesn't give one
+const DEFAULT_WIDTH: u32 = 800;
+const DEFAULT_HEIGHT: u32 = 500;
+
+fn default_fog_colour() -> Colour {
+    Colour::from_gray(0.5)
+}
+
+fn default_ambient() -> Colour {
+    Colour::from_gray(0.05)
+}
 
 impl Scene {
-    pub fn test_intersections(&self, ray: Ray, current_depth: u8) -> Intersection {
-        let mut all_objects = self
-            .objects
-            .iter()
-            .map(|obj| obj.test_intersection(&ray))
-            .map(|mut intersect| {
-                if let Some(ray_new) = intersect.normal {
-                    if current_depth < self.max_depth {
-                        let result = self.test_intersections(ray_new, 0);
-                        intersect.colour = result.colour + intersect.colour.multiply(0.99);
-                        return intersect;
-                    } else {
-                        return intersect;
+    pub fn new(objects: Vec<Intersectable>, lights: Vec<Light>) -> Scene {
+        Scene {
+            objects: objects.into_iter().map(SceneObject::from).collect(),
+            lights,
+            background: Background::default(),
+            fog_colour: default_fog_colour(),
+            fog_density: 0.0,
+            ambient: default_ambient(),
+            camera: None,
+            bvh: OnceLock::new(),
+            emitters: OnceLock::new(),
+            environment: OnceLock::new(),
+        }
+    }
+
+    pub fn with_background(mut self, background: Background) -> Scene {
+        self.background = background;
+        self.background.follow_sun(&self.lights);
+        self.environment = OnceLock::new();
+        self
+    }
+
+    pub fn with_fog(mut self, colour: Colour, density: f32) -> Scene {
+        self.fog_colour = colour;
+        self.fog_density = density.max(0.0);
+        self
+    }
+
+    pub fn with_ambient(mut self, ambient: Colour) -> Scene {
+        self.ambient = ambient;
+        self
+    }
+
+    fn bvh(&self) -> &Bvh {
+        self.bvh
+            .get_or_init(|| Bvh::build_where(&self.objects, |i| self.objects[i].visible))
+    }
+
+    /// Throws away everything worked out from the objects, to be redone when next needed
+    fn objects_changed(&mut self) {
+        self.bvh = OnceLock::new();
+        self.emitters = OnceLock::new();
+    }
+
+    /// Copies of the visible spheres that give off light, which the path tracer samples
+    /// directly instead of waiting for paths to stumble onto them
+    pub fn emissive_spheres(&self) -> &[Sphere] {
+        self.emitters.get_or_init(|| {
+            self.objects
+                .iter()
+                .filter(|object| object.visible)
+                .filter_map(|object| match &object.shape {
+                    Intersectable::Sphere(s) if s.material.emission != Colour::from_gray(0.0) => {
+                        Some(s.clone())
                     }
-                } else {
-                    return intersect;
-                }
-            })
-            .collect::<Vec<Intersection>>();
-        all_objects.sort();
-
-        if let Some(_) = all_objects[0].normal {
-            return all_objects[0];
-        } else {
-            all_objects[0].colour = all_objects[0].colour + Rgba::from_gray(-0.01);
-            return all_objects[0];
+                    _ => None,
+                })
+                .collect()
+        })
+    }
+
+    pub fn objects(&self) -> &[SceneObject] {
+        &self.objects
+    }
+
+    /// Adds an object, returning its index
+    pub fn add_object(&mut self, object: Intersectable) -> usize {
+        self.objects.push(object.into());
+        self.objects_changed();
+        self.objects.len() - 1
+    }
+
+    /// Takes the object at `index` out of the scene, moving the ones after it down one
+    pub fn remove_object(&mut self, index: usize) -> Intersectable {
+        let removed = self.objects.remove(index);
+        self.objects_changed();
+        removed.shape
+    }
+
+    /// Swaps in a changed copy of the object at `index`, shown or hidden as it was
+    pub fn replace_object(&mut self, index: usize, object: Intersectable) {
+        self.objects[index].shape = object;
+        self.objects_changed();
+    }
+
+    /// Shows or hides the object at `index`. Hidden objects stay in the scene but
+    /// rays pass straight through them
+    pub fn set_visible(&mut self, index: usize, visible: bool) {
+        if visible == self.objects[index].visible {
+            return;
+        }
+        self.objects[index].visible = visible;
+        self.objects_changed();
+    }
+
+    pub fn from_json_file(path: &Path) -> Result<Scene, String> {
+        let file =
+            File::open(path).map_err(|e| format!("failed to open {}: {}", path.display(), e))?;
+        let mut scene: Scene = serde_json::from_reader(BufReader::new(file))
+            .map_err(|e| format!("failed to parse {}: {}", path.display(), e))?;
+        scene.background.follow_sun(&scene.lights);
+        log::debug!(
+            "{} has {} objects and {} lights",
+            path.display(),
+            scene.objects.len(),
+            scene.lights.len()
+        );
+        Ok(scene)
+    }
+
+    /// Reads a scene file along with the camera it describes, starting from
+    /// `Camera::default_view` for anything the file leaves out
+    pub fn load_shot(path: &Path) -> Result<(Scene, Camera), String> {
+        let scene = Scene::from_json_file(path)?;
+        let mut camera = Camera::default_view(DEFAULT_WIDTH, DEFAULT_HEIGHT);
+        if let Some(settings) = &scene.camera {
+            settings.apply(&mut camera);
         }
+        Ok((scene, camera))
+    }
+
+    pub fn camera_settings(&self) -> Option<&CameraSettings> {
+        self.camera.as_ref()
+    }
+
+    /// Makes `camera` the one saved with the scene
+    pub fn set_camera(&mut self, camera: &Camera) {
+        self.camera = Some(CameraSettings::of(camera));
+    }
+
+    pub fn to_json_file(&self, path: &Path) -> Result<(), String> {
+        let file = File::create(path)
+            .map_err(|e| format!("failed to create {}: {}", path.display(), e))?;
+        serde_json::to_writer_pretty(file, self)
+            .map_err(|e| format!("failed to write {}: {}", path.display(), e))
+    }
+
+    /// Closest surface along the ray, or a black miss if it hits nothing
+    pub fn nearest_intersection(&self, ray: &Ray) -> Intersection {
+        self.bvh().nearest(&self.objects, ray)
+    }
+
+    /// Closest surface whose distance along the ray is between `t_min` and `t_max`
+    pub fn nearest_within(&self, ray: &Ray, t_min: f32, t_max: f32) -> Intersection {
+        self.bvh().nearest_within(&self.objects, ray, t_min, t_max)
+    }
+
+    /// Adds every face of an OBJ file to the scene, all sharing one material.
+    /// Returns how many triangles were added
+    pub fn load_obj(&mut self, path: &Path, material: Material) -> Result<usize, String> {
+        let source = std::fs::read_to_string(path)
+            .map_err(|e| format!("failed to read {}: {}", path.display(), e))?;
+        let triangles = obj::parse_obj(&source, material)
+            .map_err(|e| format!("failed to parse {}: {}", path.display(), e))?;
+        let count = triangles.len();
+        self.objects.extend(
+            triangles
+                .into_iter()
+                .map(|t| Intersectable::Triangle(t).into()),
+        );
+        self.objects_changed();
+        Ok(count)
+    }
+
+    /// Box around every visible object that has one, leaving out planes and anything
+    /// else that goes on forever. `None` if nothing can be boxed
+    pub fn bounds(&self) -> Option<Aabb> {
+        self.objects
+            .iter()
+            .filter(|object| object.visible)
+            .filter_map(|object| object.shape.bounds())
+            .reduce(|acc, bounds| Aabb::surrounding(&acc, &bounds))
+    }
+
+    /// Whether anything visible blocks the ray between `t_min` and `t_max`
+    pub fn occluded(&self, ray: &Ray, t_min: f32, t_max: f32) -> bool {
+        self.bvh().occluded(&self.objects, ray, t_min, t_max)
+    }
+
+    pub fn lights(&self) -> &[Light] {
+        &self.lights
+    }
+
+    pub fn ambient(&self) -> Colour {
+        self.ambient
+    }
+
+    /// Blends `colour` towards the fog colour by how much fog lies in `distance`,
+    /// following `1 - exp(-density * distance)`
+    pub fn fog(&self, colour: Colour, distance: f32) -> Colour {
+        if self.fog_density <= 0.0 {
+            return colour;
+        }
+        let amount = 1.0 - (-self.fog_density * distance).exp();
+        colour * (1.0 - amount) + self.fog_colour * amount
+    }
+
+    /// Colour seen by a ray travelling along `direction` that hits nothing
+    pub fn background(&self, direction: &nalgebra::Vector3<f32>) -> Colour {
+        self.background.colour(direction)
+    }
+
+    /// What the path tracer aims at the background with, if it's an image with any light
+    /// in it. Other backgrounds are smooth enough for bounces to find their light
+    pub fn environment(&self) -> Option<&EnvironmentSampler> {
+        self.environment
+            .get_or_init(|| match &self.background {
+                Background::Image { image } => EnvironmentSampler::new(image),
+                _ => None,
+            })
+            .as_ref()
     }
 
-    #[allow(dead_code)]
     pub fn curve() -> Scene {
         let mut objects = vec![];
 
@@ -57,68 +322,321 @@ impl Scene {
             objects.push(Intersectable::Sphere(Sphere {
                 origin: nalgebra::Vector3::new(3.0, y, 2.0 * (y).sin() + 0.1 * y.powi(2)),
                 radius: 0.1,
-                colour: Rgba::from_white_alpha(1.0),
+                velocity: None,
+                material: Material::matte(Colour::from_white_alpha(1.0)),
             }));
         }
 
-        objects.push(Intersectable::PointLight(light::PointLight::new(
-            nalgebra::Vector3::new(-12.0, -12.0, 20.0),
-            1.0,
-        )));
-
-        objects.push(Intersectable::PointLight(light::PointLight::new(
-            nalgebra::Vector3::new(-12.0, 12.0, 20.0),
-            1.0,
-        )));
+        let lights = vec![
+            Light::white(nalgebra::Vector3::new(-12.0, -12.0, 20.0), 1.0),
+            Light::white(nalgebra::Vector3::new(-12.0, 12.0, 20.0), 1.0),
+        ];
 
-        Scene {
-            objects,
-            max_depth: DEPTH,
-        }
+        Scene::new(objects, lights)
     }
 
-    #[allow(dead_code)]
     pub fn pondering_orbs() -> Scene {
         let objects = vec![
             Intersectable::Sphere(Sphere {
                 origin: nalgebra::Vector3::new(3.0, 8.0, 8.0),
                 radius: 1.0,
-                colour: Rgba::from_white_alpha(1.0),
+                velocity: None,
+                material: Material {
+                    albedo: Colour::from_white_alpha(1.0).into(),
+                    specular: 0.5,
+                    shininess: 16.0,
+                    reflectivity: 0.0,
+                    roughness: 0.0,
+                    ior: 1.0,
+                    transparency: 0.0,
+                    emission: Colour::from_gray(0.0),
+                    two_sided: true,
+                },
             }),
             Intersectable::Sphere(Sphere {
                 origin: nalgebra::Vector3::new(3.0, 5.0, 5.0),
                 radius: 1.0,
-                colour: Rgba::from_white_alpha(1.0),
+                velocity: None,
+                material: Material::mirror(Colour::from_white_alpha(1.0)),
             }),
             Intersectable::Sphere(Sphere {
                 origin: nalgebra::Vector3::new(0.0, 3.6, 3.9),
                 radius: 0.5,
-                colour: Rgba::from_rgb(1.0, 0.0, 0.0),
+                velocity: None,
+                material: Material {
+                    albedo: Colour::from_rgb(1.0, 0.0, 0.0).into(),
+                    specular: 1.0,
+                    shininess: 128.0,
+                    reflectivity: 0.1,
+                    roughness: 0.0,
+                    ior: 1.0,
+                    transparency: 0.0,
+                    emission: Colour::from_gray(0.0),
+                    two_sided: true,
+                },
+            }),
+            Intersectable::Sphere(Sphere {
+                origin: nalgebra::Vector3::new(1.0, -3.0, -0.5),
+                radius: 0.5,
+                velocity: None,
+                material: Material::emissive(Colour::from_rgb(1.0, 0.6, 0.2), 2.0),
+            }),
+            Intersectable::Plane(Plane {
+                point: nalgebra::Vector3::new(0.0, 0.0, -1.0),
+                normal: nalgebra::Vector3::z(),
+                material: Material {
+                    albedo: Texture::Checker(Checker {
+                        a: Colour::from_gray(0.8),
+                        b: Colour::from_gray(0.4),
+                        scale: 0.5,
+                    }),
+                    ..Material::matte(Colour::from_gray(0.8))
+                },
             }),
-            Intersectable::PointLight(PointLight::new(
-                nalgebra::Vector3::new(12.0, -8.0, -8.0),
-                1.0,
-            )),
         ];
-        Scene {
-            objects,
-            max_depth: DEPTH,
+        let lights = vec![
+            Light::point(
+                nalgebra::Vector3::new(-6.0, -4.0, 12.0),
+                Colour::from_rgb(1.0, 0.8, 0.6),
+                3.0,
+            )
+            .with_attenuation(1.0, 0.0, 0.01),
+            Light::point(
+                nalgebra::Vector3::new(-6.0, 12.0, 0.0),
+                Colour::from_rgb(0.4, 0.6, 1.0),
+                3.0,
+            )
+            .with_attenuation(1.0, 0.0, 0.01),
+        ];
+        Scene::new(objects, lights).with_background(Background::Gradient {
+            horizon: Colour::from_rgb(0.3, 0.35, 0.45),
+            zenith: Colour::from_rgb(0.05, 0.1, 0.3),
+        })
+    }
+
+    /// Up to `n` spheres with random sizes and materials that don't overlap, scattered
+    /// through a box in front of the default camera that grows with `n`. The same seed
+    /// gives the same scene on any machine. Spheres that can't find room after a few
+    /// tries are left out, so very crowded requests come back with fewer
+    pub fn random_spheres(n: usize, seed: u64) -> Scene {
+        const ATTEMPTS_PER_SPHERE: usize = 100;
+        let mut rng = ChaCha8Rng::seed_from_u64(seed);
+        let half_extent = (n as f32).cbrt().max(3.0) * 1.5;
+        let mut spheres: Vec<Sphere> = Vec::with_capacity(n);
+
+        for _ in 0..n {
+            for _ in 0..ATTEMPTS_PER_SPHERE {
+                let radius = rng.gen_range(0.2..0.6);
+                let origin = nalgebra::Vector3::new(
+                    rng.gen_range(0.0..2.0 * half_extent),
+                    rng.gen_range(-half_extent..half_extent),
+                    rng.gen_range(-half_extent..half_extent),
+                );
+                let overlaps = spheres
+                    .iter()
+                    .any(|s| (s.origin - origin).norm() < s.radius + radius);
+                if overlaps {
+                    continue;
+                }
+                let colour = Colour::from_rgb(rng.gen(), rng.gen(), rng.gen());
+                let material = match rng.gen_range(0..10) {
+                    0..=5 => Material::matte(colour),
+                    6..=7 => Material {
+                        specular: 0.5,
+                        shininess: 32.0,
+                        ..Material::matte(colour)
+                    },
+                    8 => Material::mirror(colour),
+                    _ => Material::glass(colour, 1.5),
+                };
+                spheres.push(Sphere {
+                    origin,
+                    radius,
+                    velocity: None,
+                    material,
+                });
+                break;
+            }
         }
+
+        let objects = spheres.into_iter().map(Intersectable::Sphere).collect();
+        let lights = vec![Light::white(
+            nalgebra::Vector3::new(-10.0, -half_extent, 2.0 * half_extent),
+            1.0,
+        )];
+        Scene::new(objects, lights)
     }
 
-    #[allow(dead_code)]
     pub fn eclipse() -> Scene {
-        let objects = vec![
-            Intersectable::Sphere(Sphere {
-                origin: nalgebra::Vector3::new(3.0, 0.0, 0.0),
-                radius: 1.0,
-                colour: Rgba::from_white_alpha(1.0),
-            }),
-            Intersectable::PointLight(PointLight::new(nalgebra::Vector3::new(9.0, 0.0, 0.0), 1.0)),
-        ];
-        Scene {
-            objects,
-            max_depth: DEPTH,
+        let objects = vec![Intersectable::Sphere(Sphere {
+            origin: nalgebra::Vector3::new(3.0, 0.0, 0.0),
+            radius: 1.0,
+            velocity: None,
+            material: Material::matte(Colour::from_white_alpha(1.0)),
+        })];
+        let lights = vec![Light::white(nalgebra::Vector3::new(9.0, 0.0, 0.0), 1.0)];
+        Scene::new(objects, lights)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use nalgebra::Vector3;
+
+    #[test]
+    fn sphere_between_a_point_and_the_light_occludes_it() {
+        // the sphere sits at x = 3, the light at x = 9
+        let scene = Scene::eclipse();
+        let light = Vector3::new(9.0, 0.0, 0.0);
+        let shadow_ray = |from: Vector3<f32>| Ray::new(from, light - from);
+
+        assert!(scene.occluded(&shadow_ray(Vector3::zeros()), 0.0, 9.0));
+        assert!(!scene.occluded(&shadow_ray(Vector3::zeros()), 0.0, 1.5));
+        let beside = Vector3::new(0.0, 3.0, 0.0);
+        assert!(!scene.occluded(&shadow_ray(beside), 0.0, (light - beside).norm()));
+
+        // leaving from right on the lit side, only the epsilon keeps it off the sphere
+        let surface = Vector3::new(4.0, 0.0, 0.0);
+        assert!(!scene.occluded(&shadow_ray(surface), crate::renderer::SHADOW_EPSILON, 5.0));
+    }
+
+    #[test]
+    fn pondering_orbs_survive_a_round_trip_through_json() {
+        let scene = Scene::pondering_orbs();
+        let path = std::env::temp_dir().join(format!("orbs-{}.json", std::process::id()));
+        scene.to_json_file(&path).unwrap();
+        let loaded = Scene::from_json_file(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let as_json = |scene: &Scene| serde_json::to_value(scene).unwrap();
+        assert_eq!(as_json(&loaded.unwrap()), as_json(&scene));
+    }
+
+    #[test]
+    fn camera_survives_a_round_trip_through_json() {
+        let mut camera = Camera::default_view(320, 240).with_aperture(0.2, 7.5);
+        camera.location.origin = Vector3::new(1.0, -4.0, 2.5);
+        camera.look_along(&Vector3::new(0.0, 1.0, -0.5));
+        camera.set_fov(35.0);
+        camera.roll(0.3);
+        camera.set_clip(0.5, 80.0);
+        let mut scene = Scene::eclipse();
+        scene.set_camera(&camera);
+
+        let path = std::env::temp_dir().join(format!("shot-{}.json", std::process::id()));
+        scene.to_json_file(&path).unwrap();
+        let loaded = Scene::load_shot(&path);
+        std::fs::remove_file(&path).unwrap();
+
+        let (_, loaded) = loaded.unwrap();
+        // the direction is rebuilt from yaw and pitch, so it can come back a rounding off
+        let (direction, loaded_direction) = (camera.location.direction, loaded.location.direction);
+        assert!((direction - loaded_direction).norm() < 1e-6);
+        let without_direction = |camera: &Camera| CameraSettings {
+            direction: None,
+            ..CameraSettings::of(camera)
+        };
+        assert_eq!(without_direction(&loaded), without_direction(&camera));
+    }
+
+    #[test]
+    fn fog_fades_towards_its_colour_with_distance() {
+        let colour = Colour::from_rgb(1.0, 0.2, 0.0);
+        let grey = Colour::from_gray(0.5);
+        let clear = Scene::new(vec![], vec![]).with_fog(grey, 0.0);
+        assert_eq!(clear.fog(colour, 1000.0), colour);
+
+        let foggy = Scene::new(vec![], vec![]).with_fog(grey, 0.1);
+        assert_eq!(foggy.fog(colour, 0.0), colour);
+        let far = foggy.fog(colour, 1000.0);
+        assert!((far.r() - 0.5).abs() < 1e-4 && (far.b() - 0.5).abs() < 1e-4);
+    }
+
+    #[test]
+    fn random_spheres_are_the_same_for_the_same_seed() {
+        let centres = |scene: &Scene| -> Vec<(Vector3<f32>, f32)> {
+            scene
+                .objects()
+                .iter()
+                .map(|object| match &object.shape {
+                    Intersectable::Sphere(s) => (s.origin, s.radius),
+                    other => panic!("expected only spheres, got a {}", other.kind()),
+                })
+                .collect()
+        };
+        let first = centres(&Scene::random_spheres(50, 9));
+        assert_eq!(first.len(), 50);
+        assert_eq!(first, centres(&Scene::random_spheres(50, 9)));
+        assert_ne!(first, centres(&Scene::random_spheres(50, 10)));
+        for (i, (a, a_radius)) in first.iter().enumerate() {
+            for (b, b_radius) in &first[i + 1..] {
+                assert!((a - b).norm() >= a_radius + b_radius);
+            }
         }
+
+        assert!(Scene::random_spheres(0, 9).objects().is_empty());
+    }
+
+    #[test]
+    fn bounds_enclose_every_sphere() {
+        let scene = Scene::pondering_orbs();
+        let bounds = scene.bounds().unwrap();
+        let spheres = scene
+            .objects()
+            .iter()
+            .filter_map(|object| match &object.shape {
+                Intersectable::Sphere(s) => Some(s),
+                _ => None,
+            });
+        let mut count = 0;
+        for sphere in spheres {
+            let extent = Vector3::repeat(sphere.radius);
+            let (low, high) = (sphere.origin - extent, sphere.origin + extent);
+            assert!(low.iter().zip(bounds.min.iter()).all(|(p, b)| p >= b));
+            assert!(high.iter().zip(bounds.max.iter()).all(|(p, b)| p <= b));
+            count += 1;
+        }
+        assert!(count > 0);
+
+        // and framing the scene brings all of it into view
+        let mut camera = Camera::default_view(64, 40);
+        camera.location.origin = Vector3::new(-500.0, 0.0, 0.0);
+        camera.frame(&scene);
+        for corner in [bounds.min, bounds.max] {
+            let (x, y) = camera.project(&corner).unwrap();
+            assert!((0.0..=64.0).contains(&x) && (0.0..=40.0).contains(&y));
+        }
+    }
+
+    #[test]
+    fn hidden_sphere_is_never_hit() {
+        let mut scene = Scene::eclipse();
+        let ray = Ray::new(Vector3::zeros(), Vector3::x());
+        assert!(scene.nearest_intersection(&ray).distance.is_some());
+
+        scene.set_visible(0, false);
+        assert_eq!(scene.nearest_intersection(&ray).distance, None);
+        assert!(!scene.occluded(&ray, 0.0, 100.0));
+        scene.set_visible(0, true);
+        assert!(scene.nearest_intersection(&ray).distance.is_some());
+    }
+
+    #[test]
+    fn hidden_objects_stay_hidden_through_json_and_removals() {
+        let mut scene = Scene::eclipse();
+        scene.add_object(scene.objects()[0].shape.clone());
+        scene.set_visible(1, false);
+
+        let json = serde_json::to_string(&scene).unwrap();
+        assert_eq!(json.matches("\"visible\":false").count(), 1);
+        let mut scene: Scene = serde_json::from_str(&json).unwrap();
+        assert!(scene.objects()[0].visible);
+        assert!(!scene.objects()[1].visible);
+
+        scene.remove_object(0);
+        assert!(!scene.objects()[0].visible);
+        let ray = Ray::new(Vector3::zeros(), Vector3::x());
+        assert_eq!(scene.nearest_intersection(&ray).distance, None);
     }
 }
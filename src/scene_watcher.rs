@@ -0,0 +1,74 @@
+use std::{
+    ffi::OsString,
+    path::Path,
+    sync::{
+        atomic::{AtomicBool, Ordering},
+        Arc,
+    },
+};
+
+use eframe::egui;
+use notify::{EventKind, RecommendedWatcher, RecursiveMode, Watcher};
+
+/// Notices when a scene file changes on disk. The directory it's in is what's watched,
+/// since editors often save by writing a new file and renaming it over the old one, and
+/// the file doesn't even have to exist yet
+pub struct SceneWatcher {
+    _watcher: RecommendedWatcher, // stops watching when dropped
+    changed: Arc<AtomicBool>,     // set from the watcher's thread, cleared by `take_change`
+}
+
+impl SceneWatcher {
+    /// Starts watching `path`, waking `repaint` whenever it changes so the app notices
+    /// even while it's sat idle
+    pub fn watch(path: &Path, repaint: &egui::Context) -> Result<SceneWatcher, String> {
+        let name = path
+            .file_name()
+            .map(OsString::from)
+            .ok_or_else(|| format!("{} isn't a file", path.display()))?;
+        let directory = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+
+        let changed = Arc::new(AtomicBool::new(false));
+        let flag = Arc::clone(&changed);
+        let repaint = repaint.clone();
+        let mut watcher =
+            notify::recommended_watcher(move |event: notify::Result<notify::Event>| {
+                let event = match event {
+                    Ok(event) => event,
+                    Err(e) => {
+                        log::error!("failed watching for scene changes: {}", e);
+                        return;
+                    }
+                };
+                if !matches!(event.kind, EventKind::Create(_) | EventKind::Modify(_)) {
+                    return;
+                }
+                if event
+                    .paths
+                    .iter()
+                    .any(|changed| changed.file_name() == Some(name.as_os_str()))
+                {
+                    flag.store(true, Ordering::SeqCst);
+                    repaint.request_repaint();
+                }
+            })
+            .map_err(|e| format!("failed to watch {}: {}", path.display(), e))?;
+        watcher
+            .watch(directory, RecursiveMode::NonRecursive)
+            .map_err(|e| format!("failed to watch {}: {}", directory.display(), e))?;
+
+        Ok(SceneWatcher {
+            _watcher: watcher,
+            changed,
+        })
+    }
+
+    /// Whether the file has changed since the last call. A save usually comes as a burst
+    /// of events, which this answers once for
+    pub fn take_change(&self) -> bool {
+        self.changed.swap(false, Ordering::SeqCst)
+    }
+}
@@ -1,45 +1,151 @@
-use eframe::egui::Rgba;
-use nalgebra;
+use std::f32::consts::PI;
+
+use serde::{Deserialize, Serialize};
 
 use crate::{
+    colour::Colour,
     intersect::{Intersect, Intersection},
+    material::Material,
     renderer::Ray,
 };
 
-#[derive(Clone, Copy)]
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
 pub struct Sphere {
+    #[serde(rename = "center")]
     pub origin: nalgebra::Vector3<f32>,
     pub radius: f32,
-    pub colour: Rgba,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub velocity: Option<nalgebra::Vector3<f32>>, // distance moved over the exposure, blurring it along the way
+    pub material: Material,
 }
 
 #[allow(non_snake_case)]
 impl Intersect for Sphere {
     fn test_intersection(&self, ray: &Ray) -> Intersection {
-        let L = self.origin - ray.origin;
+        self.intersect_within(ray, 0.0, f32::INFINITY)
+    }
+
+    fn intersect_within(&self, ray: &Ray, t_min: f32, t_max: f32) -> Intersection {
+        let centre = self.centre_at(ray.time);
+        let L = centre - ray.origin;
         let t_ca = L.dot(&ray.direction);
 
         let background = 0.0;
+        let miss = Intersection::new(Colour::from_gray(background), None, None);
 
-        if t_ca <= 0.0 {
-            return Intersection::new(Rgba::from_gray(background), None, None);
+        let close_approach_point = ray.at_point(t_ca); // closest approach
+        let distance = (close_approach_point - centre).norm();
+        if distance >= self.radius {
+            return miss;
         }
 
-        let close_approach_point = ray.at_point(t_ca); // closest approach
-        let distance = (close_approach_point - self.origin).norm();
+        // the ray crosses the surface twice, take the first crossing in range so rays
+        // starting inside (refracted ones) hit the far wall
         let t_surface_to_cap = (self.radius.powi(2) - distance.powi(2)).sqrt();
-        let t_surface = t_ca - t_surface_to_cap;
+        let t_near = t_ca - t_surface_to_cap;
+        let Some(t_surface) = [t_near, t_ca + t_surface_to_cap]
+            .into_iter()
+            .find(|&t| t > t_min && t < t_max)
+        else {
+            return miss;
+        };
+        // the near crossing goes in and the far one comes out, which unlike the sign of
+        // the normal against the ray can't be thrown by a grazing hit
+        let front_face = t_surface == t_near;
         let surface = ray.at_point(t_surface);
 
-        let normal_vec = surface - self.origin;
+        // always pointing out, `front_face` saying which side the ray is on
+        let normal_vec = surface - centre;
         let normal_vec = normal_vec / normal_vec.norm();
 
         let normal_ray = Ray::new(surface, normal_vec);
+        let uv = Sphere::uv(&normal_vec);
+
+        let mut intersection = Intersection::new(
+            self.material.colour_at(&normal_ray.origin, Some(uv)),
+            Some(t_surface),
+            Some(normal_ray),
+        )
+        .with_material(self.material.clone());
+        intersection.uv = Some(uv);
+        intersection.front_face = front_face;
+        return intersection;
+    }
+}
+
+impl Sphere {
+    /// Where the centre is at `time` through the exposure, from 0 at the start to 1 at the end
+    pub fn centre_at(&self, time: f32) -> nalgebra::Vector3<f32> {
+        match self.velocity {
+            Some(velocity) => self.origin + velocity * time,
+            None => self.origin,
+        }
+    }
+
+    /// Texture coordinates of the point with outward normal `normal`, u running
+    /// once around the equator from -x and v from 0 at the north (+z) pole to 1 at
+    /// the south pole
+    pub fn uv(normal: &nalgebra::Vector3<f32>) -> nalgebra::Vector2<f32> {
+        nalgebra::Vector2::new(
+            0.5 + normal.y.atan2(normal.x) / (2.0 * PI),
+            0.5 - normal.z.clamp(-1.0, 1.0).asin() / PI,
+        )
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use nalgebra::Vector3;
 
-        if distance < self.radius {
-            return Intersection::new(self.colour, Some(distance), Some(normal_ray));
+    use super::*;
+
+    fn unit_sphere() -> Sphere {
+        Sphere {
+            origin: Vector3::zeros(),
+            radius: 1.0,
+            velocity: None,
+            material: Material::matte(Colour::from_gray(1.0)),
         }
-        //return Intersection::new(Rgba::from_gray(background), None, None);
-        return Intersection::new(Rgba::from_rgb(0.0, 0.0, 0.0), None, None);
+    }
+
+    #[test]
+    fn ray_starting_inside_hits_the_far_wall() {
+        let ray = Ray::new(Vector3::new(0.5, 0.0, 0.0), Vector3::x());
+        let distance = unit_sphere().test_intersection(&ray).distance.unwrap();
+        assert!((distance - 0.5).abs() < 1e-5, "{}", distance);
+    }
+
+    #[test]
+    fn ray_pointing_away_misses() {
+        let ray = Ray::new(Vector3::new(-3.0, 0.0, 0.0), -Vector3::x());
+        assert_eq!(unit_sphere().test_intersection(&ray).distance, None);
+    }
+
+    #[test]
+    fn front_face_says_which_side_the_ray_came_from() {
+        let sphere = unit_sphere();
+        let outside = Ray::new(Vector3::new(-3.0, 0.0, 0.0), Vector3::x());
+        let inside = Ray::new(Vector3::zeros(), Vector3::x());
+
+        let entering = sphere.test_intersection(&outside);
+        assert!(entering.front_face);
+        let leaving = sphere.test_intersection(&inside);
+        assert!(!leaving.front_face);
+        // the normal points out either way
+        for hit in [entering, leaving] {
+            let normal = hit.normal.unwrap();
+            assert!(normal.direction.dot(&normal.origin) > 0.0);
+        }
+    }
+
+    #[test]
+    fn poles_and_equator_map_to_the_expected_uvs() {
+        let uv = |x, y, z| Sphere::uv(&Vector3::new(x, y, z));
+        assert_eq!(uv(0.0, 0.0, 1.0).y, 0.0);
+        assert_eq!(uv(0.0, 0.0, -1.0).y, 1.0);
+        assert_eq!(uv(1.0, 0.0, 0.0), nalgebra::Vector2::new(0.5, 0.5));
+        assert_eq!(uv(0.0, 1.0, 0.0), nalgebra::Vector2::new(0.75, 0.5));
+        assert_eq!(uv(0.0, -1.0, 0.0), nalgebra::Vector2::new(0.25, 0.5));
     }
 }
@@ -0,0 +1,329 @@
+use std::{path::PathBuf, sync::Arc};
+
+use image::RgbaImage;
+use nalgebra::{Vector2, Vector3};
+use serde::{de::Error, Deserialize, Deserializer, Serialize};
+use serde_json::Value;
+
+use crate::colour::Colour;
+
+// keeps surfaces lying exactly on a cell boundary, like a floor at z = -1, from
+// flickering between cells as the hit point wobbles by a rounding error
+const CELL_BIAS: f32 = 1e-4;
+
+/// Colour of a surface, either the same everywhere or varying across it.
+/// In a scene file a plain `[r, g, b, a]` is read as a solid colour, a string or an
+/// object with a `path` as an image to wrap around the surface, and any other object
+/// as a checkerboard
+#[derive(Clone, Debug, Serialize)]
+#[serde(untagged)]
+pub enum Texture {
+    Solid(Colour),
+    Checker(Checker),
+    Image(ImageTexture),
+}
+
+impl Texture {
+    /// Colour at a point on a surface, `uv` being `None` for primitives that don't
+    /// have texture coordinates
+    pub fn colour_at(&self, point: &Vector3<f32>, uv: Option<Vector2<f32>>) -> Colour {
+        match self {
+            Texture::Solid(colour) => *colour,
+            Texture::Checker(checker) => checker.colour_at(point),
+            Texture::Image(image) => image.sample(uv.unwrap_or_default()),
+        }
+    }
+}
+
+// told apart by the shape of the JSON rather than left to `untagged`, which would hide
+// a misspelt field behind "did not match any variant"
+impl<'de> Deserialize<'de> for Texture {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+        let texture = match &value {
+            Value::Array(_) => serde_json::from_value(value).map(Texture::Solid),
+            Value::String(_) => serde_json::from_value(value).map(Texture::Image),
+            Value::Object(fields) if fields.contains_key("path") => {
+                serde_json::from_value(value).map(Texture::Image)
+            }
+            Value::Object(_) => serde_json::from_value(value).map(Texture::Checker),
+            _ => {
+                return Err(D::Error::custom(
+                    "expected a colour, an image path or a checker",
+                ))
+            }
+        };
+        texture.map_err(D::Error::custom)
+    }
+}
+
+impl From<Colour> for Texture {
+    fn from(colour: Colour) -> Self {
+        Texture::Solid(colour)
+    }
+}
+
+/// 3D checkerboard of alternating cubes, `scale` cells to a world unit
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Checker {
+    pub a: Colour,
+    pub b: Colour,
+    pub scale: f32,
+}
+
+impl Checker {
+    pub fn colour_at(&self, point: &Vector3<f32>) -> Colour {
+        let cell = point.map(|c| (c * self.scale + CELL_BIAS).floor());
+        if (cell.x + cell.y + cell.z).rem_euclid(2.0) < 1.0 {
+            self.a
+        } else {
+            self.b
+        }
+    }
+}
+
+/// How an image texture is read between texel centres
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum TextureFilter {
+    Nearest, // the texel the point lands in, keeping pixel art blocky
+    #[default]
+    Bilinear, // blended between the four nearest texels
+}
+
+/// What UVs outside 0..1 read
+#[derive(Clone, Copy, Debug, Default, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum WrapMode {
+    #[default]
+    Repeat, // the image tiles
+    Clamp, // the edge texels stretch out forever
+}
+
+impl WrapMode {
+    /// Texel index along an axis `size` texels long
+    fn texel(self, index: f32, size: u32) -> u32 {
+        match self {
+            WrapMode::Repeat => index.rem_euclid(size as f32) as u32,
+            WrapMode::Clamp => index.clamp(0.0, size as f32 - 1.0) as u32,
+        }
+    }
+}
+
+/// An image wrapped over a surface's UVs. The image is shared between every
+/// clone of the material rather than loaded again. In a scene file it's the image's
+/// path, or `{"path": ..., "filter": "nearest", "wrap": "clamp"}` to change how it's read
+#[derive(Clone, Debug, Serialize, Deserialize)]
+#[serde(try_from = "ImageSource", into = "ImageSource")]
+pub struct ImageTexture {
+    path: PathBuf,
+    image: Arc<RgbaImage>,
+    filter: TextureFilter,
+    wrap: WrapMode,
+}
+
+impl ImageTexture {
+    pub fn load(path: PathBuf) -> Result<ImageTexture, String> {
+        let image = image::open(&path)
+            .map_err(|e| format!("failed to load texture {}: {}", path.display(), e))?
+            .into_rgba8();
+        if image.width() == 0 || image.height() == 0 {
+            return Err(format!("texture {} has no pixels", path.display()));
+        }
+        Ok(ImageTexture {
+            path,
+            image: Arc::new(image),
+            filter: TextureFilter::default(),
+            wrap: WrapMode::default(),
+        })
+    }
+
+    pub fn with_filter(mut self, filter: TextureFilter) -> ImageTexture {
+        self.filter = filter;
+        self
+    }
+
+    pub fn with_wrap(mut self, wrap: WrapMode) -> ImageTexture {
+        self.wrap = wrap;
+        self
+    }
+
+    pub fn dimensions(&self) -> (u32, u32) {
+        self.image.dimensions()
+    }
+
+    /// Linear colour of the texel in column `x` and row `y`
+    pub fn texel(&self, x: u32, y: u32) -> Colour {
+        // images are stored in sRGB, blending has to happen on linear values
+        Colour::from_srgba_unmultiplied(self.image.get_pixel(x, y).0)
+    }
+
+    /// Filtered colour at `uv`, with (0, 0) the top left corner of the image
+    pub fn sample(&self, uv: Vector2<f32>) -> Colour {
+        let (width, height) = self.image.dimensions();
+        let x = uv.x * width as f32;
+        let y = uv.y * height as f32;
+        let texel =
+            |x: f32, y: f32| self.texel(self.wrap.texel(x, width), self.wrap.texel(y, height));
+        if self.filter == TextureFilter::Nearest {
+            return texel(x.floor(), y.floor());
+        }
+
+        // texel centres sit half a texel in from their corners
+        let (x, y) = (x - 0.5, y - 0.5);
+        let (x0, y0) = (x.floor(), y.floor());
+        let (tx, ty) = (x - x0, y - y0);
+        let top = texel(x0, y0) * (1.0 - tx) + texel(x0 + 1.0, y0) * tx;
+        let bottom = texel(x0, y0 + 1.0) * (1.0 - tx) + texel(x0 + 1.0, y0 + 1.0) * tx;
+        top * (1.0 - ty) + bottom * ty
+    }
+}
+
+/// How an image texture is written in a scene file, a bare path when it's read the
+/// default way
+#[derive(Serialize)]
+#[serde(untagged)]
+enum ImageSource {
+    Path(PathBuf),
+    Sampled(SampledImage),
+}
+
+#[derive(Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct SampledImage {
+    path: PathBuf,
+    #[serde(default)]
+    filter: TextureFilter,
+    #[serde(default)]
+    wrap: WrapMode,
+}
+
+// a path or an object, picked by hand for the same reason as `Texture`
+impl<'de> Deserialize<'de> for ImageSource {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = Value::deserialize(deserializer)?;
+        let source = match &value {
+            Value::String(_) => serde_json::from_value(value).map(ImageSource::Path),
+            Value::Object(_) => serde_json::from_value(value).map(ImageSource::Sampled),
+            _ => return Err(D::Error::custom("expected an image path or an object")),
+        };
+        source.map_err(D::Error::custom)
+    }
+}
+
+impl TryFrom<ImageSource> for ImageTexture {
+    type Error = String;
+
+    fn try_from(source: ImageSource) -> Result<Self, Self::Error> {
+        match source {
+            ImageSource::Path(path) => ImageTexture::load(path),
+            ImageSource::Sampled(SampledImage { path, filter, wrap }) => {
+                Ok(ImageTexture::load(path)?
+                    .with_filter(filter)
+                    .with_wrap(wrap))
+            }
+        }
+    }
+}
+
+impl From<ImageTexture> for ImageSource {
+    fn from(texture: ImageTexture) -> Self {
+        if texture.filter == TextureFilter::default() && texture.wrap == WrapMode::default() {
+            return ImageSource::Path(texture.path);
+        }
+        ImageSource::Sampled(SampledImage {
+            path: texture.path,
+            filter: texture.filter,
+            wrap: texture.wrap,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn checker(scale: f32) -> Checker {
+        Checker {
+            a: Colour::from_gray(0.0),
+            b: Colour::from_gray(1.0),
+            scale,
+        }
+    }
+
+    #[test]
+    fn neighbouring_cells_alternate() {
+        let checker = checker(1.0);
+        let centre = checker.colour_at(&Vector3::new(0.5, 0.5, 0.5));
+        for step in [Vector3::x(), Vector3::y(), Vector3::z()] {
+            let neighbour = checker.colour_at(&(Vector3::new(0.5, 0.5, 0.5) + step));
+            assert_ne!(centre, neighbour);
+        }
+        assert_eq!(centre, checker.colour_at(&Vector3::new(1.5, 1.5, 0.5)));
+    }
+
+    #[test]
+    fn scale_sets_cell_size() {
+        // a unit cell holds two cells at scale 2, so half a unit over is the other colour
+        let point = Vector3::new(0.25, 0.25, 0.25);
+        let over = point + Vector3::new(0.5, 0.0, 0.0);
+        assert_eq!(
+            checker(1.0).colour_at(&point),
+            checker(1.0).colour_at(&over)
+        );
+        assert_ne!(
+            checker(2.0).colour_at(&point),
+            checker(2.0).colour_at(&over)
+        );
+    }
+
+    #[test]
+    fn misspelt_checker_field_is_named() {
+        let json = r#"{"a": [0, 0, 0, 1], "b": [1, 1, 1, 1], "scael": 2}"#;
+        let error = serde_json::from_str::<Texture>(json).unwrap_err();
+        assert!(error.to_string().contains("scael"), "{}", error);
+    }
+
+    #[test]
+    fn solid_colours_and_checkers_parse() {
+        let solid = serde_json::from_str::<Texture>("[1, 0, 0, 1]").unwrap();
+        assert!(
+            matches!(solid, Texture::Solid(colour) if colour == Colour::from_rgb(1.0, 0.0, 0.0))
+        );
+        let json = r#"{"a": [0, 0, 0, 1], "b": [1, 1, 1, 1], "scale": 2}"#;
+        let checker = serde_json::from_str::<Texture>(json).unwrap();
+        assert!(matches!(checker, Texture::Checker(Checker { scale, .. }) if scale == 2.0));
+    }
+
+    #[test]
+    fn nearest_filtering_reads_whole_texels() {
+        // black and white columns, so anything in between shows blending
+        let image = RgbaImage::from_fn(2, 2, |x, _| {
+            if x == 0 {
+                image::Rgba([0, 0, 0, 255])
+            } else {
+                image::Rgba([255, 255, 255, 255])
+            }
+        });
+        let path = std::env::temp_dir().join(format!("texels-{}.png", std::process::id()));
+        image.save(&path).unwrap();
+        let loaded = ImageTexture::load(path.clone());
+        std::fs::remove_file(&path).unwrap();
+        let bilinear = loaded.unwrap().with_wrap(WrapMode::Clamp);
+        let nearest = bilinear.clone().with_filter(TextureFilter::Nearest);
+
+        for (u, v) in [
+            (0.05, 0.3),
+            (0.3, 0.9),
+            (0.49, 0.5),
+            (0.51, 0.1),
+            (0.99, 0.7),
+        ] {
+            let texel = nearest.texel((u * 2.0) as u32, (v * 2.0) as u32);
+            assert_eq!(nearest.sample(Vector2::new(u, v)), texel);
+        }
+        let edge = bilinear.sample(Vector2::new(0.5, 0.5));
+        assert!((edge.r() - 0.5).abs() < 1e-3, "{:?}", edge);
+    }
+}
@@ -0,0 +1,58 @@
+/// A rectangle of pixels rendered as one unit of work, so a few expensive pixels only
+/// hold up their own tile rather than a whole row
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct Tile {
+    pub x: u32,
+    pub y: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl Tile {
+    /// Every pixel in the tile, row by row
+    pub fn pixels(&self) -> impl Iterator<Item = (u32, u32)> {
+        let Tile {
+            x,
+            y,
+            width,
+            height,
+        } = *self;
+        (y..y + height).flat_map(move |py| (x..x + width).map(move |px| (px, py)))
+    }
+}
+
+/// Splits a `width` by `height` image into `tile_size` squares, left to right and top to
+/// bottom. Tiles along the right and bottom edges are cut short to fit
+pub fn tiles(width: u32, height: u32, tile_size: u32) -> Vec<Tile> {
+    let tile_size = tile_size.max(1);
+    let mut tiles = Vec::new();
+    for y in (0..height).step_by(tile_size as usize) {
+        for x in (0..width).step_by(tile_size as usize) {
+            tiles.push(Tile {
+                x,
+                y,
+                width: tile_size.min(width - x),
+                height: tile_size.min(height - y),
+            });
+        }
+    }
+    tiles
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn tiles_cover_every_pixel_exactly_once() {
+        // neither dimension a multiple of the tile size, so the edges get cut short
+        let (width, height) = (37, 21);
+        let mut covered = vec![vec![0; width as usize]; height as usize];
+        for tile in tiles(width, height, 8) {
+            for (x, y) in tile.pixels() {
+                covered[y as usize][x as usize] += 1;
+            }
+        }
+        assert!(covered.iter().flatten().all(|&count| count == 1));
+    }
+}
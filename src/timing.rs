@@ -0,0 +1,108 @@
+use std::{
+    cell::Cell,
+    ops::AddAssign,
+    time::{Duration, Instant},
+};
+
+// reading the clock costs about as much as a shadow ray, so only one in this many is
+// timed, standing in for the rest
+const SHADOW_TIMING_INTERVAL: u32 = 16;
+
+thread_local! {
+    static SHADOW_TESTS: Cell<u32> = const { Cell::new(0) };
+    static SHADOW_TIME: Cell<Duration> = const { Cell::new(Duration::ZERO) };
+}
+
+/// Where the time making a frame went. Ray times are summed over every thread that
+/// traced them, so on several cores they add up to more than the frame took
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct FrameTiming {
+    pub primary: Duration, // tracing camera rays and everything they bounce into, bar shadows
+    pub shadow: Duration,  // testing whether lights can see the points being shaded
+    pub conversion: Duration, // encoding the buffer into an image to show
+}
+
+impl FrameTiming {
+    pub fn total(&self) -> Duration {
+        self.primary + self.shadow + self.conversion
+    }
+}
+
+impl AddAssign for FrameTiming {
+    fn add_assign(&mut self, other: FrameTiming) {
+        self.primary += other.primary;
+        self.shadow += other.shadow;
+        self.conversion += other.conversion;
+    }
+}
+
+/// Runs a shadow test, adding roughly how long it took to this thread's total
+pub fn shadow<T>(test: impl FnOnce() -> T) -> T {
+    let count = SHADOW_TESTS.with(|tests| {
+        let count = tests.get().wrapping_add(1);
+        tests.set(count);
+        count
+    });
+    if !count.is_multiple_of(SHADOW_TIMING_INTERVAL) {
+        return test();
+    }
+    let start = Instant::now();
+    let result = test();
+    let elapsed = start.elapsed() * SHADOW_TIMING_INTERVAL;
+    SHADOW_TIME.with(|total| total.set(total.get() + elapsed));
+    result
+}
+
+/// Time this thread has spent in `shadow` since the last call, starting it again from 0
+pub fn take_shadow_time() -> Duration {
+    SHADOW_TIME.with(|total| total.take())
+}
+
+/// How long `render` took, split into the shadow tests it ran and everything else
+pub fn time_render<T>(render: impl FnOnce() -> T) -> (T, FrameTiming) {
+    take_shadow_time();
+    let start = Instant::now();
+    let result = render();
+    let elapsed = start.elapsed();
+    let shadow = take_shadow_time();
+    let timing = FrameTiming {
+        primary: elapsed.saturating_sub(shadow),
+        shadow,
+        conversion: Duration::ZERO,
+    };
+    (result, timing)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{camera::Camera, output, renderer::RenderConfig, scene::Scene};
+
+    #[test]
+    fn total_is_the_sum_of_the_parts() {
+        // rendered on this thread, where its shadow tests are counted
+        let scene = Scene::pondering_orbs();
+        let camera = Camera::default_view(32, 20);
+        let outer = Instant::now();
+        let (buffer, mut timing) =
+            time_render(|| camera.create_buffer(&scene, &RenderConfig::default()));
+        let conversion = Instant::now();
+        output::flatten(&buffer, output::Encoding::default());
+        timing.conversion = conversion.elapsed();
+        let wall_clock = outer.elapsed();
+
+        assert!(timing.shadow > Duration::ZERO);
+        assert_eq!(
+            timing.total(),
+            timing.primary + timing.shadow + timing.conversion
+        );
+        // shadows are timed one in a handful, so they only roughly fit inside the render
+        let slack = Duration::from_millis(5);
+        assert!(
+            timing.total() <= wall_clock + slack,
+            "{:?} {:?}",
+            timing,
+            wall_clock
+        );
+    }
+}
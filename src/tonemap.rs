@@ -0,0 +1,66 @@
+use crate::colour::Colour;
+
+/// How HDR colours are squeezed into the displayable 0-1 range
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum ToneMap {
+    None, // clip anything brighter than 1
+    Reinhard,
+    AcesFilmic,
+}
+
+impl ToneMap {
+    pub fn apply(&self, colour: Colour) -> Colour {
+        match self {
+            ToneMap::None => colour,
+            ToneMap::Reinhard => reinhard(colour),
+            ToneMap::AcesFilmic => aces_filmic(colour),
+        }
+    }
+
+    pub fn next(&self) -> ToneMap {
+        match self {
+            ToneMap::None => ToneMap::Reinhard,
+            ToneMap::Reinhard => ToneMap::AcesFilmic,
+            ToneMap::AcesFilmic => ToneMap::None,
+        }
+    }
+}
+
+fn map_channels(colour: Colour, f: impl Fn(f32) -> f32) -> Colour {
+    Colour::from_rgba_premultiplied(f(colour.r()), f(colour.g()), f(colour.b()), colour.a())
+}
+
+/// `c / (1 + c)`, which never quite reaches white
+pub fn reinhard(colour: Colour) -> Colour {
+    map_channels(colour, |c| c / (1.0 + c))
+}
+
+/// Krzysztof Narkowicz's fit of the ACES filmic curve
+pub fn aces_filmic(colour: Colour) -> Colour {
+    map_channels(colour, |c| {
+        ((c * (2.51 * c + 0.03)) / (c * (2.43 * c + 0.59) + 0.14)).clamp(0.0, 1.0)
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn operators_keep_black_and_never_swap_brightnesses() {
+        for operator in [reinhard, aces_filmic] {
+            assert_eq!(operator(Colour::from_gray(0.0)), Colour::from_gray(0.0));
+            let mut previous = 0.0;
+            for step in 1..=200 {
+                let mapped = operator(Colour::from_gray(step as f32 * 0.1)).r();
+                assert!(
+                    mapped >= previous && mapped <= 1.0,
+                    "{} at {}",
+                    mapped,
+                    step
+                );
+                previous = mapped;
+            }
+        }
+    }
+}
@@ -0,0 +1,250 @@
+use nalgebra::Vector3;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    aabb::Aabb,
+    colour::Colour,
+    intersect::{Intersect, Intersection},
+    material::Material,
+    renderer::Ray,
+};
+
+// coefficients this close to zero are treated as zero, so tangent rays whose double
+// root rounds to a slightly negative discriminant still count as touching
+const ROOT_EPSILON: f64 = 1e-9;
+const POLISH_STEPS: usize = 2; // Newton steps cleaning up each root the closed form finds
+
+/// Ring around the z axis, centred on the origin. Place it with an instance to move or
+/// turn it. `major_radius` is from the centre to the middle of the tube and
+/// `minor_radius` the tube's own radius
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Torus {
+    pub major_radius: f32,
+    pub minor_radius: f32,
+    pub material: Material,
+}
+
+impl Torus {
+    pub fn bounds(&self) -> Aabb {
+        let outer = self.major_radius + self.minor_radius;
+        let extent = Vector3::new(outer, outer, self.minor_radius);
+        Aabb::new(-extent, extent)
+    }
+
+    /// Every distance along the ray where it crosses the surface, in no particular order.
+    /// A point is on the torus when (|p|² + R² - r²)² = 4R²(x² + y²), which along the ray
+    /// is a quartic in the distance
+    fn crossings(&self, ray: &Ray) -> Vec<f32> {
+        let direction = ray.direction.cast::<f64>();
+        let outer = (self.major_radius + self.minor_radius) as f64;
+
+        // solve from the ray's closest approach to the centre, keeping the coefficients
+        // near the scale of the torus however far away the ray starts
+        let length_squared = direction.norm_squared();
+        let shift = (-ray.origin.cast::<f64>().dot(&direction) / length_squared).max(0.0);
+        let origin = ray.origin.cast::<f64>() + direction * shift;
+        if shift > 0.0 && origin.norm_squared() > outer.powi(2) {
+            return Vec::new(); // passes outside the surrounding sphere
+        }
+
+        let major_squared = (self.major_radius as f64).powi(2);
+        let minor_squared = (self.minor_radius as f64).powi(2);
+        let half_b = origin.dot(&direction);
+        let k = origin.norm_squared() + major_squared - minor_squared;
+        let across_a = direction.x.powi(2) + direction.y.powi(2);
+        let across_half_b = origin.x * direction.x + origin.y * direction.y;
+        let across_c = origin.x.powi(2) + origin.y.powi(2);
+
+        let coefficients = [
+            length_squared.powi(2),
+            4.0 * length_squared * half_b,
+            4.0 * half_b.powi(2) + 2.0 * length_squared * k - 4.0 * major_squared * across_a,
+            4.0 * half_b * k - 8.0 * major_squared * across_half_b,
+            k.powi(2) - 4.0 * major_squared * across_c,
+        ];
+        solve_quartic(coefficients)
+            .into_iter()
+            .map(|t| (polish(&coefficients, t) + shift) as f32)
+            .collect()
+    }
+
+    /// Outward normal at a point on the surface: away from the nearest point of the
+    /// circle running through the middle of the tube
+    fn normal_at(&self, point: &Vector3<f32>) -> Vector3<f32> {
+        let around = Vector3::new(point.x, point.y, 0.0);
+        let core = around.try_normalize(f32::EPSILON).unwrap_or(Vector3::x()) * self.major_radius;
+        (point - core).normalize()
+    }
+}
+
+impl Intersect for Torus {
+    fn test_intersection(&self, ray: &Ray) -> Intersection {
+        self.intersect_within(ray, 0.0, f32::INFINITY)
+    }
+
+    fn intersect_within(&self, ray: &Ray, t_min: f32, t_max: f32) -> Intersection {
+        let nearest = self
+            .crossings(ray)
+            .into_iter()
+            .filter(|&t| t > t_min && t < t_max)
+            .min_by(f32::total_cmp);
+        let Some(t) = nearest else {
+            return Intersection::new(Colour::from_gray(0.0), None, None);
+        };
+
+        let point = ray.at_point(t);
+        let normal_ray = Ray::new(point, self.normal_at(&point));
+        Intersection::new(
+            self.material.colour_at(&point, None),
+            Some(t),
+            Some(normal_ray),
+        )
+        .with_material(self.material.clone())
+    }
+}
+
+/// A Newton step or two on `t`, a root of the quartic with these coefficients (highest
+/// power first), where the closed form loses precision for near double roots
+fn polish(coefficients: &[f64; 5], mut t: f64) -> f64 {
+    for _ in 0..POLISH_STEPS {
+        let (value, slope) = coefficients.iter().fold((0.0, 0.0), |(value, slope), c| {
+            (value * t + c, slope * t + value)
+        });
+        if slope.abs() < ROOT_EPSILON {
+            break;
+        }
+        t -= value / slope;
+    }
+    t
+}
+
+/// Real roots of c[0]x⁴ + c[1]x³ + c[2]x² + c[3]x + c[4], by Ferrari's method: remove
+/// the cubic term, then split into two quadratics using a root of the resolvent cubic
+fn solve_quartic(c: [f64; 5]) -> Vec<f64> {
+    let (a, b, c, d) = (c[1] / c[0], c[2] / c[0], c[3] / c[0], c[4] / c[0]);
+
+    // x = y - a/4 gives y⁴ + py² + qy + r
+    let a_squared = a * a;
+    let p = b - 3.0 / 8.0 * a_squared;
+    let q = a_squared * a / 8.0 - a * b / 2.0 + c;
+    let r = -3.0 / 256.0 * a_squared * a_squared + a_squared * b / 16.0 - a * c / 4.0 + d;
+
+    let mut roots = if r.abs() < ROOT_EPSILON {
+        // no constant term: y(y³ + py + q)
+        let mut roots = solve_cubic([1.0, 0.0, p, q]);
+        roots.push(0.0);
+        roots
+    } else {
+        let z = solve_cubic([1.0, -p / 2.0, -r, r * p / 2.0 - q * q / 8.0])[0];
+        let u = z * z - r;
+        let v = 2.0 * z - p;
+        let u = if u.abs() < ROOT_EPSILON {
+            0.0
+        } else if u > 0.0 {
+            u.sqrt()
+        } else {
+            return Vec::new();
+        };
+        let v = if v.abs() < ROOT_EPSILON {
+            0.0
+        } else if v > 0.0 {
+            v.sqrt()
+        } else {
+            return Vec::new();
+        };
+        let v = if q < 0.0 { -v } else { v };
+        let mut roots = solve_quadratic(1.0, v, z - u);
+        roots.extend(solve_quadratic(1.0, -v, z + u));
+        roots
+    };
+
+    for root in &mut roots {
+        *root -= a / 4.0;
+    }
+    roots
+}
+
+/// Real roots of c[0]x³ + c[1]x² + c[2]x + c[3], always at least one
+fn solve_cubic(c: [f64; 4]) -> Vec<f64> {
+    let (a, b, c) = (c[1] / c[0], c[2] / c[0], c[3] / c[0]);
+
+    // x = y - a/3 gives y³ + 3py + 2q
+    let a_squared = a * a;
+    let p = (b - a_squared / 3.0) / 3.0;
+    let q = (2.0 / 27.0 * a * a_squared - a * b / 3.0 + c) / 2.0;
+    let p_cubed = p * p * p;
+    let discriminant = q * q + p_cubed;
+
+    let roots = if discriminant.abs() < ROOT_EPSILON {
+        if q.abs() < ROOT_EPSILON {
+            vec![0.0]
+        } else {
+            let u = (-q).cbrt();
+            vec![2.0 * u, -u]
+        }
+    } else if discriminant < 0.0 {
+        // three real roots, found with trigonometry instead of complex cube roots
+        let phi = (-q / (-p_cubed).sqrt()).clamp(-1.0, 1.0).acos() / 3.0;
+        let scale = 2.0 * (-p).sqrt();
+        let third = 2.0 * std::f64::consts::PI / 3.0;
+        vec![
+            scale * phi.cos(),
+            scale * (phi + third).cos(),
+            scale * (phi - third).cos(),
+        ]
+    } else {
+        let root = discriminant.sqrt();
+        vec![(root - q).cbrt() - (root + q).cbrt()]
+    };
+    roots.into_iter().map(|y| y - a / 3.0).collect()
+}
+
+/// Real roots of ax² + bx + c
+fn solve_quadratic(a: f64, b: f64, c: f64) -> Vec<f64> {
+    let discriminant = b * b - 4.0 * a * c;
+    if discriminant.abs() < ROOT_EPSILON {
+        return vec![-b / (2.0 * a)];
+    }
+    if discriminant < 0.0 {
+        return Vec::new();
+    }
+    let root = discriminant.sqrt();
+    vec![(-b - root) / (2.0 * a), (-b + root) / (2.0 * a)]
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ring() -> Torus {
+        Torus {
+            major_radius: 2.0,
+            minor_radius: 0.5,
+            material: Material::matte(Colour::from_gray(1.0)),
+        }
+    }
+
+    #[test]
+    fn ray_down_the_axis_goes_through_the_hole() {
+        let ray = Ray::new(Vector3::new(0.0, 0.0, 5.0), -Vector3::z());
+        assert_eq!(ring().test_intersection(&ray).distance, None);
+    }
+
+    #[test]
+    fn ray_through_the_tube_hits_its_near_side() {
+        let ray = Ray::new(Vector3::new(-5.0, 0.0, 0.0), Vector3::x());
+        let hit = ring().test_intersection(&ray);
+        let distance = hit.distance.unwrap();
+        assert!((distance - 2.5).abs() < 1e-4, "{}", distance);
+        let normal = hit.normal.unwrap().direction;
+        assert!((normal + Vector3::x()).norm() < 1e-4, "{}", normal);
+    }
+
+    #[test]
+    fn ray_grazing_the_top_of_the_tube_touches_it() {
+        let ray = Ray::new(Vector3::new(-5.0, 0.0, 0.5), Vector3::x());
+        let distance = ring().test_intersection(&ray).distance.unwrap();
+        assert!((distance - 3.0).abs() < 1e-2, "{}", distance);
+    }
+}
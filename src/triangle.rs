@@ -0,0 +1,169 @@
+use nalgebra::Vector3;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    colour::Colour,
+    intersect::{Intersect, Intersection},
+    material::Material,
+    renderer::Ray,
+};
+
+const PARALLEL_EPSILON: f32 = 1e-7;
+
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Triangle {
+    pub v0: Vector3<f32>,
+    pub v1: Vector3<f32>,
+    pub v2: Vector3<f32>,
+    pub material: Material,
+    #[serde(default)]
+    pub cull_back_faces: bool, // ignore rays hitting the side the vertices wind clockwise on
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub normals: Option<[Vector3<f32>; 3]>, // per vertex normals, blended across the face
+}
+
+impl Triangle {
+    pub fn new(
+        v0: Vector3<f32>,
+        v1: Vector3<f32>,
+        v2: Vector3<f32>,
+        material: Material,
+    ) -> Triangle {
+        Triangle {
+            v0,
+            v1,
+            v2,
+            material,
+            cull_back_faces: false,
+            normals: None,
+        }
+    }
+
+    /// Smooths the shading by blending these normals at v0, v1 and v2 across the face
+    pub fn with_normals(mut self, normals: [Vector3<f32>; 3]) -> Triangle {
+        self.normals = Some(normals);
+        self
+    }
+
+    /// Normal of the plane the triangle sits in, facing the side the vertices wind anticlockwise on
+    pub fn geometric_normal(&self) -> Vector3<f32> {
+        (self.v1 - self.v0).cross(&(self.v2 - self.v0)).normalize()
+    }
+
+    /// Surface normal at the point with these barycentric weights, the vertex normals
+    /// blended if there are any and the geometric normal otherwise
+    pub fn normal_at(&self, barycentric: &Vector3<f32>) -> Vector3<f32> {
+        let Some([n0, n1, n2]) = self.normals else {
+            return self.geometric_normal();
+        };
+        (n0 * barycentric.x + n1 * barycentric.y + n2 * barycentric.z)
+            .try_normalize(f32::EPSILON)
+            .unwrap_or_else(|| self.geometric_normal())
+    }
+
+    /// Möller–Trumbore intersection, giving the distance along the ray and the
+    /// barycentric weights of v0, v1 and v2 at the hit
+    pub fn hit(&self, ray: &Ray) -> Option<(f32, Vector3<f32>)> {
+        let edge_1 = self.v1 - self.v0;
+        let edge_2 = self.v2 - self.v0;
+        let p = ray.direction.cross(&edge_2);
+        let determinant = edge_1.dot(&p);
+
+        // a negative determinant means the ray is hitting the back face
+        if self.cull_back_faces && determinant < PARALLEL_EPSILON {
+            return None;
+        }
+        if determinant.abs() < PARALLEL_EPSILON {
+            return None; // the ray runs alongside the triangle
+        }
+
+        let inverse_determinant = 1.0 / determinant;
+        let s = ray.origin - self.v0;
+        let u = s.dot(&p) * inverse_determinant;
+        if !(0.0..=1.0).contains(&u) {
+            return None;
+        }
+
+        let q = s.cross(&edge_1);
+        let v = ray.direction.dot(&q) * inverse_determinant;
+        if v < 0.0 || u + v > 1.0 {
+            return None;
+        }
+
+        let t = edge_2.dot(&q) * inverse_determinant;
+        if t <= 0.0 {
+            return None;
+        }
+        Some((t, Vector3::new(1.0 - u - v, u, v)))
+    }
+}
+
+impl Intersect for Triangle {
+    fn test_intersection(&self, ray: &Ray) -> Intersection {
+        let Some((t, barycentric)) = self.hit(ray) else {
+            return Intersection::new(Colour::from_gray(0.0), None, None);
+        };
+
+        let normal_ray = Ray::new(ray.at_point(t), self.normal_at(&barycentric));
+        let mut intersection = Intersection::new(
+            self.material.colour_at(&normal_ray.origin, None),
+            Some(t),
+            Some(normal_ray),
+        )
+        .with_material(self.material.clone());
+        intersection.barycentric = Some(barycentric);
+        intersection
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn ray_at_the_centroid_hits_it_with_equal_weights() {
+        let triangle = Triangle::new(
+            Vector3::new(0.0, 0.0, 0.0),
+            Vector3::new(3.0, 0.0, 0.0),
+            Vector3::new(0.0, 3.0, 0.0),
+            Material::matte(Colour::from_gray(1.0)),
+        );
+        let centroid = Vector3::new(1.0, 1.0, 0.0);
+        let ray = Ray::new(centroid + Vector3::z() * 2.0, -Vector3::z());
+
+        let (t, barycentric) = triangle.hit(&ray).unwrap();
+        assert!((ray.at_point(t) - centroid).norm() < 1e-5);
+        assert!((barycentric - Vector3::repeat(1.0 / 3.0)).norm() < 1e-5);
+        assert_eq!(triangle.geometric_normal(), Vector3::z());
+
+        let culled = Triangle {
+            cull_back_faces: true,
+            ..triangle
+        };
+        let from_below = Ray::new(centroid - Vector3::z() * 2.0, Vector3::z());
+        assert!(culled.hit(&from_below).is_none());
+        assert!(culled.hit(&ray).is_some());
+    }
+
+    #[test]
+    fn blended_normals_are_the_vertex_normals_at_the_vertices() {
+        let normals = [
+            Vector3::new(0.0, 0.0, 1.0),
+            Vector3::new(1.0, 0.0, 1.0).normalize(),
+            Vector3::new(0.0, -1.0, 1.0).normalize(),
+        ];
+        let triangle = Triangle::new(
+            Vector3::new(0.0, 0.0, 0.0),
+            Vector3::new(1.0, 0.0, 0.0),
+            Vector3::new(0.0, 1.0, 0.0),
+            Material::matte(Colour::from_gray(1.0)),
+        )
+        .with_normals(normals);
+        for (i, normal) in normals.iter().enumerate() {
+            let mut at_vertex = Vector3::zeros();
+            at_vertex[i] = 1.0;
+            assert!((triangle.normal_at(&at_vertex) - normal).norm() < 1e-6);
+        }
+    }
+}
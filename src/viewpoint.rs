@@ -0,0 +1,55 @@
+use std::{collections::BTreeMap, fs::File, io::BufReader, path::Path};
+
+use nalgebra::Vector3;
+use serde::{Deserialize, Serialize};
+
+use cgraphics::camera::Camera;
+
+/// A saved camera pose that can be jumped back to later
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Viewpoint {
+    pub origin: Vector3<f32>,
+    pub direction: Vector3<f32>,
+    pub fov_y: f32,
+    #[serde(default)]
+    pub roll: f32,
+}
+
+/// Viewpoints by the number key they're saved under
+pub type Viewpoints = BTreeMap<u8, Viewpoint>;
+
+impl Viewpoint {
+    pub fn of(camera: &Camera) -> Viewpoint {
+        Viewpoint {
+            origin: camera.location.origin,
+            direction: camera.location.direction,
+            fov_y: camera.fov_y,
+            roll: camera.roll,
+        }
+    }
+
+    pub fn apply(&self, camera: &mut Camera) {
+        camera.location.origin = self.origin;
+        camera.look_along(&self.direction);
+        camera.set_fov(self.fov_y);
+        camera.roll = self.roll;
+    }
+}
+
+/// Reads saved viewpoints, a missing file just meaning none have been saved yet
+pub fn load(path: &Path) -> Result<Viewpoints, String> {
+    if !path.exists() {
+        return Ok(Viewpoints::new());
+    }
+    let file = File::open(path).map_err(|e| format!("failed to open {}: {}", path.display(), e))?;
+    serde_json::from_reader(BufReader::new(file))
+        .map_err(|e| format!("failed to parse {}: {}", path.display(), e))
+}
+
+pub fn save(viewpoints: &Viewpoints, path: &Path) -> Result<(), String> {
+    let file =
+        File::create(path).map_err(|e| format!("failed to create {}: {}", path.display(), e))?;
+    serde_json::to_writer_pretty(file, viewpoints)
+        .map_err(|e| format!("failed to write {}: {}", path.display(), e))
+}
@@ -0,0 +1,21 @@
+use std::{fs, path::Path};
+
+// the renderer is meant to be usable without the UI, only the binary's modules talk to egui
+#[test]
+fn library_modules_dont_use_egui() {
+    let src = Path::new(env!("CARGO_MANIFEST_DIR")).join("src");
+    let lib = fs::read_to_string(src.join("lib.rs")).unwrap();
+    let modules = lib
+        .lines()
+        .filter_map(|line| line.strip_prefix("pub mod "))
+        .map(|name| name.trim_end_matches(';'));
+    for module in modules {
+        let source = fs::read_to_string(src.join(format!("{}.rs", module))).unwrap();
+        assert!(
+            !source.contains("eframe::") && !source.contains("egui::"),
+            "{}.rs uses egui",
+            module
+        );
+    }
+    assert!(!lib.contains("eframe::") && !lib.contains("egui::"));
+}
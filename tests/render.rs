@@ -0,0 +1,40 @@
+use cgraphics::{camera::Camera, renderer::RenderConfig, scene::Scene};
+
+#[test]
+fn renders_pondering_orbs_through_the_library() {
+    let scene = Scene::pondering_orbs();
+    let camera = Camera::default_view(32, 20);
+    let buffer = cgraphics::render(&scene, &camera, &RenderConfig::default(), None);
+
+    assert_eq!(buffer.len(), 20);
+    for row in &buffer {
+        assert_eq!(row.len(), 32);
+        for pixel in row {
+            let channels = [pixel.r(), pixel.g(), pixel.b(), pixel.a()];
+            assert!(channels.iter().all(|c| c.is_finite()), "{:?}", pixel);
+        }
+    }
+}
+
+#[test]
+fn progress_climbs_to_one_as_tiles_finish() {
+    let scene = Scene::pondering_orbs();
+    let camera = Camera::default_view(40, 24);
+    let config = RenderConfig {
+        tile_size: 8,
+        ..RenderConfig::default()
+    };
+    let reported = std::sync::Mutex::new(Vec::new());
+    let record = |fraction: f32| reported.lock().unwrap().push(fraction);
+    cgraphics::render(&scene, &camera, &config, Some(&record));
+
+    let reported = reported.into_inner().unwrap();
+    // one call per tile, 5 across by 3 down
+    assert_eq!(reported.len(), 15);
+    assert!(
+        reported.windows(2).all(|pair| pair[0] < pair[1]),
+        "{:?}",
+        reported
+    );
+    assert_eq!(reported.last(), Some(&1.0));
+}